@@ -0,0 +1,209 @@
+//! Logs of where the pointer was over a run, and plots of them
+//!
+//! An access log starts with an 8 byte header, a magic of `BFAL`, format version 1 and three zero
+//! bytes, followed by samples of 16 bytes each: the instructions run so far and the cell the
+//! pointer was on, both as little endian `u64`.
+
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+};
+
+use bfirs::compiler::{BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::BrainFuckExecutor;
+use clap::Args;
+
+use crate::{sample::run_chunked, PathIoError};
+
+const MAGIC: &[u8; 4] = b"BFAL";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 8;
+const SAMPLE_LEN: usize = 16;
+
+/// size of the plotted area in pixels
+const PLOT_WIDTH: usize = 800;
+const PLOT_HEIGHT: usize = 400;
+/// room around the plotted area for labels
+const MARGIN: usize = 60;
+
+/// Runs `code` on stdio, logging the pointer to `path` every `every` instructions. The program runs
+/// in chunks through the limited loop like `--profile-sample`, so logging costs one resume per
+/// sample.
+pub fn record<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    every: u64,
+    limit: Option<u64>,
+    path: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let code = BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?;
+
+    let mut execenv =
+        BrainFuckExecutor::new_stdio_locked::<CellSize>(code.reccomended_array_size());
+
+    let path_err = |e| PathIoError(path.to_owned(), e);
+
+    let mut log = BufWriter::new(File::create(path).map_err(path_err)?);
+    log.write_all(MAGIC).map_err(path_err)?;
+    log.write_all(&[VERSION, 0, 0, 0]).map_err(path_err)?;
+
+    let mut sample = |run: u64, ptr: usize| {
+        log.write_all(&run.to_le_bytes())?;
+        log.write_all(&(ptr as u64).to_le_bytes())
+    };
+
+    sample(0, execenv.ptr).map_err(path_err)?;
+
+    let mut run = 0;
+
+    let result = run_chunked(
+        &mut execenv,
+        &code,
+        0,
+        every,
+        limit.unwrap_or(u64::MAX),
+        |execenv, ran, _| {
+            run += ran;
+            sample(run, execenv.ptr)
+        },
+    )
+    .map_err(path_err)?;
+
+    execenv.stdout.flush()?;
+    log.flush().map_err(path_err)?;
+
+    Ok(result?)
+}
+
+#[derive(Args)]
+/// plot an access log written by `interpret --access-log` as an SVG scatter of pointer position
+/// against instructions run
+pub struct PlotAccessArgs {
+    /// access log to plot
+    log: String,
+
+    /// file to write the SVG to, defaults to stdout
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+pub fn plot(args: &PlotAccessArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = fs::read(&args.log).map_err(|e| PathIoError(args.log.clone(), e))?;
+    let samples = decode(&bytes).map_err(|e| format!("{}: {e}", args.log))?;
+
+    let svg = render_svg(&samples);
+
+    match &args.output {
+        Some(path) => fs::write(path, svg).map_err(|e| PathIoError(path.clone(), e))?,
+        None => io::stdout().lock().write_all(svg.as_bytes())?,
+    }
+
+    Ok(())
+}
+
+fn decode(bytes: &[u8]) -> Result<Vec<(u64, u64)>, String> {
+    let Some((header, body)) = bytes.split_first_chunk::<HEADER_LEN>() else {
+        return Err("too short to be an access log".to_owned());
+    };
+
+    if header[..4] != *MAGIC {
+        return Err("not an access log, it does not start with BFAL".to_owned());
+    }
+
+    if header[4] != VERSION {
+        return Err(format!(
+            "access log version {} is not supported, only version {VERSION} is",
+            header[4]
+        ));
+    }
+
+    if body.len() % SAMPLE_LEN != 0 {
+        return Err("access log ends part way through a sample".to_owned());
+    }
+
+    Ok(body
+        .chunks_exact(SAMPLE_LEN)
+        .map(|sample| {
+            let (run, ptr) = sample.split_at(8);
+            let le = |b: &[u8]| u64::from_le_bytes(b.try_into().expect("split at 8 bytes"));
+
+            (le(run), le(ptr))
+        })
+        .collect())
+}
+
+/// Draws each pixel of the plot that holds at least one sample, so huge logs still make small
+/// files
+fn render_svg(samples: &[(u64, u64)]) -> String {
+    let max_run = samples
+        .iter()
+        .map(|&(run, _)| run)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let max_ptr = samples
+        .iter()
+        .map(|&(_, ptr)| ptr)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    // pixel positions only need to be approximate
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    let scale = |v: u64, max: u64, len: usize| {
+        ((v as f64 / max as f64) * (len - 1) as f64).round() as usize
+    };
+
+    let mut hit = vec![false; PLOT_WIDTH * PLOT_HEIGHT];
+
+    for &(run, ptr) in samples {
+        let x = scale(run, max_run, PLOT_WIDTH);
+        let y = PLOT_HEIGHT - 1 - scale(ptr, max_ptr, PLOT_HEIGHT);
+
+        hit[y * PLOT_WIDTH + x] = true;
+    }
+
+    let (width, height) = (PLOT_WIDTH + 2 * MARGIN, PLOT_HEIGHT + 2 * MARGIN);
+    let (right, bottom) = (MARGIN + PLOT_WIDTH, MARGIN + PLOT_HEIGHT);
+
+    let mut svg = String::new();
+
+    // writing to a String never fails
+    _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" \
+         font-family=\"monospace\" font-size=\"12\">\n\
+         <rect width=\"{width}\" height=\"{height}\" fill=\"white\"/>\n\
+         <path d=\"M{MARGIN} {MARGIN}V{bottom}H{right}\" fill=\"none\" stroke=\"black\"/>\n\
+         <text x=\"{MARGIN}\" y=\"{}\">0</text>\n\
+         <text x=\"{right}\" y=\"{}\" text-anchor=\"end\">{max_run}</text>\n\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"middle\">instructions run</text>\n\
+         <text x=\"{}\" y=\"{bottom}\" text-anchor=\"end\">0</text>\n\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{max_ptr}</text>\n\
+         <text transform=\"translate({},{}) rotate(-90)\" text-anchor=\"middle\">cell</text>\n\
+         <g fill=\"steelblue\">\n",
+        bottom + 16,
+        bottom + 16,
+        MARGIN + PLOT_WIDTH / 2,
+        bottom + 36,
+        MARGIN - 6,
+        MARGIN - 6,
+        MARGIN + 12,
+        MARGIN / 2,
+        MARGIN + PLOT_HEIGHT / 2,
+    );
+
+    for (at, _) in hit.iter().enumerate().filter(|&(_, &hit)| hit) {
+        let (x, y) = (MARGIN + at % PLOT_WIDTH, MARGIN + at / PLOT_WIDTH);
+        _ = writeln!(svg, "<rect x=\"{x}\" y=\"{y}\" width=\"2\" height=\"2\"/>");
+    }
+
+    svg.push_str("</g>\n</svg>\n");
+
+    svg
+}
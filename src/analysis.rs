@@ -0,0 +1,328 @@
+//! Static analyses over optimized instruction streams
+
+use core::fmt;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use usize_cast::IntoUsize;
+
+use crate::compiler::{BfInstruc, BfOptimizable};
+
+/// A loop that can never exit once it is entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NonTermination {
+    /// index of the loops opening instruction
+    pub idx: usize,
+    /// whether the loop is provably entered, meaning the program can never terminate
+    pub always_entered: bool,
+}
+
+impl fmt::Display for NonTermination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "loop never changes the cell it tests and performs no IO, so it can not exit once entered"
+        )?;
+
+        if self.always_entered {
+            write!(f, ", and it is always entered")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns true if the loop body `body` leaves the pointer where it started, never modifies the
+/// cell the loop tests, and performs no IO
+fn is_unchanging_loop<T>(body: &[BfInstruc<T>]) -> bool {
+    use BfInstruc::*;
+
+    let mut offset = 0i64;
+
+    for instruc in body {
+        match instruc {
+            IncPtr => offset += 1,
+            DecPtr => offset -= 1,
+            IncPtrBy(v) => offset += i64::from(v.get()),
+            DecPtrBy(v) => offset -= i64::from(v.get()),
+            Zero | Inc | Dec | IncBy(_) | DecBy(_) | Set(_) | Clock if offset == 0 => return false,
+            Zero | Inc | Dec | IncBy(_) | DecBy(_) | Set(_) | Clock => {}
+            Read | ReadN(_) | Write | WriteConst(_) | LStart(_) | LEnd(_) | Halt | Debug
+            | Call(_) | Return => return false,
+        }
+    }
+
+    offset == 0
+}
+
+/// Evaluates the start of the program while every cell value is still known, returning the index
+/// of the first loop that is provably entered
+fn first_entered_loop<T: BfOptimizable>(stream: &[BfInstruc<T>]) -> Option<usize> {
+    use BfInstruc::*;
+
+    // cells not present in the map are still zero
+    let mut cells = BTreeMap::<usize, T>::new();
+    let mut ptr = 0usize;
+    let mut idx = 0;
+
+    while idx < stream.len() {
+        let cell = cells.entry(ptr).or_insert(T::ZERO);
+
+        match stream[idx] {
+            Zero => *cell = T::ZERO,
+            Set(v) => *cell = v,
+            Inc => *cell = cell.wrapping_add(T::from(1)),
+            Dec => *cell = cell.wrapping_sub(T::from(1)),
+            IncBy(v) => *cell = cell.wrapping_add(v),
+            DecBy(v) => *cell = cell.wrapping_sub(v),
+            IncPtr => ptr = ptr.checked_add(1)?,
+            DecPtr => ptr = ptr.checked_sub(1)?,
+            IncPtrBy(v) => ptr = ptr.checked_add(v.get().into_usize())?,
+            DecPtrBy(v) => ptr = ptr.checked_sub(v.get().into_usize())?,
+            // loop ends are only reached here by skipping the loop, which needs no work
+            Write | WriteConst(_) | LEnd(_) | Debug => {}
+            // the cell is unknown from here on, or no loop is entered after the program ends
+            Read | ReadN(_) | Clock | Halt | Call(_) | Return => return None,
+            LStart(end) if *cell == T::ZERO => idx = end.into_usize(),
+            LStart(_) => return Some(idx),
+        }
+
+        idx += 1;
+    }
+
+    None
+}
+
+/// Finds loops that trivially can not terminate once entered, and whether they are provably entered
+#[must_use]
+pub fn find_nontermination<T: BfOptimizable>(stream: &[BfInstruc<T>]) -> Vec<NonTermination> {
+    let entered = first_entered_loop(stream);
+
+    stream
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, instruc)| match *instruc {
+            BfInstruc::LStart(end) if is_unchanging_loop(&stream[idx + 1..end.into_usize()]) => {
+                Some(NonTermination {
+                    idx,
+                    always_entered: entered == Some(idx),
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The range of cells the data pointer can ever point to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointerBounds {
+    pub min: usize,
+    pub max: usize,
+}
+
+/// Computes the net pointer movement of `block`, and the lowest and highest offsets it reaches
+/// relative to where it starts. Fails if any loop inside moves the pointer on each iteration, as
+/// the amount it moves then depends on how many times it runs.
+fn block_bounds<T>(stream: &[BfInstruc<T>], block: Range<usize>) -> Option<(i64, i64, i64)> {
+    use BfInstruc::*;
+
+    let (mut cur, mut lo, mut hi) = (0i64, 0i64, 0i64);
+    let mut idx = block.start;
+
+    while idx < block.end {
+        match stream[idx] {
+            IncPtr => cur += 1,
+            DecPtr => cur -= 1,
+            IncPtrBy(v) | ReadN(v) => cur += i64::from(v.get()),
+            DecPtrBy(v) => cur -= i64::from(v.get()),
+            // blocks are not followed, so streams with them are never proven
+            Call(_) | Return => return None,
+            LStart(end) => {
+                let end = end.into_usize();
+                let (net, body_lo, body_hi) = block_bounds(stream, idx + 1..end)?;
+
+                if net != 0 {
+                    return None;
+                }
+
+                lo = lo.min(cur + body_lo);
+                hi = hi.max(cur + body_hi);
+                idx = end;
+            }
+            _ => {}
+        }
+
+        lo = lo.min(cur);
+        hi = hi.max(cur);
+        idx += 1;
+    }
+
+    Some((cur, lo, hi))
+}
+
+/// Attempts to prove the data pointer never leaves `0..array_len` by interval analysis over the
+/// program, which succeeds when every loop leaves the pointer where it started
+#[must_use]
+pub fn prove_pointer_bounds<T>(stream: &[BfInstruc<T>], array_len: usize) -> Option<PointerBounds> {
+    let (_, lo, hi) = block_bounds(stream, 0..stream.len())?;
+
+    let bounds = PointerBounds {
+        min: usize::try_from(lo).ok()?,
+        max: usize::try_from(hi).ok()?,
+    };
+
+    (bounds.max < array_len).then_some(bounds)
+}
+
+/// The output and tape a program is known to produce before it first depends on input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPrefix<T> {
+    pub output: Vec<u8>,
+    /// the start of the tape, every cell past its end is still zero
+    pub tape: Vec<T>,
+    pub ptr: usize,
+    /// the instruction evaluation stopped before, or `None` if the program ran to completion
+    pub resume: Option<usize>,
+}
+
+/// Evaluates the program from its start for at most `max_steps` instructions, stopping early at
+/// the first read or before the pointer would leave `0..array_len`, so that error is left for
+/// runtime. Only the cells that are reached are allocated, so this stays cheap on large tapes.
+#[must_use]
+pub fn known_output_prefix<T: BfOptimizable>(
+    stream: &[BfInstruc<T>],
+    array_len: usize,
+    max_steps: u64,
+) -> KnownPrefix<T> {
+    use BfInstruc::*;
+
+    let mut prefix = KnownPrefix {
+        output: vec![],
+        tape: vec![],
+        ptr: 0,
+        resume: None,
+    };
+
+    let mut idx = 0;
+    let mut steps = 0;
+
+    while idx < stream.len() {
+        let ptr = prefix.ptr;
+
+        let moved_to = match stream[idx] {
+            IncPtr => ptr.checked_add(1),
+            DecPtr => ptr.checked_sub(1),
+            IncPtrBy(v) | ReadN(v) => ptr.checked_add(v.get().into_usize()),
+            DecPtrBy(v) => ptr.checked_sub(v.get().into_usize()),
+            _ => Some(ptr),
+        };
+
+        // blocks are not followed, so evaluation stops at the first call or return
+        let stop = steps == max_steps || matches!(stream[idx], Read | ReadN(_) | Call(_) | Return);
+
+        let Some(moved_to) = moved_to.filter(|&to| to < array_len && !stop) else {
+            prefix.resume = Some(idx);
+            break;
+        };
+
+        if ptr >= prefix.tape.len() {
+            prefix.tape.resize(ptr + 1, T::ZERO);
+        }
+
+        let cell = &mut prefix.tape[ptr];
+
+        match stream[idx] {
+            Zero => *cell = T::ZERO,
+            Set(v) => *cell = v,
+            Inc => *cell = cell.wrapping_add(T::from(1)),
+            Dec => *cell = cell.wrapping_sub(T::from(1)),
+            IncBy(v) => *cell = cell.wrapping_add(v),
+            DecBy(v) => *cell = cell.wrapping_sub(v),
+            Write => prefix.output.push(cell.truncate_u8()),
+            WriteConst(bytes) => prefix.output.extend_from_slice(bytes.as_bytes()),
+            LStart(end) if *cell == T::ZERO => idx = end.into_usize(),
+            LEnd(start) if *cell != T::ZERO => idx = start.into_usize(),
+            // the program ended here, so there is nothing left to resume
+            Halt => break,
+            // pointer moves are applied below, and reads never get this far
+            _ => {}
+        }
+
+        prefix.ptr = moved_to;
+
+        steps += 1;
+        idx += 1;
+    }
+
+    prefix
+}
+
+#[test]
+fn test_find_nontermination() {
+    use crate::compiler::BfInstructionStream;
+
+    let lints = |code: &[u8]| {
+        let stream =
+            BfInstructionStream::<u8>::optimized_from_text(code.iter().copied(), None).unwrap();
+        find_nontermination(&stream)
+    };
+
+    assert_eq!(
+        lints(b"+[]"),
+        [NonTermination {
+            idx: 1,
+            always_entered: true
+        }]
+    );
+    assert_eq!(
+        lints(b",[>+<]"),
+        [NonTermination {
+            idx: 1,
+            always_entered: false
+        }]
+    );
+    assert!(lints(b"+[-]>+[>]").is_empty());
+    assert!(lints(b"+[,]+[>+<-]").is_empty());
+}
+
+#[test]
+fn test_prove_pointer_bounds() {
+    use crate::compiler::BfInstructionStream;
+
+    let prove = |code: &[u8], len| {
+        let stream =
+            BfInstructionStream::<u8>::optimized_from_text(code.iter().copied(), None).unwrap();
+        prove_pointer_bounds(&stream, len)
+    };
+
+    assert_eq!(
+        prove(b"++++[>++[>+<-]<-]>>>.", 4),
+        Some(PointerBounds { min: 0, max: 3 })
+    );
+    assert_eq!(prove(b"++++[>++[>+<-]<-]>>>.", 3), None);
+    assert_eq!(prove(b"+[>+]", 30_000), None);
+    assert_eq!(prove(b"<", 30_000), None);
+}
+
+#[test]
+fn test_known_output_prefix() {
+    use crate::compiler::BfInstructionStream;
+
+    let prefix = |code: &[u8]| {
+        let stream =
+            BfInstructionStream::<u8>::optimized_from_text(code.iter().copied(), None).unwrap();
+        known_output_prefix(&stream, 30_000, 1_000)
+    };
+
+    let done = prefix(b"++++++[>+++++++++++<-]>-.+.");
+    assert_eq!(done.output, b"AB");
+    assert_eq!((done.ptr, done.resume), (1, None));
+    assert_eq!(done.tape, [0, 66]);
+
+    let read = prefix(b"+++.>,.");
+    assert_eq!(read.output, b"\x03");
+    assert_eq!(read.resume, Some(3));
+
+    assert_eq!(prefix(b"+[]").resume, Some(2));
+    assert_eq!(prefix(b"<").resume, Some(0));
+}
@@ -0,0 +1,150 @@
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+use bfirs::compiler::{BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::{BfExecErrorTy, BrainFuckExecutorBuilder};
+use clap::Args;
+
+#[derive(Args)]
+/// measure interpreter throughput on a program, or on the built in corpus
+pub struct BenchArgs {
+    /// run the built in benchmark corpus instead of the given program
+    #[arg(long)]
+    builtin: bool,
+
+    /// number of timed runs per program, the fastest is reported, defaults to 5
+    #[arg(short = 'n', long)]
+    iterations: Option<u32>,
+
+    /// also report hardware performance counters for one extra run of each program
+    #[cfg(all(feature = "perf", target_os = "linux"))]
+    #[arg(long)]
+    perf: bool,
+}
+
+/// sierpinski triangle by Daniel B Cristofani
+const SIERPINSKI: &[u8] = b"++++++++[>+>++++<<-]>++>>+<[-[>>+<<-]+>>]>+[-<<<[->[+[-]+>++>>>-<<]<[<]>>++++++[<<+++++>>-]+<<++.[-]<<]>.>+[>>]>+]";
+
+/// six levels of counting loops 16 iterations each, dominated by loop overhead and cell arithmetic
+const NESTED_LOOPS: &[u8] = b"
+    ++++++++++++++++[>++++++++++++++++[>++++++++++++++++[>
+    ++++++++++++++++[>++++++++++++++++[>++++++++++++++++[-]
+    <-]<-]<-]<-]<-]
+";
+
+/// writes 65536 bytes one at a time, dominated by output
+const OUTPUT_FLOOD: &[u8] = b"
+    ++++++++++++++++[>++++++++++++++++[>++++++++++++++++[>++++++++++++++++[>.+<-]<-]<-]<-]
+";
+
+/// repeatedly grows a run of 100 cells, walking its full length each step, dominated by pointer movement
+const TAPE_SWEEP: &[u8] = b"
+    ++++++++++++++++[>>
+    ++++++++++++++++++++++++++++++++++++++++++++++++++
+    ++++++++++++++++++++++++++++++++++++++++++++++++++
+    [[>]+[<]>-]>[>]<[[-]<]<<-]
+";
+
+/// The built in benchmark corpus, every program behaves the same at any cell width
+///
+/// The usual mandelbrot, hanoi and factor benchmarks are third party programs that are not
+/// vendored in this tree, and mandelbrot relies on 8 bit wrapping. Time them by passing their
+/// files to `bench` without `--builtin`.
+const CORPUS: &[(&str, &[u8])] = &[
+    ("sierpinski", SIERPINSKI),
+    ("nested loops", NESTED_LOOPS),
+    ("output flood", OUTPUT_FLOOD),
+    ("tape sweep", TAPE_SWEEP),
+];
+
+/// Runs `code` once with output discarded, returning how long it took and how many instructions
+/// it executed
+fn run_once<CellSize: BfOptimizable>(
+    code: &BfInstructionStream<CellSize>,
+) -> Result<(Duration, u64), Box<dyn std::error::Error>> {
+    let mut execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .array_len(code.reccomended_array_size())
+        .stream_in(io::empty())
+        .stream_out(io::sink())
+        .limit(u64::MAX)
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    let start = Instant::now();
+
+    match execenv.run_limited(code) {
+        Ok(()) => {}
+        Err(e) if matches!(e.source, BfExecErrorTy::NotEnoughInstructions) => {
+            return Err("program did not halt within u64::MAX instructions".into());
+        }
+        Err(e) => return Err(e.into()),
+    }
+
+    Ok((start.elapsed(), u64::MAX - execenv.instructions_left()))
+}
+
+/// Runs `code` `iterations` times, returning the fastest run and how many instructions it executed
+fn time_program<CellSize: BfOptimizable>(
+    code: &BfInstructionStream<CellSize>,
+    iterations: u32,
+) -> Result<(Duration, u64), Box<dyn std::error::Error>> {
+    let mut best = (Duration::MAX, 0);
+
+    for _ in 0..iterations.max(1) {
+        best = best.min(run_once(code)?);
+    }
+
+    Ok(best)
+}
+
+pub fn bench<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    args: &BenchArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let programs = if args.builtin {
+        CORPUS
+    } else {
+        &[("program", code)][..]
+    };
+
+    let mut out = io::stdout().lock();
+
+    for &(name, program) in programs {
+        let code =
+            BfInstructionStream::<CellSize>::optimized_from_text(program.iter().copied(), arr_len)?;
+
+        let (elapsed, instructions) = time_program(&code, args.iterations.unwrap_or(5))?;
+
+        // precision loss is irrelevant for a human readable throughput figure
+        #[allow(clippy::cast_precision_loss)]
+        let mips = instructions as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE) / 1e6;
+
+        writeln!(
+            out,
+            "{name:<14} {elapsed:>12.3?} {instructions:>12} instructions {mips:>10.1} Minstr/s"
+        )?;
+
+        #[cfg(all(feature = "perf", target_os = "linux"))]
+        if args.perf {
+            let (ran, counts) = crate::perf::measure(|| run_once(&code))
+                .map_err(|e| format!("perf counters unavailable: {e}"))?;
+            let (_, instructions) = ran?;
+
+            for (event, count) in counts {
+                // precision loss is irrelevant for a human readable ratio
+                #[allow(clippy::cast_precision_loss)]
+                let per_instruction = count as f64 / instructions.max(1) as f64;
+
+                writeln!(
+                    out,
+                    "  {event:<14} {count:>12} {per_instruction:>10.3} per instruction"
+                )?;
+            }
+        }
+    }
+
+    Ok(())
+}
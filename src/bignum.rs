@@ -0,0 +1,280 @@
+//! An interpreter with unbounded cells, for programs like factorials and digits of pi that need
+//! numbers no fixed width can hold
+//!
+//! Cells never wrap, so decrementing zero gives -1. Output writes the low byte of a cell's two's
+//! complement value, matching what a wrapping cell holding the same bits would write.
+
+use std::io;
+
+use num_bigint::BigInt;
+
+use crate::compiler::{BfCompError, Eof};
+use crate::interpreter::{read_decimal, BfExecError, BfExecErrorTy, IoMode};
+use crate::token::is_op;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Op {
+    Add(i64),
+    Move(isize),
+    Read,
+    Write,
+    /// jumps past the matching `Close` when the cell is zero
+    Open(usize),
+    /// jumps back after the matching `Open` when the cell is not zero
+    Close(usize),
+    /// a loop without IO or nested loops that returns to the cell it started on and steps that
+    /// cell by `step`, run all at once when its iteration count is known. When it would never end
+    /// the loop that follows runs as written instead.
+    Linear {
+        step: i64,
+        adds: Vec<(isize, i64)>,
+    },
+}
+
+/// A program compiled for [`BigExecutor`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigProgram(Vec<Op>);
+
+impl BigProgram {
+    /// Compiles brainfuck source, grouping runs of instructions and folding simple loops
+    ///
+    /// # Errors
+    /// Errors if the loops in `code` are not balanced
+    pub fn from_text(code: impl IntoIterator<Item = u8>) -> Result<Self, BfCompError> {
+        let mut ops = vec![];
+        let mut open = vec![];
+
+        for b in code.into_iter().filter(|&b| is_op(b)) {
+            match (b, ops.last_mut()) {
+                (b'+', Some(Op::Add(n))) => *n += 1,
+                (b'-', Some(Op::Add(n))) => *n -= 1,
+                (b'>', Some(Op::Move(n))) => *n += 1,
+                (b'<', Some(Op::Move(n))) => *n -= 1,
+                (b'+', _) => ops.push(Op::Add(1)),
+                (b'-', _) => ops.push(Op::Add(-1)),
+                (b'>', _) => ops.push(Op::Move(1)),
+                (b'<', _) => ops.push(Op::Move(-1)),
+                (b'.', _) => ops.push(Op::Write),
+                (b',', _) => ops.push(Op::Read),
+                (b'[', _) => {
+                    open.push(ops.len());
+                    ops.push(Op::Open(0));
+                }
+                (_, _) => {
+                    let start = open.pop().ok_or(BfCompError::LoopEndBeforeLoopStart)?;
+
+                    if let Some(linear) = linear_loop(&ops[start + 1..]) {
+                        ops.insert(start, linear);
+                        ops.push(Op::Close(start + 1));
+                        ops[start + 1] = Op::Open(ops.len());
+                    } else {
+                        ops.push(Op::Close(start));
+                        ops[start] = Op::Open(ops.len());
+                    }
+                }
+            }
+        }
+
+        if !open.is_empty() {
+            return Err(BfCompError::LoopCountMismatch);
+        }
+
+        Ok(Self(ops))
+    }
+}
+
+/// folds a loop body into [`Op::Linear`] if it only adds and moves and ends where it started
+fn linear_loop(body: &[Op]) -> Option<Op> {
+    let mut adds: Vec<(isize, i64)> = vec![];
+    let mut offset = 0isize;
+
+    for op in body {
+        match op {
+            Op::Add(n) => match adds.iter_mut().find(|(off, _)| *off == offset) {
+                Some((_, total)) => *total += n,
+                None => adds.push((offset, *n)),
+            },
+            Op::Move(n) => offset += n,
+            _ => return None,
+        }
+    }
+
+    if offset != 0 {
+        return None;
+    }
+
+    let step = adds.iter().find(|(off, _)| *off == 0).map(|&(_, n)| n)?;
+    adds.retain(|&(off, _)| off != 0);
+
+    // other steps can skip over zero, leaving how often the loop runs a question of wrapping
+    matches!(step, 1 | -1).then_some(Op::Linear { step, adds })
+}
+
+/// Runs a [`BigProgram`] over a fixed size tape of unbounded cells
+pub struct BigExecutor<I: io::Read, O: io::Write> {
+    pub stdin: I,
+    pub stdout: O,
+    pub data: Box<[BigInt]>,
+    pub ptr: usize,
+    pub flush_before_read: bool,
+    /// what reads store once input has run out
+    pub eof: Eof,
+    /// how cells are written and input is read, numbers are never wrapped
+    pub io: IoMode,
+    /// instructions left to run, where a folded loop counts as one
+    pub instructions_left: Option<u64>,
+}
+
+impl<I: io::Read, O: io::Write> BigExecutor<I, O> {
+    #[must_use]
+    pub fn new(array_len: usize, stdin: I, stdout: O) -> Self {
+        Self {
+            stdin,
+            stdout,
+            data: vec![BigInt::default(); array_len].into_boxed_slice(),
+            ptr: 0,
+            flush_before_read: true,
+            eof: Eof::Zero,
+            io: IoMode::Bytes,
+            instructions_left: None,
+        }
+    }
+
+    /// Runs `program` from its start, continuing from the current tape and pointer
+    ///
+    /// # Errors
+    /// Errors if the pointer leaves the tape, the instruction limit runs out, or on any IO error
+    ///
+    /// # Panics
+    /// Panics if the pointer was already off the tape before running
+    pub fn run(&mut self, program: &BigProgram) -> Result<(), BfExecError> {
+        let mut idx = 0;
+
+        let err = |source, idx| BfExecError { source, idx };
+
+        while let Some(op) = program.0.get(idx) {
+            if let Some(left) = &mut self.instructions_left {
+                *left = left
+                    .checked_sub(1)
+                    .ok_or(err(BfExecErrorTy::NotEnoughInstructions, idx))?;
+            }
+
+            match op {
+                Op::Add(n) => self.data[self.ptr] += *n,
+                Op::Move(n) => {
+                    self.ptr = self
+                        .ptr
+                        .checked_add_signed(*n)
+                        .ok_or(err(BfExecErrorTy::Underflow, idx))?;
+
+                    if self.ptr >= self.data.len() {
+                        return Err(err(BfExecErrorTy::Overflow, idx));
+                    }
+                }
+                Op::Write if self.io == IoMode::Numeric => {
+                    writeln!(self.stdout, "{}", self.data[self.ptr])
+                        .map_err(|e| err(e.into(), idx))?;
+                }
+                Op::Read if self.io == IoMode::Numeric => {
+                    if self.flush_before_read {
+                        self.stdout.flush().map_err(|e| err(e.into(), idx))?;
+                    }
+
+                    match read_decimal(&mut self.stdin).map_err(|e| err(e.into(), idx))? {
+                        Some((negative, digits)) => {
+                            let value = BigInt::parse_bytes(&digits, 10).expect("only digits");
+                            self.data[self.ptr] = if negative { -value } else { value };
+                        }
+                        None => match self.eof {
+                            Eof::Zero => self.data[self.ptr] = BigInt::default(),
+                            Eof::MinusOne => self.data[self.ptr] = BigInt::from(-1),
+                            Eof::Unchanged => {}
+                        },
+                    }
+                }
+                Op::Write => {
+                    let low = &self.data[self.ptr] & &BigInt::from(0xff);
+                    let byte = u8::try_from(&low).expect("masked to a byte");
+
+                    self.stdout
+                        .write_all(&[byte])
+                        .map_err(|e| err(e.into(), idx))?;
+                }
+                Op::Read => {
+                    if self.flush_before_read {
+                        self.stdout.flush().map_err(|e| err(e.into(), idx))?;
+                    }
+
+                    let mut byte = [0];
+                    let got = self.stdin.read(&mut byte).map_err(|e| err(e.into(), idx))?;
+
+                    match (got, self.eof) {
+                        (0, Eof::Zero) => self.data[self.ptr] = BigInt::default(),
+                        (0, Eof::MinusOne) => self.data[self.ptr] = BigInt::from(-1),
+                        (0, Eof::Unchanged) => {}
+                        _ => self.data[self.ptr] = BigInt::from(byte[0]),
+                    }
+                }
+                Op::Open(end) => {
+                    if self.data[self.ptr] == BigInt::default() {
+                        idx = *end;
+                        continue;
+                    }
+                }
+                Op::Close(start) => {
+                    if self.data[self.ptr] != BigInt::default() {
+                        idx = *start + 1;
+                        continue;
+                    }
+                }
+                Op::Linear { step, adds } => {
+                    let count = -&self.data[self.ptr] * step;
+
+                    if count > BigInt::default() {
+                        for &(off, n) in adds {
+                            let cell = self
+                                .ptr
+                                .checked_add_signed(off)
+                                .ok_or(err(BfExecErrorTy::Underflow, idx))?;
+
+                            *self
+                                .data
+                                .get_mut(cell)
+                                .ok_or(err(BfExecErrorTy::Overflow, idx))? += &count * n;
+                        }
+
+                        self.data[self.ptr] = BigInt::default();
+                    }
+                }
+            }
+
+            idx += 1;
+        }
+
+        self.stdout.flush().map_err(|e| BfExecError {
+            source: e.into(),
+            idx,
+        })
+    }
+}
+
+#[test]
+fn test_bignum() {
+    // 2^70 by doubling, which a 64 bit cell can not hold
+    let code = "+".to_owned() + &"[->++<]>".repeat(70) + "+.";
+
+    let program = BigProgram::from_text(code.bytes()).unwrap();
+    let mut exec = BigExecutor::new(100, &[][..], vec![]);
+    exec.run(&program).unwrap();
+
+    assert_eq!(exec.data[70], (BigInt::from(1) << 70) + 1);
+    assert_eq!(exec.stdout, b"\x01");
+
+    // cells go below zero instead of wrapping, and write their two's complement low byte
+    let program = BigProgram::from_text(b"--[+>+<]-.".iter().copied()).unwrap();
+    let mut exec = BigExecutor::new(2, &[][..], vec![]);
+    exec.run(&program).unwrap();
+
+    assert_eq!(exec.data[..], [BigInt::from(-1), BigInt::from(2)]);
+    assert_eq!(exec.stdout, b"\xff");
+}
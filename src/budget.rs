@@ -0,0 +1,188 @@
+use std::{
+    convert::Infallible,
+    io::{self, BufRead, Write},
+};
+
+use bfirs::compiler::{BfInstruc, BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::{BfExecError, BfExecErrorTy, BrainFuckExecutor};
+use bfirs::token::LineIndex;
+
+use crate::sample::run_chunked;
+
+/// how many chunks the budget is split into, each ending in one sample of where the program is
+const SAMPLES: u64 = 1024;
+
+/// Runs `code` from instruction `start` for at most `limit` instructions in chunks, noting which
+/// instruction each chunk stopped on. If the budget runs out, reports the innermost loop most of
+/// those samples fell in to stderr, so a program that ran out of instructions points at where
+/// they went.
+pub fn run_attributed<CellSize: BfOptimizable, I: io::Read, O: io::Write>(
+    execenv: &mut BrainFuckExecutor<CellSize, I, O>,
+    code: &BfInstructionStream<CellSize>,
+    source: &[u8],
+    limit: u64,
+    start: usize,
+) -> Result<(), BfExecError> {
+    let every = (limit / SAMPLES).max(1);
+
+    let mut samples = vec![];
+
+    let Ok(result) = run_chunked(execenv, code, start, every, limit, |_, _, at| {
+        samples.extend(at);
+        Ok::<_, Infallible>(())
+    });
+
+    if let (Err(e), Some(spans)) = (&result, code.spans()) {
+        if matches!(e.source, BfExecErrorTy::NotEnoughInstructions) {
+            // the error is more useful than a failure to explain it
+            _ = report(code, spans, source, &samples);
+        }
+    }
+
+    result
+}
+
+/// for each instruction, the index of the innermost loop start around it
+fn innermost_loops<T>(code: &[BfInstruc<T>]) -> Vec<Option<usize>> {
+    let mut open = vec![];
+
+    code.iter()
+        .enumerate()
+        .map(|(idx, instruc)| match instruc {
+            BfInstruc::LStart(_) => {
+                open.push(idx);
+                Some(idx)
+            }
+            BfInstruc::LEnd(_) => open.pop(),
+            _ => open.last().copied(),
+        })
+        .collect()
+}
+
+fn report<T>(
+    code: &[BfInstruc<T>],
+    spans: &[std::ops::Range<usize>],
+    source: &[u8],
+    samples: &[usize],
+) -> io::Result<()> {
+    let loops = innermost_loops(code);
+
+    let mut counts = std::collections::BTreeMap::<Option<usize>, usize>::new();
+    for &sample in samples {
+        *counts
+            .entry(loops.get(sample).copied().flatten())
+            .or_default() += 1;
+    }
+
+    let Some((&top, &count)) = counts
+        .iter()
+        .max_by_key(|&(start, count)| (count, start.is_some()))
+    else {
+        return Ok(());
+    };
+
+    // precision loss is irrelevant for a human readable percentage
+    #[allow(clippy::cast_precision_loss)]
+    let percent = count as f64 / samples.len() as f64 * 100.0;
+
+    let mut err = io::stderr().lock();
+
+    let Some(start) = top else {
+        return writeln!(
+            err,
+            "note: about {percent:.0}% of the budget was spent outside of any loop"
+        );
+    };
+
+    let BfInstruc::LStart(end) = code[start] else {
+        unreachable!("innermost loops are always loop starts");
+    };
+
+    let span = spans[start].start..spans[end as usize].end;
+    let lines = LineIndex::new(source);
+    let (line, col) = lines.line_col(span.start);
+    let (end_line, end_col) = lines.line_col(span.end - 1);
+
+    let text = String::from_utf8_lossy(&source[span]);
+    let mut excerpt: String = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    if excerpt.chars().count() > 40 {
+        excerpt = excerpt.chars().take(37).collect::<String>() + "...";
+    }
+
+    writeln!(
+        err,
+        "note: about {percent:.0}% of the budget was spent in the loop at {line}:{col} to \
+         {end_line}:{end_col}, `{excerpt}`"
+    )
+}
+
+/// Asks on the terminal how many more instructions to run after running out at `idx`, `None`
+/// when the answer is to stop. The terminal is opened directly since stdin belongs to the program.
+pub fn prompt_more(idx: usize) -> io::Result<Option<u64>> {
+    let tty = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .map_err(|e| {
+            io::Error::new(e.kind(), format!("--on-limit prompt needs a terminal: {e}"))
+        })?;
+
+    let mut lines = io::BufReader::new(&tty).lines();
+
+    loop {
+        write!(
+            &tty,
+            "out of instructions at instruction {idx}, how many more to run? (empty to stop) "
+        )?;
+
+        let Some(line) = lines.next().transpose()? else {
+            return Ok(None);
+        };
+
+        let line = line.trim();
+
+        if line.is_empty() {
+            return Ok(None);
+        }
+
+        match line.parse() {
+            Ok(0) | Err(_) => writeln!(&tty, "expected a positive number of instructions")?,
+            Ok(more) => return Ok(Some(more)),
+        }
+    }
+}
+
+#[test]
+fn test_innermost_loops() {
+    use BfInstruc::*;
+
+    // +[>[-]<-]+
+    let code: [BfInstruc<u8>; 10] = [
+        Inc,
+        LStart(8),
+        IncPtr,
+        LStart(5),
+        Dec,
+        LEnd(3),
+        DecPtr,
+        Dec,
+        LEnd(1),
+        Inc,
+    ];
+
+    assert_eq!(
+        innermost_loops(&code),
+        [
+            None,
+            Some(1),
+            Some(1),
+            Some(3),
+            Some(3),
+            Some(3),
+            Some(1),
+            Some(1),
+            Some(1),
+            None
+        ]
+    );
+}
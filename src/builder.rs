@@ -0,0 +1,160 @@
+//! Building instruction streams from Rust, for programs that generate brainfuck and would
+//! otherwise write it out as text only to parse it back
+//!
+//! Loops are only built from closures, so every stream a builder produces is balanced.
+
+use std::num::NonZeroU32;
+
+use crate::compiler::{BfCompError, BfInstruc, BfInstructionStream, BfOptimizable};
+
+/// Builds a [`BfInstructionStream`] one instruction at a time, see [`BfInstructionStream::builder`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StreamBuilder<T>(Vec<BfInstruc<T>>);
+
+impl<T: BfOptimizable> Default for StreamBuilder<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: BfOptimizable> StreamBuilder<T> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(vec![])
+    }
+
+    /// adds `n` to the current cell, wrapping
+    #[must_use]
+    pub fn inc(mut self, n: T) -> Self {
+        if n == T::from(1) {
+            self.0.push(BfInstruc::Inc);
+        } else if n != T::ZERO {
+            self.0.push(BfInstruc::IncBy(n));
+        }
+
+        self
+    }
+
+    /// subtracts `n` from the current cell, wrapping
+    #[must_use]
+    pub fn dec(mut self, n: T) -> Self {
+        if n == T::from(1) {
+            self.0.push(BfInstruc::Dec);
+        } else if n != T::ZERO {
+            self.0.push(BfInstruc::DecBy(n));
+        }
+
+        self
+    }
+
+    /// moves the pointer `n` cells to the right
+    #[must_use]
+    pub fn right(mut self, n: u32) -> Self {
+        match NonZeroU32::new(n) {
+            Some(n) if n.get() == 1 => self.0.push(BfInstruc::IncPtr),
+            Some(n) => self.0.push(BfInstruc::IncPtrBy(n)),
+            None => {}
+        }
+
+        self
+    }
+
+    /// moves the pointer `n` cells to the left
+    #[must_use]
+    pub fn left(mut self, n: u32) -> Self {
+        match NonZeroU32::new(n) {
+            Some(n) if n.get() == 1 => self.0.push(BfInstruc::DecPtr),
+            Some(n) => self.0.push(BfInstruc::DecPtrBy(n)),
+            None => {}
+        }
+
+        self
+    }
+
+    /// sets the current cell to `value`
+    #[must_use]
+    pub fn set(mut self, value: T) -> Self {
+        self.0.push(if value == T::ZERO {
+            BfInstruc::Zero
+        } else {
+            BfInstruc::Set(value)
+        });
+
+        self
+    }
+
+    /// sets the current cell to zero, like `[-]`
+    #[must_use]
+    pub fn zero(self) -> Self {
+        self.set(T::ZERO)
+    }
+
+    /// writes the current cell
+    #[must_use]
+    pub fn write(mut self) -> Self {
+        self.0.push(BfInstruc::Write);
+        self
+    }
+
+    /// reads a byte into the current cell
+    #[must_use]
+    pub fn read(mut self) -> Self {
+        self.0.push(BfInstruc::Read);
+        self
+    }
+
+    /// runs what `body` builds for as long as the current cell is not zero, like `[...]`
+    #[must_use]
+    pub fn loop_(mut self, body: impl FnOnce(Self) -> Self) -> Self {
+        self.0.push(BfInstruc::LStart(0));
+
+        let mut this = body(self);
+        this.0.push(BfInstruc::LEnd(0));
+
+        this
+    }
+
+    /// Finishes the stream, sizing the tape like [`BfInstructionStream::optimized_from_text`]
+    /// when `array_len` is `None`. The instructions are kept as they were built.
+    ///
+    /// # Errors
+    /// Errors if the stream is too large for the interpreter
+    pub fn build(self, array_len: Option<u32>) -> Result<BfInstructionStream<T>, BfCompError> {
+        BfInstructionStream::try_from_instructions(self.0, array_len)
+    }
+}
+
+impl<T: BfOptimizable> BfInstructionStream<T> {
+    /// Starts building a stream in Rust, like
+    /// `BfInstructionStream::builder().inc(3).loop_(|b| b.dec(1).right(1).inc(2).left(1))`
+    #[must_use]
+    pub const fn builder() -> StreamBuilder<T> {
+        StreamBuilder::new()
+    }
+}
+
+#[test]
+fn test_builder() {
+    use crate::interpreter::BrainFuckExecutorBuilder;
+
+    // 3 * 4 in the second cell, then writes it
+    let stream = BfInstructionStream::<u8>::builder()
+        .inc(3)
+        .loop_(|b| b.dec(1).right(1).inc(4).left(1))
+        .right(1)
+        .write()
+        .build(None)
+        .unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(&[][..])
+        .stream_out(vec![])
+        .array_len(stream.reccomended_array_size())
+        .build()
+        .unwrap();
+
+    env.run(&stream).unwrap();
+
+    assert_eq!(env.stdout, b"\x0c");
+    assert_eq!(env.data[..2], [0, 12]);
+}
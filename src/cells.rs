@@ -0,0 +1,153 @@
+//! Converting tapes between cell widths
+
+use thiserror::Error;
+
+use crate::compiler::BfOptimizable;
+
+/// What to do with a cell whose value does not fit in a narrower width
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Narrowing {
+    /// fail on the first cell that does not fit
+    #[default]
+    Reject,
+    /// keep the low bits, the value a narrower wrapping cell would hold
+    Wrap,
+    /// clamp to the nearest value the narrower width can hold
+    Saturate,
+}
+
+/// A cell that did not fit in the target width under [`Narrowing::Reject`]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error(
+    "cell {idx} holds {value}, which does not fit in {} {bits} bits",
+    if *signed { "signed" } else { "unsigned" }
+)]
+pub struct CellTooWide {
+    pub idx: usize,
+    pub value: i128,
+    pub bits: u32,
+    /// whether the width it did not fit in is signed
+    pub signed: bool,
+}
+
+/// the value `cell` holds, reading its bits as two's complement for signed cell types
+fn value_of<T: BfOptimizable>(cell: T) -> i128 {
+    let bits: u64 = cell.into();
+    let unused = 128 - T::BITS;
+
+    if T::SIGNED {
+        (i128::from(bits) << unused) >> unused
+    } else {
+        i128::from(bits)
+    }
+}
+
+/// the smallest and largest values a cell of type `T` holds
+fn range_of<T: BfOptimizable>() -> (i128, i128) {
+    if T::SIGNED {
+        (-(1 << (T::BITS - 1)), (1 << (T::BITS - 1)) - 1)
+    } else {
+        (0, (1 << T::BITS) - 1)
+    }
+}
+
+/// the cell of type `T` holding the low bits of `value`
+fn from_bits<T: BfOptimizable>(value: i128) -> T {
+    let mask = u64::MAX >> (64 - T::BITS);
+
+    // truncating keeps the low bits, which is what the mask then narrows further
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let bits = value as u64 & mask;
+
+    T::try_from(bits).unwrap_or_else(|_| unreachable!("a masked value always fits"))
+}
+
+/// Converts every cell of `tape` to the width of `Dst`, keeping the value each cell holds. Signed
+/// cells are read as two's complement, so widening sign extends them and zero extends unsigned
+/// cells, while values that do not fit follow `narrowing`.
+///
+/// # Errors
+/// Errors with the first cell that does not fit when `narrowing` is [`Narrowing::Reject`]
+pub fn convert<Src: BfOptimizable, Dst: BfOptimizable>(
+    tape: &[Src],
+    narrowing: Narrowing,
+) -> Result<Vec<Dst>, CellTooWide> {
+    let (min, max) = range_of::<Dst>();
+
+    tape.iter()
+        .enumerate()
+        .map(|(idx, &cell)| {
+            let value = value_of(cell);
+
+            if (min..=max).contains(&value) {
+                return Ok(from_bits(value));
+            }
+
+            match narrowing {
+                Narrowing::Reject => Err(CellTooWide {
+                    idx,
+                    value,
+                    bits: Dst::BITS,
+                    signed: Dst::SIGNED,
+                }),
+                Narrowing::Wrap => Ok(from_bits(value)),
+                Narrowing::Saturate => Ok(from_bits(value.clamp(min, max))),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_convert() {
+    use crate::compiler::{I16, I8};
+
+    let wide = [1u32, 300, 70_000];
+
+    assert_eq!(convert::<u8, u32>(&[255], Narrowing::Reject), Ok(vec![255]));
+    assert_eq!(
+        convert::<u32, u8>(&wide, Narrowing::Reject),
+        Err(CellTooWide {
+            idx: 1,
+            value: 300,
+            bits: 8,
+            signed: false,
+        })
+    );
+    assert_eq!(
+        convert::<u32, u8>(&wide, Narrowing::Wrap),
+        Ok(vec![1, 44, 112])
+    );
+    assert_eq!(
+        convert::<u32, u16>(&wide, Narrowing::Saturate),
+        Ok(vec![1, 300, u16::MAX])
+    );
+
+    // signed cells keep their value, sign extending when widened and clamping to both ends
+    let signed = [I8(-1), I8(-128), I8(127)];
+
+    assert_eq!(
+        convert::<I8, I16>(&signed, Narrowing::Reject),
+        Ok(vec![I16(-1), I16(-128), I16(127)])
+    );
+    assert_eq!(
+        convert::<I8, u8>(&signed, Narrowing::Wrap),
+        Ok(vec![255, 128, 127])
+    );
+    assert_eq!(
+        convert::<I8, u16>(&signed, Narrowing::Saturate),
+        Ok(vec![0, 0, 127])
+    );
+    assert_eq!(
+        convert::<I16, I8>(&[I16(-300), I16(300)], Narrowing::Saturate),
+        Ok(vec![I8(-128), I8(127)])
+    );
+    assert_eq!(
+        convert::<u8, I8>(&[200], Narrowing::Reject),
+        Err(CellTooWide {
+            idx: 0,
+            value: 200,
+            bits: 8,
+            signed: true,
+        })
+    );
+}
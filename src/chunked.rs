@@ -0,0 +1,177 @@
+//! A tape kept in fixed size blocks for the interpreter, for huge tapes that programs only touch
+//! parts of
+//!
+//! Blocks are allocated the first time a cell in them is written, and cells in blocks that were
+//! never written read as zero. A program that scatters its work across a multi gigabyte tape only
+//! pays for the blocks it writes to, at the cost of finding the block on every access.
+
+use crate::compiler::BfOptimizable;
+use crate::interpreter::{BrainFuckExecutor, Tape};
+
+/// A tape of `len` cells stored as blocks of [`ChunkedTape::BLOCK_LEN`] cells, allocated on first
+/// write and found through an index of one entry per block
+#[derive(Debug, Clone)]
+pub struct ChunkedTape<T> {
+    blocks: Vec<Option<Box<[T]>>>,
+    len: usize,
+}
+
+impl<T: BfOptimizable> ChunkedTape<T> {
+    /// Cells per block, 64KiB of the narrowest cells
+    pub const BLOCK_LEN: usize = 1 << 16;
+
+    /// Creates a tape of `len` zeroed cells without allocating any blocks
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        Self {
+            blocks: vec![None; len.div_ceil(Self::BLOCK_LEN)],
+            len,
+        }
+    }
+
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns how many blocks have been allocated
+    #[must_use]
+    pub fn allocated_blocks(&self) -> usize {
+        self.blocks.iter().filter(|b| b.is_some()).count()
+    }
+
+    /// Returns the cell at `idx`, zero if its block was never written
+    ///
+    /// # Panics
+    /// Panics if `idx` is past the end of the tape
+    #[must_use]
+    pub fn get(&self, idx: usize) -> T {
+        assert!(idx < self.len, "cell {idx} is past the end of the tape");
+
+        self.blocks[idx / Self::BLOCK_LEN]
+            .as_ref()
+            .map_or(T::ZERO, |block| block[idx % Self::BLOCK_LEN])
+    }
+
+    /// Returns the cell at `idx` to write to, allocating its block if this is its first write
+    ///
+    /// # Panics
+    /// Panics if `idx` is past the end of the tape
+    pub fn get_mut(&mut self, idx: usize) -> &mut T {
+        assert!(idx < self.len, "cell {idx} is past the end of the tape");
+
+        let start = idx - idx % Self::BLOCK_LEN;
+        // the last block only holds the cells left at the end of the tape
+        let block_len = Self::BLOCK_LEN.min(self.len - start);
+
+        let block = self.blocks[idx / Self::BLOCK_LEN]
+            .get_or_insert_with(|| vec![T::ZERO; block_len].into_boxed_slice());
+
+        &mut block[idx % Self::BLOCK_LEN]
+    }
+}
+
+impl<T: BfOptimizable> Tape<T> for ChunkedTape<T> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn get(&self, idx: usize) -> T {
+        Self::get(self, idx)
+    }
+
+    fn get_mut(&mut self, idx: usize) -> &mut T {
+        Self::get_mut(self, idx)
+    }
+}
+
+/// Runs a [`BfInstructionStream`](crate::compiler::BfInstructionStream) over a [`ChunkedTape`],
+/// built with [`BrainFuckExecutorBuilder::build_with_tape`](crate::interpreter::BrainFuckExecutorBuilder::build_with_tape)
+pub type ChunkedExecutor<T, I, O> = BrainFuckExecutor<T, I, O, ChunkedTape<T>>;
+
+#[test]
+fn test_matches_flat_tape() {
+    use crate::compiler::BfInstructionStream;
+    use crate::interpreter::BrainFuckExecutorBuilder;
+
+    const PROGRAMS: &[&[u8]] = &[
+        b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.",
+        b",[.,]",
+        b",>,>,>,<<<[.>]",
+        b"-[>+<-----]>.[-]++++++++++.",
+        b"+++[->+.>++.>+++.>++++.>+++++.<<<<<]>>>>>>++[->+.>++.>+++.>++++.>+++++.<<<<<]",
+    ];
+
+    // the pointer wraps off the front of the tape, and input is read with a bulk read
+    const WRAPPING: &[u8] = b"<+++.>,,,<<[.<]";
+
+    for &program in PROGRAMS.iter().chain([&WRAPPING]) {
+        let mut code =
+            BfInstructionStream::<u8>::optimized_from_text(program.iter().copied(), None).unwrap();
+        // the last program repeats a loop, so calls into blocks are compared too
+        code.dedup_loops().unwrap();
+
+        let builder = || {
+            BrainFuckExecutorBuilder::<u8, _, _>::new()
+                .stream_in(&b"abc"[..])
+                .stream_out(vec![])
+                .wrap_tape(program == WRAPPING)
+        };
+
+        let mut flat = builder()
+            .array_len(code.reccomended_array_size())
+            .build()
+            .unwrap();
+        flat.run(&code).unwrap();
+
+        let mut chunked = builder()
+            .build_with_tape(ChunkedTape::new(code.reccomended_array_size()))
+            .unwrap();
+        chunked.run(&code).unwrap();
+
+        assert_eq!(chunked.stdout, flat.stdout);
+        assert_eq!(chunked.ptr, flat.ptr);
+
+        for (at, &cell) in flat.data.iter().enumerate() {
+            assert_eq!(chunked.data.get(at), cell);
+        }
+    }
+}
+
+#[test]
+fn test_blocks_on_first_write() {
+    use crate::compiler::BfInstructionStream;
+    use crate::interpreter::BrainFuckExecutorBuilder;
+
+    let new_exec = |len| {
+        BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(&[][..])
+            .stream_out(vec![])
+            .build_with_tape(ChunkedTape::new(len))
+            .unwrap()
+    };
+
+    let block = ChunkedTape::<u8>::BLOCK_LEN;
+
+    // a tape of billions of cells, where only the first block and one far off are written
+    let program = [&b"+.["[..], &b">".repeat(3 * block), b"]+<."].concat();
+    let code = BfInstructionStream::<u8>::optimized_from_text(program.into_iter(), None).unwrap();
+
+    let mut exec = new_exec(u32::MAX as usize);
+    exec.run(&code).unwrap();
+
+    assert_eq!(exec.stdout, [1, 0]);
+    assert_eq!(exec.ptr, 3 * block - 1);
+    assert_eq!(exec.data.allocated_blocks(), 2);
+    assert_eq!(exec.data.get(3 * block), 1);
+
+    let mut exec = new_exec(block + 1);
+    exec.ptr = block;
+    exec.run(&code).unwrap_err();
+    assert_eq!(exec.ptr, block);
+}
@@ -0,0 +1,435 @@
+//! Reading C written by the C backend back into an instruction stream, so generated C can be
+//! optimized again or rendered for another target without the brainfuck it came from
+//!
+//! Only the statements the backend itself writes are recognized. Resumable C is not supported,
+//! and neither is consteval output that resumes partway through a loop.
+
+use std::collections::HashMap;
+use std::num::NonZeroU32;
+
+use thiserror::Error;
+
+use crate::compiler::{BfCompError, BfInstruc, BfInstructionStream, BfOptimizable, ConstBytes};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CImportError {
+    #[error("line {line}: `{stmt}` is not a statement the C backend writes")]
+    Unrecognized { line: usize, stmt: String },
+    #[error("the C has `{found}` cells, but `{expected}` cells were asked for")]
+    CellType {
+        found: String,
+        expected: &'static str,
+    },
+    #[error("resumable C can not be imported")]
+    Resumable,
+    #[error(
+        "line {0}: execution resumes inside of a loop, which no instruction stream can express"
+    )]
+    ResumeInLoop(usize),
+    #[error("line {0}: call to a function that was not defined before it")]
+    UndefinedFunction(usize),
+    #[error("the C has no main function")]
+    NoMain,
+    #[error("the C ends inside of a block")]
+    UnexpectedEnd,
+    #[error(transparent)]
+    Compile(#[from] BfCompError),
+}
+
+/// A statement, or the start or end of a block, and the line it starts on
+struct Stmt<'a> {
+    line: usize,
+    text: &'a str,
+}
+
+/// Splits C into statements ending in `;`, `{` or `}`, dropping preprocessor lines. Returns the
+/// value given to `ARRSIZE` along with the statements.
+fn split(c: &str) -> (Option<u32>, Vec<Stmt<'_>>) {
+    let mut array_len = None;
+    let mut stmts = vec![];
+
+    let bytes = c.as_bytes();
+    let mut line = 1;
+    let mut start = 0;
+    let mut start_line = 1;
+    let mut idx = 0;
+    let mut in_string = false;
+
+    while idx < bytes.len() {
+        let b = bytes[idx];
+
+        if in_string {
+            match b {
+                b'\\' => idx += 1,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else if b == b'#' && c[start..idx].trim().is_empty() {
+            let end = c[idx..].find('\n').map_or(c.len(), |end| idx + end);
+
+            if let Some(len) = c[idx..end].strip_prefix("#define ARRSIZE ") {
+                array_len = len.trim().parse().ok();
+            }
+
+            idx = end;
+            start = end;
+            continue;
+        } else if matches!(b, b';' | b'{' | b'}') {
+            if b == b'}' && !c[start..idx].trim().is_empty() {
+                stmts.push(Stmt {
+                    line: start_line,
+                    text: c[start..idx].trim(),
+                });
+                start = idx;
+            }
+
+            stmts.push(Stmt {
+                line: start_line,
+                text: c[start..=idx].trim(),
+            });
+            start = idx + 1;
+        } else if b == b'"' {
+            in_string = true;
+        }
+
+        if bytes[idx] == b'\n' {
+            line += 1;
+        }
+
+        idx += 1;
+
+        if c[start..idx.min(c.len())].trim().is_empty() {
+            start_line = line;
+        }
+    }
+
+    (array_len, stmts)
+}
+
+/// parses a cell value as the backend writes it, which is negative for signed cells
+fn parse_cell<T: BfOptimizable>(text: &str) -> Option<T> {
+    let text = text.strip_suffix(T::C_LITERAL_SUFFIX).unwrap_or(text);
+
+    let value = match text.strip_prefix('-') {
+        Some(neg) => neg.parse::<u64>().ok()?.wrapping_neg(),
+        None => text.parse().ok()?,
+    };
+
+    T::try_from(value & (u64::MAX >> (64 - T::BITS))).ok()
+}
+
+/// decodes the string literal of an `fwrite` statement
+fn parse_fwrite(stmt: &str) -> Option<Vec<u8>> {
+    let literal = stmt.strip_prefix("fwrite(\"")?;
+    let literal = &literal[..literal.rfind("\", 1, ")?];
+
+    let mut bytes = vec![];
+    let mut chars = literal.bytes();
+
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+
+        match chars.next()? {
+            b'n' => bytes.push(b'\n'),
+            d @ b'0'..=b'7' => {
+                let mut value = u32::from(d - b'0');
+
+                for _ in 0..2 {
+                    value = value * 8 + u32::from(chars.next()?.checked_sub(b'0')?);
+                }
+
+                bytes.push(u8::try_from(value).ok()?);
+            }
+            other => bytes.push(other),
+        }
+    }
+
+    Some(bytes)
+}
+
+struct Importer<'a, T> {
+    stmts: Vec<Stmt<'a>>,
+    idx: usize,
+    functions: HashMap<&'a str, Vec<BfInstruc<T>>>,
+}
+
+impl<'a, T: BfOptimizable> Importer<'a, T> {
+    fn next(&mut self) -> Result<&Stmt<'a>, CImportError> {
+        let stmt = self
+            .stmts
+            .get(self.idx)
+            .ok_or(CImportError::UnexpectedEnd)?;
+        self.idx += 1;
+        Ok(stmt)
+    }
+
+    /// skips the block whose opening statement was just read
+    fn skip_block(&mut self) -> Result<(), CImportError> {
+        let mut depth = 1;
+
+        while depth > 0 {
+            let text = self.next()?.text;
+
+            if text.ends_with('{') {
+                depth += 1;
+            } else if text == "}" {
+                depth -= 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// reads a function body up to its closing brace
+    fn body(&mut self) -> Result<Vec<BfInstruc<T>>, CImportError> {
+        use BfInstruc::*;
+
+        let mut body = vec![];
+        let mut depth = 0usize;
+        // where `goto startpos_jump` was, everything after it up to the label is skipped
+        let mut jump = None;
+
+        loop {
+            let &Stmt { line, text } = self.next()?;
+            let unrecognized = || CImportError::Unrecognized {
+                line,
+                stmt: text.to_owned(),
+            };
+
+            let text = match text.strip_prefix("startpos_jump:") {
+                Some(rest) => {
+                    if depth != 0 {
+                        return Err(CImportError::ResumeInLoop(line));
+                    }
+
+                    if let Some(jump) = jump.take() {
+                        body.truncate(jump);
+                    }
+
+                    rest.trim()
+                }
+                None => text,
+            };
+
+            let number = |prefix: &str, suffix: &str| {
+                text.strip_prefix(prefix)
+                    .and_then(|rest| rest.strip_suffix(suffix))
+                    .map(str::trim)
+            };
+            let nonzero = |n: &str| n.parse::<NonZeroU32>().map_err(|_| unrecognized());
+            let cell = |n: &str| parse_cell::<T>(n).ok_or_else(unrecognized);
+
+            match text {
+                "}" if depth == 0 => return Ok(body),
+                "}" => {
+                    depth -= 1;
+                    body.push(LEnd(0));
+                }
+                "while (*a != 0) {" | "if (*a != 0) {" => {
+                    depth += 1;
+                    body.push(LStart(0));
+                }
+                // `fflush` only ever follows the output consteval baked in, which was written
+                // while the tape was still all zero, and decompiling the bytes as writes leaves
+                // them in the current cell
+                "*a = 0;" | "fflush(stdout);" => body.push(Zero),
+                "++*a;" => body.push(Inc),
+                "--*a;" => body.push(Dec),
+                "++a;" => body.push(IncPtr),
+                "--a;" => body.push(DecPtr),
+                "w(*a);" => body.push(Write),
+                "r(a);" => body.push(Read),
+                "goto startpos_jump;" => jump = Some(body.len()),
+                "exit(0);" => body.push(Halt),
+                "return 0;" | "return a;" | "" => {}
+                _ if text.ends_with("= arr;") => {}
+                // the mask is implied by the cell type
+                _ if text.starts_with("*a &= ") => {}
+                _ if text.starts_with("fwrite(") => {
+                    let bytes = parse_fwrite(text).ok_or_else(unrecognized)?;
+
+                    for chunk in bytes.chunks(ConstBytes::CAPACITY) {
+                        body.push(WriteConst(ConstBytes::new(chunk)));
+                    }
+                }
+                _ => {
+                    if let Some(n) = number("*a += ", ";") {
+                        body.push(IncBy(cell(n)?));
+                    } else if let Some(n) = number("*a -= ", ";") {
+                        body.push(DecBy(cell(n)?));
+                    } else if let Some(n) = number("*a = ", ";") {
+                        body.push(Set(cell(n)?));
+                    } else if let Some(n) = number("a += ", ";") {
+                        body.push(IncPtrBy(nonzero(n)?));
+                    } else if let Some(n) = number("a -= ", ";") {
+                        body.push(DecPtrBy(nonzero(n)?));
+                    } else if let Some(n) = number("rn(a, ", ");") {
+                        let n = nonzero(n)?;
+
+                        // the pointer moves past the cells read in a statement of its own
+                        if self.next()?.text != format!("a += {n};") {
+                            return Err(unrecognized());
+                        }
+
+                        body.push(ReadN(n));
+                    } else if let Some(name) = number("a = ", "(a);") {
+                        let function = self
+                            .functions
+                            .get(name)
+                            .ok_or(CImportError::UndefinedFunction(line))?;
+
+                        body.extend_from_slice(function);
+                    } else if let Some((idx, value)) =
+                        number("a[", ";").and_then(|rest| rest.split_once("] = "))
+                    {
+                        // cells set before main runs, while the pointer is at the first cell
+                        let (idx, value) = (
+                            idx.parse::<u32>().map_err(|_| unrecognized())?,
+                            cell(value)?,
+                        );
+
+                        match NonZeroU32::new(idx) {
+                            Some(idx) => body.extend([IncPtrBy(idx), Set(value), DecPtrBy(idx)]),
+                            None => body.push(Set(value)),
+                        }
+                    } else {
+                        return Err(unrecognized());
+                    }
+                }
+            }
+        }
+    }
+
+    fn import(mut self, array_len: Option<u32>) -> Result<BfInstructionStream<T>, CImportError> {
+        let mut main = None;
+
+        while let Some(&Stmt { line, text }) = self.stmts.get(self.idx) {
+            self.idx += 1;
+
+            if text.contains("bf_resume(") {
+                return Err(CImportError::Resumable);
+            }
+
+            if let Some(found) = text
+                .strip_prefix("static ")
+                .and_then(|rest| rest.strip_suffix(" arr[ARRSIZE];"))
+            {
+                if found != T::C_INT_NAME {
+                    return Err(CImportError::CellType {
+                        found: found.to_owned(),
+                        expected: T::C_INT_NAME,
+                    });
+                }
+            } else if text == "int main() {" {
+                main = Some(self.body()?);
+            } else if let Some(name) = text
+                .strip_prefix(&format!("static {}* ", T::C_INT_NAME))
+                .and_then(|rest| rest.split_once('('))
+                .map(|(name, _)| name)
+                .filter(|name| name.starts_with("bf_"))
+            {
+                let body = self.body()?;
+                self.functions.insert(name, body);
+            } else if text.starts_with("void ") && text.ends_with('{') {
+                // the IO helpers
+                self.skip_block()?;
+            } else {
+                return Err(CImportError::Unrecognized {
+                    line,
+                    stmt: text.to_owned(),
+                });
+            }
+        }
+
+        let main = main.ok_or(CImportError::NoMain)?;
+
+        Ok(BfInstructionStream::try_from_instructions(main, array_len)?)
+    }
+}
+
+/// Reads C written by the C backend for cells of type `T` back into an instruction stream
+///
+/// # Errors
+/// Errors on any statement the C backend would not have written, if the C was written for
+/// another cell type, or if the C is resumable or resumes partway through a loop
+pub fn import_c<T: BfOptimizable>(c: &str) -> Result<BfInstructionStream<T>, CImportError> {
+    let (array_len, stmts) = split(c);
+
+    Importer {
+        stmts,
+        idx: 0,
+        functions: HashMap::new(),
+    }
+    .import(array_len)
+}
+
+#[test]
+fn test_import_c() {
+    use crate::compiler::CCodegenOpts;
+    use crate::interpreter::BrainFuckExecutorBuilder;
+
+    let run = |stream: &BfInstructionStream<u8>| {
+        let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(&b"xyz"[..])
+            .stream_out(vec![])
+            .array_len(stream.reccomended_array_size())
+            .build()
+            .unwrap();
+
+        env.run(stream).unwrap();
+        (env.stdout, env.data)
+    };
+
+    let source =
+        b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.
+        ------.--------.>>+.>++.,[.,]>>,>,>,<<[-]+++[>+++<-]>.";
+    let stream =
+        BfInstructionStream::<u8>::optimized_from_text(source.iter().copied(), None).unwrap();
+
+    // outlining every few statements puts the program through function definitions and calls
+    for split_threshold in [None, std::num::NonZeroUsize::new(3)] {
+        let opts = CCodegenOpts {
+            split_threshold,
+            ..CCodegenOpts::default()
+        };
+
+        let mut c = vec![];
+        stream.render_c_with(opts, &mut c).unwrap();
+
+        let imported = import_c::<u8>(std::str::from_utf8(&c).unwrap()).unwrap();
+        assert_eq!(run(&imported), run(&stream));
+    }
+
+    let mut c = vec![];
+    stream.render_c(&mut c).unwrap();
+    assert!(matches!(
+        import_c::<u16>(std::str::from_utf8(&c).unwrap()),
+        Err(CImportError::CellType { .. })
+    ));
+}
+
+#[test]
+fn test_import_c_wide_cells() {
+    fn round_trip<T: BfOptimizable + std::fmt::Debug>() {
+        let source = b",[-]-.>,----.>+[-]--<";
+        let stream =
+            BfInstructionStream::<T>::optimized_from_text(source.iter().copied(), None).unwrap();
+
+        let mut c = vec![];
+        stream.render_c(&mut c).unwrap();
+
+        let imported = import_c::<T>(std::str::from_utf8(&c).unwrap()).unwrap();
+        assert_eq!(&*imported, &*stream);
+
+        // constants near the top of the cell range come back as short brainfuck
+        let mut bf = vec![];
+        imported.render_bf(&mut bf).unwrap();
+        assert_eq!(bf, b",[-]-.>,----.>[-]--<\n");
+    }
+
+    round_trip::<u32>();
+    round_trip::<u64>();
+}
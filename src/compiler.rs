@@ -1,9 +1,15 @@
 use core::fmt;
-use std::io;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
 use std::num::NonZeroU32;
+use std::num::NonZeroUsize;
+use std::ops::Range;
 use thiserror::Error;
 use usize_cast::IntoUsize;
 
+use crate::token::{is_op, Extensions, Token, TokenKind, Tokenizer};
+
 #[repr(u8)]
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub enum BfInstruc<CellSize> {
@@ -14,12 +20,71 @@ pub enum BfInstruc<CellSize> {
     DecPtr,
     Write,
     Read,
+    /// jumps past the matching [`LEnd`](BfInstruc::LEnd) at this index when the cell is zero.
+    /// Indexes are `u32` so instructions stay 8 bytes for cells up to 32 bits, which limits a
+    /// stream to `u32::MAX` instructions, longer ones fail with [`BfCompError::Overflow`]
     LStart(u32),
+    /// jumps back to the matching [`LStart`](BfInstruc::LStart) at this index when the cell is
+    /// not zero
     LEnd(u32),
     IncBy(CellSize),
     DecBy(CellSize),
     IncPtrBy(NonZeroU32),
     DecPtrBy(NonZeroU32),
+    Set(CellSize),
+    /// reads into this and the following cells, then moves past them, like a run of `,>`
+    ReadN(NonZeroU32),
+    /// writes bytes that were known at compile time
+    WriteConst(ConstBytes),
+    /// stores how many instructions have run before it in the current cell, wrapping, see
+    /// [`Extensions::clock`]
+    Clock,
+    /// ends the program successfully, see [`Extensions::halt`]
+    Halt,
+    /// dumps the pointer and the cells around it to stderr, see [`Extensions::debug`]
+    Debug,
+    /// runs the block starting at this index, continuing after the call once the block returns,
+    /// see [`BfInstructionStream::dedup_loops`]
+    Call(u32),
+    /// ends the block the last [`Call`](BfInstruc::Call) ran, or the program if no block is running
+    Return,
+}
+
+/// Up to [`ConstBytes::CAPACITY`] bytes stored inline, so instructions carrying them stay `Copy`
+/// and no larger than any other instruction
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct ConstBytes {
+    len: u8,
+    bytes: [u8; Self::CAPACITY],
+}
+
+impl ConstBytes {
+    pub const CAPACITY: usize = 6;
+
+    /// # Panics
+    /// Panics if `bytes` is longer than [`ConstBytes::CAPACITY`]
+    #[must_use]
+    pub fn new(bytes: &[u8]) -> Self {
+        assert!(
+            bytes.len() <= Self::CAPACITY,
+            "too many bytes for ConstBytes"
+        );
+
+        let mut inline = [0; Self::CAPACITY];
+        inline[..bytes.len()].copy_from_slice(bytes);
+
+        Self {
+            // checked against CAPACITY above
+            #[allow(clippy::cast_possible_truncation)]
+            len: bytes.len() as u8,
+            bytes: inline,
+        }
+    }
+
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..usize::from(self.len)]
+    }
 }
 
 impl<T> TryFrom<u8> for BfInstruc<T> {
@@ -38,6 +103,9 @@ impl<T> TryFrom<u8> for BfInstruc<T> {
             // Jump points must be computed later by the full stream parser
             b'[' => LStart(0),
             b']' => LEnd(0),
+            b'^' => Clock,
+            b'@' => Halt,
+            b'#' => Debug,
             _ => {
                 return Err(value);
             }
@@ -46,6 +114,45 @@ impl<T> TryFrom<u8> for BfInstruc<T> {
 }
 
 impl<T> BfInstruc<T> {
+    /// Returns the unoptimized instruction for a token, or `None` for a comment
+    #[must_use]
+    pub fn from_token(token: &Token) -> Option<Self> {
+        match token.kind {
+            TokenKind::Op(byte) => Self::try_from(byte).ok(),
+            TokenKind::Comment => None,
+        }
+    }
+
+    /// Returns the name of this instruction's variant, ignoring its payload
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        use BfInstruc::*;
+
+        match self {
+            Zero => "Zero",
+            Inc => "Inc",
+            Dec => "Dec",
+            IncPtr => "IncPtr",
+            DecPtr => "DecPtr",
+            Write => "Write",
+            Read => "Read",
+            LStart(_) => "LStart",
+            LEnd(_) => "LEnd",
+            IncBy(_) => "IncBy",
+            DecBy(_) => "DecBy",
+            IncPtrBy(_) => "IncPtrBy",
+            DecPtrBy(_) => "DecPtrBy",
+            Set(_) => "Set",
+            ReadN(_) => "ReadN",
+            WriteConst(_) => "WriteConst",
+            Clock => "Clock",
+            Halt => "Halt",
+            Debug => "Debug",
+            Call(_) => "Call",
+            Return => "Return",
+        }
+    }
+
     fn as_multi_with(&self, v: u32) -> Option<Self>
     where
         T: BfOptimizable,
@@ -55,21 +162,21 @@ impl<T> BfInstruc<T> {
         // this will actually overflow on a maxxed out u32, to combat this we limit the max size of a stream to around 2 billion instructions
         // security as layers, or something
 
-        let rem_v = T::MAX.into().checked_add(1);
+        let rem_v = 1u64.checked_shl(T::BITS);
 
-        let loop_use = rem_v.map_or(v, |rem| v % rem);
+        let loop_use = rem_v.map_or(u64::from(v), |rem| u64::from(v) % rem);
 
         Some(match self {
             Inc => IncBy(
                 loop_use
                     .try_into()
-                    .map_err(|_| panic!("could not convert u32 to T"))
+                    .map_err(|_| panic!("could not convert u64 to T"))
                     .unwrap(),
             ),
             Dec => DecBy(
                 loop_use
                     .try_into()
-                    .map_err(|_| panic!("could not convert u32 to T"))
+                    .map_err(|_| panic!("could not convert u64 to T"))
                     .unwrap(),
             ),
             IncPtr => IncPtrBy(NonZeroU32::new(v).unwrap()),
@@ -85,11 +192,12 @@ impl<T> BfInstruc<T> {
 
     fn write_c_for(&self, out: &mut dyn io::Write) -> io::Result<()>
     where
-        T: fmt::Display,
+        T: BfOptimizable,
     {
         use BfInstruc::*;
 
         let opening_brace = '{';
+        let suffix = T::C_LITERAL_SUFFIX;
 
         match self {
             Zero => write!(out, "*a = 0;"),
@@ -101,15 +209,127 @@ impl<T> BfInstruc<T> {
             Read => write!(out, "r(a);"),
             LStart(_) => write!(out, "while (*a != 0) {opening_brace}"),
             LEnd(_) => out.write_all(b"}"),
-            IncBy(amount) => write!(out, "*a += {amount};"),
-            DecBy(amount) => write!(out, "*a -= {amount};"),
+            IncBy(amount) => write!(out, "*a += {amount}{suffix};"),
+            DecBy(amount) => write!(out, "*a -= {amount}{suffix};"),
             IncPtrBy(amount) => write!(out, "a += {amount};"),
             DecPtrBy(amount) => write!(out, "a -= {amount};"),
+            Set(value) => write!(out, "*a = {value}{suffix};"),
+            ReadN(amount) => write!(out, "rn(a, {amount}); a += {amount};"),
+            WriteConst(bytes) => write_c_fwrite(bytes.as_bytes(), out),
+            Clock => Err(io::Error::other(
+                "the clock extension has no C equivalent, C does not count instructions",
+            )),
+            // this may be in an outlined function, where returning would not end the program
+            Halt => write!(out, "exit(0);"),
+            Debug => Err(io::Error::other(
+                "the debug extension has no C equivalent, it is only run by the interpreter",
+            )),
+            Call(_) | Return => Err(io::Error::other(
+                "deduplicated blocks have no C equivalent, render C from the stream before \
+                 deduplicating it",
+            )),
+        }?;
+
+        // cells narrower than their C type have to be wrapped by hand
+        match (self, T::C_MASK) {
+            (Inc | Dec | IncBy(_) | DecBy(_), Some(mask)) => write!(out, " *a &= {mask:#x};"),
+            _ => Ok(()),
+        }
+    }
+
+    /// appends plain brainfuck equivalent to this instruction to `out`, erroring if it does not
+    /// fit in memory
+    fn push_bf_for(&self, out: &mut Vec<u8>) -> io::Result<()>
+    where
+        T: BfOptimizable,
+    {
+        use BfInstruc::*;
+
+        let repeat = |out: &mut Vec<u8>, b: u8, n: u64| {
+            let n = usize::try_from(n)
+                .ok()
+                .filter(|&n| out.try_reserve(n).is_ok());
+            let Some(n) = n else {
+                return Err(io::Error::other(
+                    "the brainfuck for this stream is too large to hold in memory",
+                ));
+            };
+
+            out.extend(std::iter::repeat_n(b, n));
+            Ok(())
+        };
+
+        // cells wrap, so adding more than half their range is shorter as a subtraction
+        let shortest = |amount: T, up: u8, down: u8| {
+            let amount: u64 = amount.into();
+            let mask = u64::MAX >> (64 - T::BITS);
+
+            if amount > mask / 2 {
+                (down, amount.wrapping_neg() & mask)
+            } else {
+                (up, amount)
+            }
+        };
+
+        match self {
+            Zero => out.extend_from_slice(b"[-]"),
+            Inc => out.push(b'+'),
+            Dec => out.push(b'-'),
+            IncPtr => out.push(b'>'),
+            DecPtr => out.push(b'<'),
+            Write => out.push(b'.'),
+            Read => out.push(b','),
+            LStart(_) => out.push(b'['),
+            LEnd(_) => out.push(b']'),
+            IncBy(amount) => {
+                let (b, n) = shortest(*amount, b'+', b'-');
+                repeat(out, b, n)?;
+            }
+            DecBy(amount) => {
+                let (b, n) = shortest(*amount, b'-', b'+');
+                repeat(out, b, n)?;
+            }
+            IncPtrBy(amount) => repeat(out, b'>', amount.get().into())?,
+            DecPtrBy(amount) => repeat(out, b'<', amount.get().into())?,
+            Set(value) => {
+                out.extend_from_slice(b"[-]");
+
+                let (b, n) = shortest(*value, b'+', b'-');
+                repeat(out, b, n)?;
+            }
+            ReadN(amount) => {
+                for _ in 0..amount.get() {
+                    out.extend_from_slice(b",>");
+                }
+            }
+            // constant writes only hold bytes that were also the value of their cell, and the
+            // last one written is the value the cell is left with
+            WriteConst(bytes) => {
+                out.extend_from_slice(b"[-]");
+
+                let mut cur = 0u8;
+                for &b in bytes.as_bytes() {
+                    if b >= cur {
+                        repeat(out, b'+', (b - cur).into())?;
+                    } else {
+                        repeat(out, b'-', (cur - b).into())?;
+                    }
+                    out.push(b'.');
+                    cur = b;
+                }
+            }
+            Clock => out.push(b'^'),
+            Halt => out.push(b'@'),
+            Debug => out.push(b'#'),
+            // render_bf refuses streams with blocks before getting here
+            Call(_) | Return => {}
         }
+
+        Ok(())
     }
 }
 
-#[derive(Copy, Clone, Debug, Error)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Error)]
 pub enum BfCompError {
     #[error(
         "the count of loop start instructions does not match the count of loop end instructions"
@@ -117,16 +337,19 @@ pub enum BfCompError {
     LoopCountMismatch,
     #[error("loop end instruction was encountered before loop start instruction to complete it")]
     LoopEndBeforeLoopStart,
+    /// the program has more instructions than jump indexes or the tape pointer can address
     #[error("overflowed maximum code size allowed by interpreter")]
     Overflow,
+    #[error("call instruction targets the start of the stream or past its end")]
+    CallOutOfRange,
 }
 
 pub trait BfOptimizable:
     Copy
     + Clone
     + Eq
-    + Into<u32>
-    + TryFrom<u32>
+    + Into<u64>
+    + TryFrom<u64>
     + From<u8>
     + Ord
     + std::ops::Rem<Self, Output = Self>
@@ -135,7 +358,15 @@ pub trait BfOptimizable:
 {
     const MAX: Self;
     const ZERO: Self;
+    const BITS: u32;
     const C_INT_NAME: &'static str;
+    /// suffix given to integer constants of this width in C, for values too large for `long long`
+    const C_LITERAL_SUFFIX: &'static str;
+    /// mask applied in C after every arithmetic instruction, for cells narrower than their C type
+    const C_MASK: Option<u64> = None;
+    /// whether the bits of a cell are a two's complement value, which conversions between widths
+    /// sign extend
+    const SIGNED: bool = false;
 
     #[must_use]
     fn wrapping_add(self, other: Self) -> Self;
@@ -147,11 +378,13 @@ pub trait BfOptimizable:
 }
 
 macro_rules! make_optimizable {
-    ($Ty:ty, $c_int:expr) => {
+    ($Ty:ty, $c_int:expr, $c_suffix:expr) => {
         impl BfOptimizable for $Ty {
             const MAX: Self = Self::MAX;
             const ZERO: Self = 0;
+            const BITS: u32 = Self::BITS;
             const C_INT_NAME: &'static str = $c_int;
+            const C_LITERAL_SUFFIX: &'static str = $c_suffix;
 
             fn wrapping_add(self, other: Self) -> Self {
                 self.wrapping_add(other)
@@ -161,6 +394,7 @@ macro_rules! make_optimizable {
                 self.wrapping_sub(other)
             }
 
+            #[allow(clippy::cast_possible_truncation)]
             fn truncate_u8(self) -> u8 {
                 self as u8
             }
@@ -168,9 +402,256 @@ macro_rules! make_optimizable {
     };
 }
 
-make_optimizable!(u8, "unsigned char");
-make_optimizable!(u16, "unsigned short");
-make_optimizable!(u32, "unsigned int");
+make_optimizable!(u8, "unsigned char", "");
+make_optimizable!(u16, "unsigned short", "");
+make_optimizable!(u32, "unsigned int", "");
+make_optimizable!(u64, "unsigned long long", "ULL");
+
+macro_rules! make_signed {
+    ($(#[$doc:meta])* $Name:ident, $Signed:ty, $Unsigned:ty, $c_int:expr) => {
+        $(#[$doc])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        pub struct $Name(pub $Signed);
+
+        /// bytes read from input keep their value, wrapping when the cell is too narrow to hold it
+        impl From<u8> for $Name {
+            #[allow(clippy::cast_possible_wrap)]
+            fn from(value: u8) -> Self {
+                Self(<$Unsigned>::from(value) as $Signed)
+            }
+        }
+
+        /// the two's complement bit pattern of the cell, so tapes and conversions see the same
+        /// bits an unsigned cell of this width would hold
+        impl From<$Name> for u64 {
+            #[allow(clippy::cast_sign_loss)]
+            fn from(value: $Name) -> Self {
+                u64::from(value.0 as $Unsigned)
+            }
+        }
+
+        /// accepts any bit pattern of this width
+        impl TryFrom<u64> for $Name {
+            type Error = std::num::TryFromIntError;
+
+            #[allow(clippy::cast_possible_wrap)]
+            fn try_from(value: u64) -> Result<Self, Self::Error> {
+                Ok(Self(<$Unsigned>::try_from(value)? as $Signed))
+            }
+        }
+
+        impl std::ops::Rem for $Name {
+            type Output = Self;
+
+            fn rem(self, other: Self) -> Self {
+                Self(self.0.wrapping_rem(other.0))
+            }
+        }
+
+        impl fmt::Display for $Name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl BfOptimizable for $Name {
+            const MAX: Self = Self(<$Signed>::MAX);
+            const ZERO: Self = Self(0);
+            const BITS: u32 = <$Signed>::BITS;
+            const C_INT_NAME: &'static str = $c_int;
+            const C_LITERAL_SUFFIX: &'static str = "";
+            const SIGNED: bool = true;
+
+            fn wrapping_add(self, other: Self) -> Self {
+                Self(self.0.wrapping_add(other.0))
+            }
+
+            fn wrapping_sub(self, other: Self) -> Self {
+                Self(self.0.wrapping_sub(other.0))
+            }
+
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            fn truncate_u8(self) -> u8 {
+                self.0 as u8
+            }
+        }
+    };
+}
+
+make_signed!(
+    /// A signed 8 bit cell, for dialects where cells count below zero
+    I8,
+    i8,
+    u8,
+    "signed char"
+);
+make_signed!(
+    /// A signed 16 bit cell, for dialects where cells count below zero
+    I16,
+    i16,
+    u16,
+    "short"
+);
+// cells only ever wrap, are tested against zero and have their low byte written, which are all
+// the same for signed and unsigned cells of a width. Arithmetic on C `signed char` and `short`
+// promotes to `int` and can not overflow, but on `int` itself overflow is undefined behaviour,
+// so 32 bit signed cells are unsigned in C.
+make_signed!(
+    /// A signed 32 bit cell, for dialects where cells count below zero
+    I32,
+    i32,
+    u32,
+    "unsigned int"
+);
+
+/// A value too large for the cell width it was converted to
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("value does not fit in a {0} bit cell")]
+pub struct CellOverflow(pub u32);
+
+/// An unsigned cell of any width from 9 to 31 bits, stored in a `u32` and masked after every
+/// arithmetic operation, for emulating machines with odd word sizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Masked<const N: u32>(u32);
+
+impl<const N: u32> Masked<N> {
+    const MASK: u32 = {
+        assert!(N > 8 && N < 32, "masked cells must be 9 to 31 bits wide");
+        u32::MAX >> (32 - N)
+    };
+
+    /// Returns the value of this cell
+    #[must_use]
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+}
+
+impl<const N: u32> From<u8> for Masked<N> {
+    fn from(value: u8) -> Self {
+        Self(u32::from(value) & Self::MASK)
+    }
+}
+
+impl<const N: u32> From<Masked<N>> for u64 {
+    fn from(value: Masked<N>) -> Self {
+        u64::from(value.0)
+    }
+}
+
+impl<const N: u32> TryFrom<u64> for Masked<N> {
+    type Error = CellOverflow;
+
+    fn try_from(value: u64) -> Result<Self, Self::Error> {
+        match u32::try_from(value) {
+            Ok(value) if value <= Self::MASK => Ok(Self(value)),
+            _ => Err(CellOverflow(N)),
+        }
+    }
+}
+
+impl<const N: u32> std::ops::Rem for Masked<N> {
+    type Output = Self;
+
+    fn rem(self, other: Self) -> Self {
+        Self(self.0 % other.0)
+    }
+}
+
+impl<const N: u32> fmt::Display for Masked<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl<const N: u32> BfOptimizable for Masked<N> {
+    const MAX: Self = Self(Self::MASK);
+    const ZERO: Self = Self(0);
+    const BITS: u32 = N;
+    const C_INT_NAME: &'static str = "unsigned int";
+    const C_LITERAL_SUFFIX: &'static str = "";
+    const C_MASK: Option<u64> = Some(Self::MASK as u64);
+
+    // 2^N divides 2^32, so wrapping in 32 bits and then masking wraps modulo 2^N
+    fn wrapping_add(self, other: Self) -> Self {
+        Self(self.0.wrapping_add(other.0) & Self::MASK)
+    }
+
+    fn wrapping_sub(self, other: Self) -> Self {
+        Self(self.0.wrapping_sub(other.0) & Self::MASK)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn truncate_u8(self) -> u8 {
+        self.0 as u8
+    }
+}
+
+thread_local! {
+    static CURRENT_PASS: Cell<&'static str> = const { Cell::new("none") };
+    static OPT_FUEL: Cell<Option<u64>> = const { Cell::new(None) };
+    static LAST_REWRITE_PASS: Cell<&'static str> = const { Cell::new("none") };
+}
+
+/// Returns the name of the optimizer pass or synthesizer currently running on this thread,
+/// used to give context to internal compiler error reports
+#[must_use]
+pub fn current_pass() -> &'static str {
+    CURRENT_PASS.with(Cell::get)
+}
+
+/// Restores the pass that was running before [`enter_pass`] when dropped, so the current pass is
+/// accurate however the pass it names returns
+struct PassGuard(&'static str);
+
+impl Drop for PassGuard {
+    fn drop(&mut self) {
+        CURRENT_PASS.with(|pass| pass.set(self.0));
+    }
+}
+
+#[must_use]
+fn enter_pass(name: &'static str) -> PassGuard {
+    PassGuard(CURRENT_PASS.with(|pass| pass.replace(name)))
+}
+
+/// runs `func` as the pass called `name`
+fn in_pass<R>(name: &'static str, func: impl FnOnce() -> R) -> R {
+    let _pass = enter_pass(name);
+    func()
+}
+
+/// Limits optimizer passes on this thread to `fuel` more rewrites, after which they leave the
+/// stream as it is, or removes the limit when `None`. Bisecting over the fuel given to a
+/// miscompiled program finds the first rewrite that breaks it.
+pub fn set_opt_fuel(fuel: Option<u64>) {
+    OPT_FUEL.with(|left| left.set(fuel));
+}
+
+/// Returns the rewrites left on this thread, or `None` when there is no limit
+#[must_use]
+pub fn opt_fuel() -> Option<u64> {
+    OPT_FUEL.with(Cell::get)
+}
+
+/// Returns the pass that made the last rewrite counted against the fuel on this thread
+#[must_use]
+pub fn last_rewrite_pass() -> &'static str {
+    LAST_REWRITE_PASS.with(Cell::get)
+}
+
+/// Spends one rewrite of fuel, returning false if the rewrite must not be made
+fn take_fuel() -> bool {
+    OPT_FUEL.with(|left| match left.get() {
+        None => true,
+        Some(0) => false,
+        Some(n) => {
+            left.set(Some(n - 1));
+            LAST_REWRITE_PASS.with(|pass| pass.set(current_pass()));
+            true
+        }
+    })
+}
 
 pub struct BfExecState<'a, T: BfOptimizable> {
     pub cursor: usize,
@@ -205,75 +686,181 @@ fn byte_to_cstr_literal(b: u8, buf: &mut [u8; 4]) -> &str {
     core::str::from_utf8(buf).unwrap()
 }
 
+/// writes a C statement writing `bytes` to stdout with a single call
+fn write_c_fwrite(bytes: &[u8], out: &mut dyn io::Write) -> io::Result<()> {
+    write!(out, "fwrite(\"")?;
+
+    for &c in bytes {
+        write!(out, "{}", byte_to_cstr_literal(c, &mut [0; 4]))?;
+    }
+
+    write!(out, "\", 1, {}, stdout);", bytes.len())
+}
+
 impl<T: BfOptimizable> BfInstructionStream<T> {
+    /// the cells generated C allocates for the tape. When the pointer provably never passes a
+    /// cell, the cells after it are never touched and are left out, shrinking the binary's bss.
+    /// `bf_resume` can be entered with any pointer, so resumable C always gets the full tape.
+    fn c_tape_len(&self, opts: CCodegenOpts) -> usize {
+        if opts.resumable {
+            return self.1;
+        }
+
+        crate::analysis::prove_pointer_bounds(&self.0, self.1)
+            .map_or(self.1, |bounds| bounds.max + 1)
+    }
+
     fn write_c_header(
         &self,
         out: &mut dyn io::Write,
         use_write: bool,
         use_read: bool,
-        use_stack: bool,
+        tape_len: Option<usize>,
+        opts: CCodegenOpts,
+        defs: &[u8],
     ) -> io::Result<()> {
         writeln!(out, "#include <stdio.h>")?;
 
-        if use_stack {
-            writeln!(out, "#define ARRSIZE {}", self.1)?;
+        if self.0.contains(&BfInstruc::Halt) {
+            writeln!(out, "#include <stdlib.h>")?;
+        }
+
+        if let Some(tape_len) = tape_len {
+            writeln!(out, "#define ARRSIZE {tape_len}")?;
+            // static storage places the tape in zero initialized memory, so no code is generated to
+            // initialize it, and large tapes do not overflow the stack
+            writeln!(out, "static {} arr[ARRSIZE];", T::C_INT_NAME)?;
         }
 
         if use_write {
             writeln!(out, "void w(char v) {{ fputc(v, stdout); }}")?;
         }
         if use_read {
+            let minus_one = T::ZERO.wrapping_sub(T::from(1));
+            let suffix = T::C_LITERAL_SUFFIX;
+
+            // what is stored in a cell read past the end of input
+            let (read, past_got) = match opts.eof {
+                Eof::Zero => (
+                    "*a = fgetc(stdin); if (feof(stdin)) *a = 0;".to_owned(),
+                    "0".to_owned(),
+                ),
+                Eof::MinusOne => (
+                    format!("*a = fgetc(stdin); if (feof(stdin)) *a = {minus_one}{suffix};"),
+                    format!("{minus_one}{suffix}"),
+                ),
+                Eof::Unchanged => (
+                    "{ int c = fgetc(stdin); if (c != EOF) *a = c; }".to_owned(),
+                    "a[i]".to_owned(),
+                ),
+            };
+
             writeln!(
                 out,
-                "void r({}* a) {{ fflush(stdout); *a = fgetc(stdin); if (feof(stdin)) *a = 0; }}",
+                "void r({}* a) {{ fflush(stdout); {read} }}",
                 T::C_INT_NAME
             )?;
-        }
-
-        writeln!(out, "int main() {{")?;
-
-        if use_stack {
+            // reads in chunks with one fread each, filling anything past the end of input
             writeln!(
                 out,
-                "{} arr[ARRSIZE] = {{0,}};\n{}* restrict a = arr;",
-                T::C_INT_NAME,
+                "void rn({}* a, unsigned long n) {{ unsigned char b[256]; unsigned long i, k, got; \
+                 fflush(stdout); while (n != 0) {{ k = n < 256 ? n : 256; got = fread(b, 1, k, stdin); \
+                 for (i = 0; i < k; i++) a[i] = i < got ? b[i] : {past_got}; a += k; n -= k; }} }}",
                 T::C_INT_NAME
             )?;
         }
 
+        out.write_all(defs)?;
+
+        writeln!(out, "int main() {{")?;
+
+        if tape_len.is_some() {
+            writeln!(out, "{}* {}a = arr;", T::C_INT_NAME, opts.std.restrict())?;
+        }
+
         Ok(())
     }
 
+    fn write_c_footer(out: &mut dyn io::Write, std: CStd) -> io::Result<()> {
+        // falling off the end of main only implies a successful exit status since C99
+        if std == CStd::C89 {
+            writeln!(out, "return 0;")?;
+        }
+
+        writeln!(out, "}}")
+    }
+
     /// renders this instruction stream to a writer in c
     ///
     /// # Errors
     /// This function returns any errors raised by the `out` parameter
-    pub fn render_c(&self, mut out: &mut dyn io::Write) -> io::Result<()> {
+    pub fn render_c(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        self.render_c_with(CCodegenOpts::default(), out)
+    }
+
+    /// renders this instruction stream to a writer in c, using the given codegen options
+    ///
+    /// # Errors
+    /// This function returns any errors raised by the `out` parameter
+    pub fn render_c_with(&self, opts: CCodegenOpts, out: &mut dyn io::Write) -> io::Result<()> {
+        let _pass = enter_pass("render_c");
+
         let (use_w, use_r) = self.0.iter().fold((false, false), |(w, r), val| match val {
-            BfInstruc::Read => (w, true),
+            BfInstruc::Read | BfInstruc::ReadN(_) => (w, true),
             BfInstruc::Write => (true, r),
             _ => (w, r),
         });
 
-        self.write_c_header(out, use_w, use_r, !self.0.is_empty())?;
+        let mut body = CBody::new(&self.0, opts, None);
+        let mut main = vec![];
+        body.write_main(&mut main)?;
 
-        for i in &self.0 {
-            i.write_c_for(&mut out)?;
+        let tape_len = (!self.0.is_empty()).then(|| self.c_tape_len(opts));
+        self.write_c_header(out, use_w, use_r, tape_len, opts, &body.defs)?;
 
-            writeln!(out)?;
-        }
+        out.write_all(&main)?;
 
-        out.write_all(b"}\n")
+        Self::write_c_footer(out, opts.std)
     }
 
-    fn write_bytestring_c(write: &[u8], out: &mut dyn io::Write) -> io::Result<()> {
-        write!(out, "fwrite(\"")?;
+    /// renders this instruction stream back to plain brainfuck, expanding grouped and folded
+    /// instructions into the loops and runs they represent, wrapped at 80 columns
+    ///
+    /// # Errors
+    /// This function returns any errors raised by the `out` parameter, errors on streams
+    /// deduplicated by [`BfInstructionStream::dedup_loops`], and errors if the brainfuck is too
+    /// large to hold in memory
+    pub fn render_bf(&self, out: &mut dyn io::Write) -> io::Result<()> {
+        let _pass = enter_pass("render_bf");
+
+        if self
+            .0
+            .iter()
+            .any(|i| matches!(i, BfInstruc::Call(_) | BfInstruc::Return))
+        {
+            return Err(io::Error::other(
+                "deduplicated blocks have no brainfuck equivalent, render brainfuck from the \
+                 stream before deduplicating it",
+            ));
+        }
+
+        let mut bf = vec![];
+
+        for i in &self.0 {
+            i.push_bf_for(&mut bf)?;
+        }
 
-        for &c in write {
-            write!(out, "{}", byte_to_cstr_literal(c, &mut [0; 4]))?;
+        for line in bf.chunks(80) {
+            out.write_all(line)?;
+            out.write_all(b"\n")?;
         }
 
-        writeln!(out, "\", 1, {}, stdout);", write.len())?;
+        Ok(())
+    }
+
+    fn write_bytestring_c(write: &[u8], out: &mut dyn io::Write) -> io::Result<()> {
+        write_c_fwrite(write, out)?;
+        writeln!(out)?;
 
         writeln!(out, "fflush(stdout);")?;
 
@@ -290,14 +877,35 @@ impl<T: BfOptimizable> BfInstructionStream<T> {
         written: &[u8],
         out: &mut dyn io::Write,
     ) -> io::Result<()> {
+        self.render_interpreted_c_with(CCodegenOpts::default(), state, written, out)
+    }
+
+    /// Writes C to a file, from a partially computed interpreter state, using the given codegen options
+    ///
+    /// # Errors
+    /// Errors on any `io::Errors`
+    pub fn render_interpreted_c_with(
+        &self,
+        opts: CCodegenOpts,
+        state: &BfExecState<T>,
+        written: &[u8],
+        out: &mut dyn io::Write,
+    ) -> io::Result<()> {
+        let _pass = enter_pass("render_interpreted_c");
+
         if let Some(left_off) = state.instruction_pointer {
             let (use_w, use_r) = self.0.iter().fold((false, false), |(w, r), val| match val {
-                BfInstruc::Read => (w, true),
+                BfInstruc::Read | BfInstruc::ReadN(_) => (w, true),
                 BfInstruc::Write => (true, r),
                 _ => (w, r),
             });
 
-            self.write_c_header(out, use_w, use_r, true)?;
+            let mut body = CBody::new(&self.0, opts, (left_off != 0).then_some(left_off));
+            let mut main = vec![];
+            body.write_main(&mut main)?;
+
+            let tape_len = Some(self.c_tape_len(opts));
+            self.write_c_header(out, use_w, use_r, tape_len, opts, &body.defs)?;
 
             if !written.is_empty() {
                 Self::write_bytestring_c(written, out)?;
@@ -305,7 +913,7 @@ impl<T: BfOptimizable> BfInstructionStream<T> {
 
             for (idx, &b) in state.data.iter().enumerate() {
                 if b != T::ZERO {
-                    writeln!(out, "a[{idx}] = {b};")?;
+                    writeln!(out, "a[{idx}] = {b}{};", T::C_LITERAL_SUFFIX)?;
                 }
             }
 
@@ -313,115 +921,1180 @@ impl<T: BfOptimizable> BfInstructionStream<T> {
                 writeln!(out, "a += {};", state.cursor)?;
             }
 
-            if left_off != 0 {
-                writeln!(out, "goto startpos_jump;")?;
-            }
-
-            for (idx, instruc) in self.0.iter().enumerate() {
-                if idx == left_off && left_off != 0 {
-                    writeln!(out, "startpos_jump:")?;
-                }
-
-                instruc.write_c_for(out)?;
-
-                writeln!(out)?;
-            }
+            out.write_all(&main)?;
         } else {
-            self.write_c_header(out, false, false, false)?;
+            self.write_c_header(out, false, false, None, opts, &[])?;
 
             if !written.is_empty() {
                 Self::write_bytestring_c(written, out)?;
             }
         }
 
-        writeln!(out, "}}")?;
-
-        Ok(())
+        Self::write_c_footer(out, opts.std)
     }
-}
 
-pub struct BfInstructionStream<T>(Vec<BfInstruc<T>>, usize);
-
-impl<T: BfOptimizable> BfInstructionStream<T> {
-    /// Returns a brainfuck stream fully optimized and run ready from brainfuck text
+    /// Compiles brainfuck read from `source` straight to unoptimized C, holding only a run of
+    /// identical instructions in memory at a time. `scan` must come from [`scan_source`] over the
+    /// same source, which gives the header what it needs before the body is written.
+    ///
+    /// Codegen options other than the C standard do not apply to streamed output.
     ///
     /// # Errors
-    /// This function will error if while compiling the loop instructions are malformed by having a mismatched count or by having a loop end instruction without a start instruction
-    pub fn optimized_from_text(
-        v: impl Iterator<Item = u8>,
+    /// Errors on any `io::Errors`, or if loops in `source` do not match what `scan` found
+    pub fn render_c_streaming(
+        source: impl io::Read,
+        scan: &SourceScan,
         array_len: Option<u32>,
-    ) -> Result<Self, BfCompError> {
-        let mut new = Self(Self::bf_to_stream(v), 0);
-
-        let array_len: u32 = array_len.unwrap_or_else(|| {
-            new.iter()
-                .fold(0, |accu, x| {
-                    if let BfInstruc::IncPtr = x {
-                        accu + 1
-                    } else {
-                        accu
-                    }
-                })
-                .max(30_000)
-        });
-
-        new.1 = array_len.into_usize();
-
-        if new.len() > (isize::MAX as usize) {
-            return Err(BfCompError::Overflow);
-        }
+        opts: CCodegenOpts,
+        out: &mut dyn io::Write,
+    ) -> Result<(), StreamCompileError> {
+        let _pass = enter_pass("render_c_streaming");
 
-        // run optimization passes
-        new.group_common_bf();
-        new.static_optimize();
-        new.insert_bf_jump_points()?;
+        scan.loops_balanced?;
 
-        Ok(new)
-    }
+        let array_len = match array_len {
+            Some(len) => len,
+            None => {
+                u32::try_from(scan.ptr_increments.max(30_000)).map_err(|_| BfCompError::Overflow)?
+            }
+        };
 
-    /// returns a statically guessed array size that would work best for this brainfuck stream
-    #[must_use]
-    pub fn reccomended_array_size(&self) -> usize {
-        self.1
-    }
+        Self(vec![], array_len.into_usize(), None).write_c_header(
+            out,
+            scan.uses_write,
+            scan.uses_read,
+            Some(array_len.into_usize()),
+            opts,
+            &[],
+        )?;
 
-    // without this inline attr it fails to inline this function into the mainloop, preventing a considerable speedup
-    #[inline]
-    fn group_common_bf(&mut self) {
-        let stream = &mut self.0;
+        let mut depth = 0u64;
+        let mut run: Option<(u8, u64)> = None;
 
-        let mut newlen = 0usize;
+        for byte in io::BufReader::new(source).bytes() {
+            let byte = byte?;
 
-        let mut i = 0usize;
-        while i < stream.len() {
-            if stream[i].is_multi_optimizable() {
-                let mut ctr = 1;
+            if !is_op(byte) {
+                continue;
+            }
 
-                while (i + 1 < stream.len()) && (stream[i] == stream[i + 1]) {
-                    i += 1;
-                    ctr += 1;
+            match byte {
+                b'[' => depth += 1,
+                b']' => {
+                    depth = depth
+                        .checked_sub(1)
+                        .ok_or(BfCompError::LoopEndBeforeLoopStart)?;
                 }
+                _ => {}
+            }
 
-                if ctr == 1 {
-                    stream[newlen] = stream[i];
-                } else {
-                    stream[newlen] = stream[i].as_multi_with(ctr).unwrap();
+            run = match run {
+                Some((op, count)) if op == byte && b"+-<>".contains(&byte) => Some((op, count + 1)),
+                Some((op, count)) => {
+                    Self::write_c_run(op, count, out)?;
+                    Some((byte, 1))
                 }
-            } else {
-                stream[newlen] = stream[i];
-            }
+                None => Some((byte, 1)),
+            };
+        }
 
-            newlen += 1;
-            i += 1;
+        if let Some((op, count)) = run {
+            Self::write_c_run(op, count, out)?;
+        }
+
+        // the source changed between passes
+        if depth != 0 {
+            return Err(BfCompError::LoopCountMismatch.into());
         }
 
-        stream.truncate(newlen);
+        Self::write_c_footer(out, opts.std)?;
+
+        Ok(())
     }
-}
+
+    /// writes `count` repetitions of the brainfuck instruction `op` as C
+    fn write_c_run(op: u8, count: u64, out: &mut dyn io::Write) -> io::Result<()> {
+        use BfInstruc::*;
+
+        let mut instrucs = vec![];
+
+        match op {
+            b'+' | b'-' => {
+                // cell arithmetic wraps, so only the remainder modulo the cell size matters
+                let amount = 1u64.checked_shl(T::BITS).map_or(count, |size| count % size);
+
+                if amount == 1 {
+                    instrucs.push(if op == b'+' { Inc } else { Dec });
+                } else if amount != 0 {
+                    let amount = T::try_from(amount).unwrap_or_else(|_| {
+                        unreachable!("remainder modulo the cell size fits in a cell")
+                    });
+
+                    instrucs.push(if op == b'+' {
+                        IncBy(amount)
+                    } else {
+                        DecBy(amount)
+                    });
+                }
+            }
+            b'>' | b'<' => {
+                let mut left = count;
+
+                while left > 0 {
+                    let step = u32::try_from(left).unwrap_or(u32::MAX);
+                    left -= u64::from(step);
+
+                    instrucs.push(match (op, NonZeroU32::new(step)) {
+                        (b'>', _) if step == 1 => IncPtr,
+                        (b'<', _) if step == 1 => DecPtr,
+                        (b'>', Some(step)) => IncPtrBy(step),
+                        (_, Some(step)) => DecPtrBy(step),
+                        (_, None) => unreachable!("left is nonzero"),
+                    });
+                }
+            }
+            _ => {
+                for _ in 0..count {
+                    instrucs.push(BfInstruc::try_from(op).expect("op is a brainfuck instruction"));
+                }
+            }
+        }
+
+        for instruc in instrucs {
+            instruc.write_c_for(out)?;
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// What generated C needs to know about brainfuck source before its body is written, gathered by
+/// [`scan_source`] without holding the source in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceScan {
+    /// number of `>` instructions, used for the default tape size
+    pub ptr_increments: u64,
+    pub uses_write: bool,
+    pub uses_read: bool,
+    /// whether every loop in the source is closed, and opened before it is closed
+    pub loops_balanced: Result<(), BfCompError>,
+}
+
+/// An error raised while compiling source that is streamed rather than held in memory
+#[derive(Debug, Error)]
+pub enum StreamCompileError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Compile(#[from] BfCompError),
+}
+
+/// Scans brainfuck source in a single streaming pass, for use with
+/// [`BfInstructionStream::render_c_streaming`]
+///
+/// # Errors
+/// Errors on any `io::Errors` raised by `source`
+pub fn scan_source(source: impl io::Read) -> io::Result<SourceScan> {
+    let mut scan = SourceScan {
+        ptr_increments: 0,
+        uses_write: false,
+        uses_read: false,
+        loops_balanced: Ok(()),
+    };
+
+    let mut depth = 0u64;
+
+    for byte in io::BufReader::new(source).bytes() {
+        match byte? {
+            b'>' => scan.ptr_increments += 1,
+            b'.' => scan.uses_write = true,
+            b',' => scan.uses_read = true,
+            b'[' => depth += 1,
+            b']' => match depth.checked_sub(1) {
+                Some(v) => depth = v,
+                None if scan.loops_balanced.is_ok() => {
+                    scan.loops_balanced = Err(BfCompError::LoopEndBeforeLoopStart);
+                }
+                None => {}
+            },
+            _ => {}
+        }
+    }
+
+    if depth != 0 && scan.loops_balanced.is_ok() {
+        scan.loops_balanced = Err(BfCompError::LoopCountMismatch);
+    }
+
+    Ok(scan)
+}
+
+pub struct BfInstructionStream<T>(Vec<BfInstruc<T>>, usize, Option<Vec<Range<usize>>>);
+
+/// Options controlling the shape of generated C
+#[derive(Clone, Copy, Debug)]
+pub struct CCodegenOpts {
+    /// outline runs of more than this many statements into their own functions, so that C compilers
+    /// do not degrade on a single enormous `main`. `None` emits everything inline
+    pub split_threshold: Option<NonZeroUsize>,
+    /// emit the program as `bf_resume(tape, ip)`, which can enter the program at any instruction
+    /// index through a switch over labels. Splitting is not applied in this mode
+    pub resumable: bool,
+    /// the C standard the output must conform to
+    pub std: CStd,
+    /// what reads store once input has run out
+    pub eof: Eof,
+}
+
+/// What a read stores in its cell once input has run out
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Eof {
+    #[default]
+    Zero,
+    /// -1, every bit of the cell set
+    MinusOne,
+    /// the cell keeps the value it had
+    Unchanged,
+}
+
+/// A C language standard that generated C can target
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CStd {
+    /// ANSI C, which has no `restrict` and no implicit return from `main`
+    C89,
+    #[default]
+    C99,
+    C11,
+    C23,
+}
+
+impl CStd {
+    /// the `restrict` qualifier followed by a space, or nothing if the standard lacks it
+    fn restrict(self) -> &'static str {
+        match self {
+            CStd::C89 => "",
+            CStd::C99 | CStd::C11 | CStd::C23 => "restrict ",
+        }
+    }
+}
+
+impl Default for CCodegenOpts {
+    fn default() -> Self {
+        Self {
+            split_threshold: NonZeroUsize::new(50_000),
+            resumable: false,
+            std: CStd::default(),
+            eof: Eof::default(),
+        }
+    }
+}
+
+/// Builds the body of a generated C program, outlining large regions into helper functions
+struct CBody<'a, T> {
+    instrucs: &'a [BfInstruc<T>],
+    opts: CCodegenOpts,
+    /// instruction index that `main` must be able to jump to, which can not be outlined
+    resume: Option<usize>,
+    /// definitions of outlined helper functions, emitted before `main`
+    defs: Vec<u8>,
+    next_fn: usize,
+}
+
+impl<'a, T: BfOptimizable> CBody<'a, T> {
+    fn new(instrucs: &'a [BfInstruc<T>], opts: CCodegenOpts, resume: Option<usize>) -> Self {
+        Self {
+            instrucs,
+            opts,
+            resume,
+            defs: vec![],
+            next_fn: 0,
+        }
+    }
+
+    /// returns the end of the item starting at `idx`, a single instruction or a whole loop
+    fn item_end(&self, idx: usize) -> usize {
+        match self.instrucs[idx] {
+            BfInstruc::LStart(end) => end.into_usize() + 1,
+            _ => idx + 1,
+        }
+    }
+
+    /// returns true if `idx` starts a loop whose body ends by zeroing the cell the loop tests, so
+    /// the loop can only ever run once and is written as an `if`
+    fn runs_at_most_once(&self, idx: usize) -> bool {
+        match self.instrucs[idx] {
+            BfInstruc::LStart(end) => {
+                matches!(self.instrucs[end.into_usize() - 1], BfInstruc::Zero)
+            }
+            _ => false,
+        }
+    }
+
+    fn write_plain(&self, range: Range<usize>, out: &mut Vec<u8>) -> io::Result<()> {
+        let mut idx = range.start;
+
+        while idx < range.end {
+            if Some(idx) == self.resume {
+                writeln!(out, "startpos_jump:")?;
+            }
+
+            if let BfInstruc::WriteConst(_) = self.instrucs[idx] {
+                // a long constant write is split over several instructions, join them back into
+                // one fwrite unless execution resumes partway through
+                let mut bytes = vec![];
+
+                while let Some(BfInstruc::WriteConst(b)) =
+                    self.instrucs[..range.end].get(idx).copied()
+                {
+                    if !bytes.is_empty() && Some(idx) == self.resume {
+                        break;
+                    }
+
+                    bytes.extend_from_slice(b.as_bytes());
+                    idx += 1;
+                }
+
+                write_c_fwrite(&bytes, out)?;
+            } else if self.runs_at_most_once(idx) {
+                write!(out, "if (*a != 0) {{")?;
+                idx += 1;
+            } else {
+                self.instrucs[idx].write_c_for(out)?;
+                idx += 1;
+            }
+
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
+    /// writes the whole program, leaving the code that belongs in `main` in `out`
+    fn write_main(&mut self, out: &mut Vec<u8>) -> io::Result<()> {
+        if self.opts.resumable {
+            let entry = self.resume.take().unwrap_or(0);
+            self.write_resumable()?;
+
+            return writeln!(out, "a = bf_resume(a, {entry});");
+        }
+
+        if self.resume.is_some() {
+            writeln!(out, "goto startpos_jump;")?;
+        }
+
+        self.write_range(0..self.instrucs.len(), out)
+    }
+
+    /// defines `bf_resume`, with a label before every instruction and a switch dispatching to them
+    fn write_resumable(&mut self) -> io::Result<()> {
+        let len = self.instrucs.len();
+        let out = &mut self.defs;
+
+        writeln!(
+            out,
+            "{0}* bf_resume({0}* {1}a, unsigned long ip) {{",
+            T::C_INT_NAME,
+            self.opts.std.restrict()
+        )?;
+
+        writeln!(out, "switch (ip) {{")?;
+        for idx in 0..=len {
+            writeln!(out, "case {idx}: goto l{idx};")?;
+        }
+        writeln!(out, "default: return a;\n}}")?;
+
+        for (idx, instruc) in self.instrucs.iter().enumerate() {
+            // the empty statement keeps the label valid when it comes before a closing brace
+            writeln!(out, "l{idx}: ;")?;
+            instruc.write_c_for(out)?;
+            writeln!(out)?;
+        }
+
+        writeln!(out, "l{len}:\nreturn a;\n}}")
+    }
+
+    /// writes the instructions in `range`, which must start and end at the same loop depth
+    fn write_range(&mut self, range: Range<usize>, out: &mut Vec<u8>) -> io::Result<()> {
+        let Some(threshold) = self.opts.split_threshold.map(NonZeroUsize::get) else {
+            return self.write_plain(range, out);
+        };
+
+        if range.len() <= threshold && !range.contains(&self.resume.unwrap_or(usize::MAX)) {
+            return self.write_plain(range, out);
+        }
+
+        let mut idx = range.start;
+        let mut chunk_start = idx;
+
+        while idx < range.end {
+            let end = self.item_end(idx);
+
+            let inline =
+                end - idx > threshold || (idx..end).contains(&self.resume.unwrap_or(usize::MAX));
+
+            if inline || end - chunk_start > threshold {
+                self.outline(chunk_start..idx, out)?;
+                chunk_start = idx;
+            }
+
+            if inline {
+                if end - idx == 1 {
+                    self.write_plain(idx..end, out)?;
+                } else {
+                    // loops that can not be outlined are written inline, with their bodies split
+                    self.write_plain(idx..idx + 1, out)?;
+                    self.write_range(idx + 1..end - 1, out)?;
+                    self.write_plain(end - 1..end, out)?;
+                }
+
+                chunk_start = end;
+            }
+
+            idx = end;
+        }
+
+        self.outline(chunk_start..range.end, out)
+    }
+
+    /// moves the instructions in `range` into a new function, and writes a call to it to `out`
+    fn outline(&mut self, range: Range<usize>, out: &mut Vec<u8>) -> io::Result<()> {
+        if range.is_empty() {
+            return Ok(());
+        }
+
+        let id = self.next_fn;
+        self.next_fn += 1;
+
+        let mut def = vec![];
+        writeln!(
+            def,
+            "static {0}* bf_{id}({0}* {1}a) {{",
+            T::C_INT_NAME,
+            self.opts.std.restrict()
+        )?;
+        self.write_plain(range, &mut def)?;
+        writeln!(def, "return a;\n}}")?;
+
+        self.defs.extend(def);
+
+        writeln!(out, "a = bf_{id}(a);")
+    }
+}
+
+/// records that the instruction at `to` was built from the source of instructions `first..=last`
+fn carry_span(spans: &mut Option<Vec<Range<usize>>>, to: usize, first: usize, last: usize) {
+    if let Some(spans) = spans {
+        spans[to] = spans[first].start..spans[last].end;
+    }
+}
+
+impl<T: BfOptimizable> BfInstructionStream<T> {
+    /// Returns a brainfuck stream fully optimized and run ready from brainfuck text
+    ///
+    /// # Errors
+    /// This function will error if while compiling the loop instructions are malformed by having a mismatched count or by having a loop end instruction without a start instruction
+    pub fn optimized_from_text(
+        v: impl Iterator<Item = u8>,
+        array_len: Option<u32>,
+    ) -> Result<Self, BfCompError> {
+        let _pass = enter_pass("bf_to_stream");
+        Self::optimize(Self(Self::bf_to_stream(v), 0, None), array_len)
+    }
+
+    /// Returns a brainfuck stream like [`BfInstructionStream::optimized_from_text`], that additionally
+    /// tracks what range of the source text each instruction was built from, see [`BfInstructionStream::spans`]
+    ///
+    /// # Errors
+    /// This function will error if while compiling the loop instructions are malformed by having a mismatched count or by having a loop end instruction without a start instruction
+    pub fn optimized_from_text_spanned(
+        v: &[u8],
+        array_len: Option<u32>,
+    ) -> Result<Self, BfCompError> {
+        Self::optimized_from_text_extended(v, array_len, Extensions::default())
+    }
+
+    /// Returns a spanned brainfuck stream like [`BfInstructionStream::optimized_from_text_spanned`],
+    /// that also parses the instructions of the enabled `extensions`
+    ///
+    /// # Errors
+    /// This function will error if while compiling the loop instructions are malformed by having a mismatched count or by having a loop end instruction without a start instruction
+    pub fn optimized_from_text_extended(
+        v: &[u8],
+        array_len: Option<u32>,
+        extensions: Extensions,
+    ) -> Result<Self, BfCompError> {
+        let _pass = enter_pass("bf_to_stream");
+        let (stream, spans) = Tokenizer::new(v.iter().copied())
+            .with_extensions(extensions)
+            .filter_map(|token| Some((BfInstruc::from_token(&token)?, token.span)))
+            .unzip();
+
+        Self::optimize(Self(stream, 0, Some(spans)), array_len)
+    }
+
+    /// Returns a spanned stream like [`BfInstructionStream::optimized_from_text_spanned`], except
+    /// that writes are never folded into constant writes. Every output byte is then written by an
+    /// instruction built from the `.` that wrote it, and at the moment the source would write it,
+    /// which traces and replays of a run need.
+    ///
+    /// # Errors
+    /// This function will error if while compiling the loop instructions are malformed by having a mismatched count or by having a loop end instruction without a start instruction
+    pub fn traceable_from_text(v: &[u8], array_len: Option<u32>) -> Result<Self, BfCompError> {
+        let _pass = enter_pass("bf_to_stream");
+        let (stream, spans) = Tokenizer::new(v.iter().copied())
+            .filter_map(|token| Some((BfInstruc::from_token(&token)?, token.span)))
+            .unzip();
+
+        Self::optimize_with(Self(stream, 0, Some(spans)), array_len, false)
+    }
+
+    /// Returns a run ready stream from already built instructions, recomputing every jump point
+    /// so that loop targets supplied by the caller are never trusted, and sizing the tape like
+    /// [`BfInstructionStream::optimized_from_text`] does. No optimization passes are run.
+    ///
+    /// # Errors
+    /// This function will error if the loop instructions are malformed by having a mismatched count or by having a loop end instruction without a start instruction
+    pub fn try_from_instructions(
+        stream: Vec<BfInstruc<T>>,
+        array_len: Option<u32>,
+    ) -> Result<Self, BfCompError> {
+        let mut new = Self(stream, 0, None);
+
+        new.1 = array_len
+            .unwrap_or_else(|| new.default_array_len())
+            .into_usize();
+
+        if new.len() > (isize::MAX as usize) {
+            return Err(BfCompError::Overflow);
+        }
+
+        new.insert_bf_jump_points()?;
+
+        Ok(new)
+    }
+
+    /// the tape length used when none is given, at least 30k cells and at least as many as the
+    /// program moves right in total
+    fn default_array_len(&self) -> u32 {
+        self.iter()
+            .fold(0u32, |accu, x| match x {
+                BfInstruc::IncPtr => accu.saturating_add(1),
+                BfInstruc::IncPtrBy(v) | BfInstruc::ReadN(v) => accu.saturating_add(v.get()),
+                _ => accu,
+            })
+            .max(30_000)
+    }
+
+    fn optimize(new: Self, array_len: Option<u32>) -> Result<Self, BfCompError> {
+        Self::optimize_with(new, array_len, true)
+    }
+
+    fn optimize_with(
+        mut new: Self,
+        array_len: Option<u32>,
+        fold_writes: bool,
+    ) -> Result<Self, BfCompError> {
+        new.1 = array_len
+            .unwrap_or_else(|| new.default_array_len())
+            .into_usize();
+
+        if new.len() > (isize::MAX as usize) {
+            return Err(BfCompError::Overflow);
+        }
+
+        // run optimization passes
+        in_pass("group_common_bf", || new.group_common_bf());
+        in_pass("static_optimize", || new.static_optimize());
+        in_pass("fold_zero_set", || new.fold_zero_set());
+        in_pass("eliminate_dead_stores", || new.eliminate_dead_stores());
+        in_pass("fold_bulk_reads", || new.fold_bulk_reads());
+        if fold_writes {
+            in_pass("fold_const_writes", || new.fold_const_writes());
+        }
+        in_pass("insert_bf_jump_points", || new.insert_bf_jump_points())?;
+
+        Ok(new)
+    }
+
+    /// returns a statically guessed array size that would work best for this brainfuck stream
+    #[must_use]
+    pub fn reccomended_array_size(&self) -> usize {
+        self.1
+    }
+
+    /// returns the byte range of source text each instruction was built from, if this stream was
+    /// built by [`BfInstructionStream::optimized_from_text_spanned`]
+    #[must_use]
+    pub fn spans(&self) -> Option<&[Range<usize>]> {
+        self.2.as_deref()
+    }
+
+    // without this inline attr it fails to inline this function into the mainloop, preventing a considerable speedup
+    #[inline]
+    fn group_common_bf(&mut self) {
+        let stream = &mut self.0;
+        let spans = &mut self.2;
+
+        let mut newlen = 0usize;
+
+        let mut i = 0usize;
+        while i < stream.len() {
+            let start = i;
+
+            if stream[i].is_multi_optimizable() {
+                let mut ctr = 1;
+
+                while (i + 1 < stream.len()) && (stream[i] == stream[i + 1]) {
+                    i += 1;
+                    ctr += 1;
+                }
+
+                if ctr == 1 {
+                    stream[newlen] = stream[i];
+                } else if take_fuel() {
+                    stream[newlen] = stream[i].as_multi_with(ctr).unwrap();
+                } else {
+                    // out of fuel, keep the run as it was
+                    for j in start..i {
+                        stream[newlen] = stream[j];
+                        carry_span(spans, newlen, j, j);
+                        newlen += 1;
+                    }
+
+                    stream[newlen] = stream[i];
+                    carry_span(spans, newlen, i, i);
+                    newlen += 1;
+                    i += 1;
+                    continue;
+                }
+            } else {
+                stream[newlen] = stream[i];
+            }
+
+            carry_span(spans, newlen, start, i);
+
+            newlen += 1;
+            i += 1;
+        }
+
+        self.truncate(newlen);
+    }
+
+    /// folds a `Zero` or `Set` followed by any run of constant adds, subtracts, zeroes and sets of
+    /// the same cell into a single `Set`, or `Zero` when the cell ends up zero
+    fn fold_zero_set(&mut self) {
+        use BfInstruc::*;
+
+        let stream = &mut self.0;
+        let spans = &mut self.2;
+
+        let mut newlen = 0usize;
+
+        for i in 0..stream.len() {
+            let known = match newlen.checked_sub(1).map(|last| stream[last]) {
+                Some(Zero) => Some(T::ZERO),
+                Some(Set(v)) => Some(v),
+                _ => None,
+            };
+
+            let folded = known.and_then(|v| match stream[i] {
+                Inc => Some(v.wrapping_add(T::from(1))),
+                Dec => Some(v.wrapping_sub(T::from(1))),
+                IncBy(by) => Some(v.wrapping_add(by)),
+                DecBy(by) => Some(v.wrapping_sub(by)),
+                Zero => Some(T::ZERO),
+                Set(to) => Some(to),
+                _ => None,
+            });
+
+            if let Some(value) = folded.filter(|_| take_fuel()) {
+                let last = newlen - 1;
+
+                stream[last] = if value == T::ZERO { Zero } else { Set(value) };
+                carry_span(spans, last, last, i);
+            } else {
+                stream[newlen] = stream[i];
+                carry_span(spans, newlen, i, i);
+                newlen += 1;
+            }
+        }
+
+        self.truncate(newlen);
+    }
+
+    /// removes stores to a cell that a later `Zero` or `Set` overwrites before anything reads it.
+    ///
+    /// Stores are only tracked within loop free spans between reads, and any write or pointer move
+    /// to a cell not yet visited in that span keeps every store before it, so a program that fails
+    /// part way through leaves the same tape and output as it would have without this pass.
+    fn eliminate_dead_stores(&mut self) {
+        use BfInstruc::*;
+
+        // offsets this far apart may be the same cell on a tape whose ends wrap
+        let tape_len = i64::try_from(self.1).unwrap_or(i64::MAX);
+        let stream = &mut self.0;
+        let spans = &mut self.2;
+
+        let mut dead = vec![false; stream.len()];
+        // stores to each cell that nothing has read since, keyed by offset like fold_const_writes
+        let mut pending = BTreeMap::<i64, Vec<usize>>::new();
+        let (mut off, mut lo, mut hi) = (0i64, 0i64, 0i64);
+
+        for (idx, &instruc) in stream.iter().enumerate() {
+            let moved = match instruc {
+                IncPtr => 1,
+                DecPtr => -1,
+                IncPtrBy(v) => i64::from(v.get()),
+                DecPtrBy(v) => -i64::from(v.get()),
+                _ => 0,
+            };
+
+            if moved != 0 {
+                off += moved;
+
+                if off < lo || off > hi {
+                    pending.clear();
+                    lo = lo.min(off);
+                    hi = hi.max(off);
+
+                    if hi - lo >= tape_len {
+                        (off, lo, hi) = (0, 0, 0);
+                    }
+                }
+
+                continue;
+            }
+
+            match instruc {
+                Zero | Set(_) => {
+                    let stores = pending.entry(off).or_default();
+
+                    for &store in stores.iter() {
+                        if take_fuel() {
+                            dead[store] = true;
+                        }
+                    }
+
+                    stores.clear();
+                    stores.push(idx);
+                }
+                // adding reads the cell, but only to store it back, so it dies with the cell
+                Inc | Dec | IncBy(_) | DecBy(_) => pending.entry(off).or_default().push(idx),
+                Write | WriteConst(_) => pending.clear(),
+                _ => {
+                    pending.clear();
+                    (off, lo, hi) = (0, 0, 0);
+                }
+            }
+        }
+
+        let mut newlen = 0usize;
+
+        for i in 0..stream.len() {
+            if !dead[i] {
+                stream[newlen] = stream[i];
+                carry_span(spans, newlen, i, i);
+                newlen += 1;
+            }
+        }
+
+        self.truncate(newlen);
+    }
+
+    /// folds runs of two or more `Read, IncPtr` pairs into a single `ReadN`
+    fn fold_bulk_reads(&mut self) {
+        use BfInstruc::*;
+
+        let stream = &mut self.0;
+        let spans = &mut self.2;
+
+        let is_pair = |stream: &[BfInstruc<T>], i: usize| {
+            matches!(stream.get(i..i + 2), Some([Read, IncPtr]))
+        };
+
+        let mut newlen = 0usize;
+
+        let mut i = 0usize;
+        while i < stream.len() {
+            let mut pairs = 0u32;
+
+            while pairs < u32::MAX && is_pair(stream, i + 2 * pairs.into_usize()) {
+                pairs += 1;
+            }
+
+            if pairs >= 2 && take_fuel() {
+                let len = 2 * pairs.into_usize();
+
+                stream[newlen] = ReadN(NonZeroU32::new(pairs).unwrap());
+                carry_span(spans, newlen, i, i + len - 1);
+                i += len;
+            } else {
+                stream[newlen] = stream[i];
+                carry_span(spans, newlen, i, i);
+                i += 1;
+            }
+
+            newlen += 1;
+        }
+
+        self.truncate(newlen);
+    }
+
+    /// folds runs of two or more writes whose values are known at compile time into `WriteConst`
+    /// instructions placed where the last write of the run was.
+    ///
+    /// Values are only tracked from the last loop boundary or read, and a run ends at any pointer
+    /// move to a cell not yet visited in that span, so nothing between the writes of a run can
+    /// fail and lose output that would otherwise have been written.
+    fn fold_const_writes(&mut self) {
+        use BfInstruc::*;
+
+        struct Run {
+            writes: Vec<usize>,
+            bytes: Vec<u8>,
+        }
+
+        // offsets this far apart may be the same cell on a tape whose ends wrap
+        let tape_len = i64::try_from(self.1).unwrap_or(i64::MAX);
+        let stream = &mut self.0;
+        let spans = &mut self.2;
+
+        // indexes of writes folded into a later one
+        let mut dropped = vec![false; stream.len()];
+        // last write of each run, to its bytes and the start of its first writes span
+        let mut folds = BTreeMap::<usize, (Vec<u8>, Option<usize>)>::new();
+
+        let mut end_run = |run: &mut Run| {
+            if let [first, .., last] = run.writes[..] {
+                if take_fuel() {
+                    for &w in &run.writes[..run.writes.len() - 1] {
+                        dropped[w] = true;
+                    }
+
+                    let start = spans.as_ref().map(|s| s[first].start);
+                    folds.insert(last, (std::mem::take(&mut run.bytes), start));
+                }
+            }
+
+            run.writes.clear();
+            run.bytes.clear();
+        };
+
+        let mut run = Run {
+            writes: vec![],
+            bytes: vec![],
+        };
+        // cells are keyed by offset from where tracking last restarted
+        let mut known = BTreeMap::<i64, T>::new();
+        let (mut off, mut lo, mut hi) = (0i64, 0i64, 0i64);
+
+        for (idx, &instruc) in stream.iter().enumerate() {
+            let moved = match instruc {
+                IncPtr => 1,
+                DecPtr => -1,
+                IncPtrBy(v) => i64::from(v.get()),
+                DecPtrBy(v) => -i64::from(v.get()),
+                _ => 0,
+            };
+
+            if moved != 0 {
+                off += moved;
+
+                if off < lo || off > hi {
+                    end_run(&mut run);
+                    lo = lo.min(off);
+                    hi = hi.max(off);
+
+                    if hi - lo >= tape_len {
+                        known.clear();
+                        (off, lo, hi) = (0, 0, 0);
+                    }
+                }
+
+                continue;
+            }
+
+            match instruc {
+                Zero => _ = known.insert(off, T::ZERO),
+                Set(v) => _ = known.insert(off, v),
+                Inc | Dec | IncBy(_) | DecBy(_) => {
+                    if let Some(cell) = known.get_mut(&off) {
+                        *cell = match instruc {
+                            Inc => cell.wrapping_add(T::from(1)),
+                            Dec => cell.wrapping_sub(T::from(1)),
+                            IncBy(v) => cell.wrapping_add(v),
+                            DecBy(v) => cell.wrapping_sub(v),
+                            _ => unreachable!(),
+                        };
+                    }
+                }
+                Write => match known.get(&off).map(|&v| u8::try_from(v.into())) {
+                    // values that do not fit in a byte can not be restored by decompiled output
+                    Some(Ok(b)) => {
+                        run.writes.push(idx);
+                        run.bytes.push(b);
+                    }
+                    _ => end_run(&mut run),
+                },
+                _ => {
+                    end_run(&mut run);
+                    known.clear();
+                    (off, lo, hi) = (0, 0, 0);
+                }
+            }
+        }
+
+        end_run(&mut run);
+
+        let mut newlen = 0usize;
+
+        // every write folded away makes room for a chunk, so this never overtakes the read index
+        for i in 0..stream.len() {
+            if dropped[i] {
+                continue;
+            }
+
+            let Some((bytes, start)) = folds.remove(&i) else {
+                stream[newlen] = stream[i];
+                carry_span(spans, newlen, i, i);
+                newlen += 1;
+                continue;
+            };
+
+            let span = spans.as_ref().map(|s| start.unwrap_or(0)..s[i].end);
+
+            for chunk in bytes.chunks(ConstBytes::CAPACITY) {
+                stream[newlen] = WriteConst(ConstBytes::new(chunk));
+                if let (Some(spans), Some(span)) = (spans.as_mut(), &span) {
+                    spans[newlen] = span.clone();
+                }
+                newlen += 1;
+            }
+        }
+
+        self.truncate(newlen);
+    }
+
+    /// Loops shorter than this are never moved into blocks by
+    /// [`BfInstructionStream::dedup_loops`], as calling them would save little
+    pub const DEDUP_MIN_LEN: usize = 16;
+
+    /// Moves loops that appear more than once, and are at least [`Self::DEDUP_MIN_LEN`]
+    /// instructions long, into blocks after the end of the program, replacing every copy with a
+    /// [`BfInstruc::Call`] to its block. Large generated programs that repeat the same code
+    /// shrink, but every call and return runs as an instruction of its own, so instruction limits
+    /// and the clock extension count two more for every block run. Streams that already have
+    /// blocks are left as they are.
+    ///
+    /// The result can only be run by the interpreters, rendering it as C or brainfuck errors.
+    ///
+    /// # Errors
+    /// This function will error if the rearranged stream no longer fits jump indexes
+    pub fn dedup_loops(&mut self) -> Result<(), BfCompError> {
+        use std::collections::HashMap;
+        use BfInstruc::*;
+
+        let _pass = enter_pass("dedup_loops");
+
+        let stream = &self.0;
+        let spans = &self.2;
+
+        if stream.iter().any(|i| matches!(i, Call(_) | Return)) {
+            return Ok(());
+        }
+
+        let mut out = Vec::with_capacity(stream.len());
+        let mut out_spans = spans.as_ref().map(|_| Vec::with_capacity(stream.len()));
+
+        let copy = |out: &mut Vec<_>, out_spans: &mut Option<Vec<_>>, from: Range<usize>| {
+            out.extend_from_slice(&stream[from.clone()]);
+            if let (Some(out_spans), Some(spans)) = (out_spans, spans) {
+                out_spans.extend_from_slice(&spans[from]);
+            }
+        };
+
+        // the source each block is copied from, in the order they are first called
+        let mut blocks = vec![];
+        let mut block_of = HashMap::new();
+        let mut calls = vec![];
+        let mut next = 0;
+
+        for (r, first) in repeated_loops(stream) {
+            if !take_fuel() {
+                continue;
+            }
+
+            copy(&mut out, &mut out_spans, next..r.start);
+
+            let block = *block_of.entry(first).or_insert_with(|| {
+                blocks.push(r.clone());
+                blocks.len() - 1
+            });
+
+            calls.push((out.len(), block));
+            out.push(Call(0));
+            if let (Some(out_spans), Some(spans)) = (&mut out_spans, spans) {
+                out_spans.push(spans[r.start].start..spans[r.end - 1].end);
+            }
+
+            next = r.end;
+        }
+
+        if calls.is_empty() {
+            return Ok(());
+        }
+
+        copy(&mut out, &mut out_spans, next..stream.len());
+
+        // the program ends at its own return, before reaching the blocks
+        out.push(Return);
+        if let (Some(out_spans), Some(spans)) = (&mut out_spans, spans) {
+            let end = spans.last().map_or(0, |s| s.end);
+            out_spans.push(end..end);
+        }
+
+        let mut starts = vec![];
+
+        for block in blocks {
+            starts.push(u32::try_from(out.len()).map_err(|_| BfCompError::Overflow)?);
+
+            copy(&mut out, &mut out_spans, block.clone());
+
+            out.push(Return);
+            if let (Some(out_spans), Some(spans)) = (&mut out_spans, spans) {
+                out_spans.push(spans[block.end - 1].clone());
+            }
+        }
+
+        for (at, block) in calls {
+            out[at] = Call(starts[block]);
+        }
+
+        self.0 = out;
+        self.2 = out_spans;
+
+        self.insert_bf_jump_points()
+    }
+}
+
+/// Finds the outermost loops of at least [`BfInstructionStream::DEDUP_MIN_LEN`] instructions that
+/// have the same shape as another outermost loop, returning each with where the first loop of its
+/// shape starts
+fn repeated_loops<T: BfOptimizable>(stream: &[BfInstruc<T>]) -> Vec<(Range<usize>, usize)> {
+    use std::collections::HashMap;
+
+    // the hash of every prefix of the stream, so that any loop hashes in constant time
+    const BASE: u64 = 0x100_0000_01b3;
+
+    let mut prefix = Vec::with_capacity(stream.len() + 1);
+    let mut powers = Vec::with_capacity(stream.len() + 1);
+    let (mut hash, mut power) = (0u64, 1u64);
+
+    for instruc in stream {
+        prefix.push(hash);
+        powers.push(power);
+        hash = hash.wrapping_mul(BASE).wrapping_add(shape_hash(instruc));
+        power = power.wrapping_mul(BASE);
+    }
+    prefix.push(hash);
+    powers.push(power);
+
+    // loops are keyed by their hash and length, and compared before being called the same
+    let key = |r: &Range<usize>| {
+        let hash = prefix[r.end].wrapping_sub(prefix[r.start].wrapping_mul(powers[r.len()]));
+        (hash, r.len())
+    };
+
+    let loop_at = |i: usize| match stream[i] {
+        BfInstruc::LStart(end) => Some(i..end.into_usize() + 1)
+            .filter(|r| r.len() >= BfInstructionStream::<T>::DEDUP_MIN_LEN),
+        _ => None,
+    };
+
+    let mut counts = HashMap::<(u64, usize), u32>::new();
+
+    for r in (0..stream.len()).filter_map(loop_at) {
+        *counts.entry(key(&r)).or_default() += 1;
+    }
+
+    // the first outermost loop of each shape, and how many outermost loops share it
+    let mut firsts = HashMap::<(u64, usize), (usize, u32)>::new();
+    let mut copies = vec![];
+
+    let mut i = 0;
+    while i < stream.len() {
+        let Some(r) = loop_at(i).filter(|r| counts[&key(r)] > 1) else {
+            i += 1;
+            continue;
+        };
+
+        let (first, same) = firsts.entry(key(&r)).or_insert((r.start, 0));
+
+        if same_shape(&stream[*first..*first + r.len()], &stream[r.clone()]) {
+            *same += 1;
+            copies.push((r.clone(), *first));
+            i = r.end;
+        } else {
+            i += 1;
+        }
+    }
+
+    // copies nested in other copies are never reached, which can leave a shape with only one
+    copies.retain(|(r, _)| firsts[&key(r)].1 > 1);
+    copies
+}
+
+/// a hash of an instruction that ignores where loops jump, so runs of the same shape hash alike
+fn shape_hash<T: BfOptimizable>(instruc: &BfInstruc<T>) -> u64 {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+    use BfInstruc::*;
+
+    let mut hasher = DefaultHasher::new();
+    std::mem::discriminant(instruc).hash(&mut hasher);
+
+    match instruc {
+        IncBy(v) | DecBy(v) | Set(v) => hasher.write_u64((*v).into()),
+        IncPtrBy(v) | DecPtrBy(v) | ReadN(v) => hasher.write_u32(v.get()),
+        WriteConst(bytes) => hasher.write(bytes.as_bytes()),
+        Call(at) => hasher.write_u32(*at),
+        _ => {}
+    }
+
+    hasher.finish()
+}
+
+/// whether two runs of instructions are the same once moved to the same place, where balanced
+/// loops of the same shape jump to the same relative targets
+fn same_shape<T: PartialEq>(a: &[BfInstruc<T>], b: &[BfInstruc<T>]) -> bool {
+    use BfInstruc::*;
+
+    a.len() == b.len()
+        && a.iter().zip(b).all(|pair| match pair {
+            (LStart(_), LStart(_)) | (LEnd(_), LEnd(_)) => true,
+            (a, b) => a == b,
+        })
+}
 
 impl<T> BfInstructionStream<T> {
+    /// Wraps `stream` as it is, trusting every jump target in it and using its length as the
+    /// recommended tape size, so malformed loops index out of bounds at run time
+    #[deprecated(note = "jump targets are not checked, use `try_from_instructions` instead")]
+    #[must_use]
+    pub fn from_instructions_unchecked(stream: Vec<BfInstruc<T>>) -> Self {
+        let stream_len = stream.len();
+        Self(stream, stream_len, None)
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.0.truncate(len);
+        if let Some(spans) = &mut self.2 {
+            spans.truncate(len);
+        }
+    }
+
     fn bf_to_stream(v: impl Iterator<Item = u8>) -> Vec<BfInstruc<T>> {
-        v.filter_map(|byte| BfInstruc::try_from(byte).ok())
+        Tokenizer::new(v)
+            .filter_map(|token| BfInstruc::from_token(&token))
             .collect()
     }
 
@@ -433,8 +2106,6 @@ impl<T> BfInstructionStream<T> {
 
         const OPT_COUNT: usize = 2;
 
-        let v = &mut self.0;
-
         let static_tree: [(&[BfInstruc<T>], BfInstruc<T>); OPT_COUNT] = [
             (&[LStart(0), Dec, LEnd(0)], Zero),
             (&[LStart(0), Inc, LEnd(0)], Zero),
@@ -445,6 +2116,9 @@ impl<T> BfInstructionStream<T> {
         while optimized_count != 0 {
             optimized_count = 0;
 
+            let v = &mut self.0;
+            let spans = &mut self.2;
+
             let mut paths = [0usize; OPT_COUNT];
 
             let mut newidx = 0usize;
@@ -464,12 +2138,18 @@ impl<T> BfInstructionStream<T> {
                     }
 
                     if *p == static_tree[idx].0.len() {
+                        if !take_fuel() {
+                            *p = 0;
+                            continue;
+                        }
+
                         optimized = Some((static_tree[idx].1.clone(), *p));
                         break 'opt;
                     }
                 }
 
                 v[newidx] = v[i].clone();
+                carry_span(spans, newidx, i, i);
                 newidx += 1;
 
                 if let Some((ins, cnt)) = optimized {
@@ -478,12 +2158,13 @@ impl<T> BfInstructionStream<T> {
 
                     newidx -= cnt;
                     v[newidx] = ins;
+                    carry_span(spans, newidx, newidx, newidx + cnt - 1);
                     newidx += 1;
                 }
 
                 i += 1;
             }
-            v.truncate(newidx);
+            self.truncate(newidx);
         }
     }
 
@@ -501,12 +2182,13 @@ impl<T> BfInstructionStream<T> {
                 }
                 BfInstruc::LEnd(_) => {
                     if let Some(v) = stack.pop() {
+                        // jump points are stored as u32, so streams that still do not fit after
+                        // optimizing can not be represented
                         stream[v] = BfInstruc::LStart(
-                            u32::try_from(idx).expect("u32 overflowed casting from usize"),
-                        );
-                        stream[idx] = BfInstruc::LEnd(
-                            u32::try_from(v).expect("u32 overflowed casting from usize"),
+                            u32::try_from(idx).map_err(|_| BfCompError::Overflow)?,
                         );
+                        stream[idx] =
+                            BfInstruc::LEnd(u32::try_from(v).map_err(|_| BfCompError::Overflow)?);
                     } else {
                         return Err(BfCompError::LoopEndBeforeLoopStart);
                     }
@@ -519,14 +2201,26 @@ impl<T> BfInstructionStream<T> {
             return Err(BfCompError::LoopCountMismatch);
         }
 
+        // calls jump to the instruction before their block, so no block can start the stream
+        let len = stream.len();
+        if stream
+            .iter()
+            .any(|i| matches!(i, BfInstruc::Call(at) if *at == 0 || at.into_usize() >= len))
+        {
+            return Err(BfCompError::CallOutOfRange);
+        }
+
         Ok(())
     }
 }
 
+/// The same unchecked conversion as the deprecated
+/// [`BfInstructionStream::from_instructions_unchecked`]. Trait impls can not be marked deprecated,
+/// so this conversion does not warn, use [`BfInstructionStream::try_from_instructions`] instead.
 impl<T> From<Vec<BfInstruc<T>>> for BfInstructionStream<T> {
+    #[allow(deprecated)]
     fn from(stream: Vec<BfInstruc<T>>) -> Self {
-        let stream_len = stream.len();
-        Self(stream, stream_len)
+        Self::from_instructions_unchecked(stream)
     }
 }
 
@@ -543,3 +2237,294 @@ impl<T> std::ops::Deref for BfInstructionStream<T> {
         &self.0
     }
 }
+
+#[test]
+fn test_fold_zero_set() {
+    use BfInstruc::*;
+
+    let stream =
+        BfInstructionStream::<u8>::optimized_from_text(b"[-]+++>[+]->[-]-".iter().copied(), None)
+            .unwrap();
+
+    assert_eq!(&*stream, &[Set(3), IncPtr, Set(255), IncPtr, Set(255)]);
+
+    let stream = BfInstructionStream::<u8>::optimized_from_text(
+        b"[-]++[-]+++-->[+]-+".iter().copied(),
+        None,
+    )
+    .unwrap();
+
+    assert_eq!(&*stream, &[Set(1), IncPtr, Zero]);
+}
+
+#[test]
+fn test_fold_bulk_reads() {
+    use BfInstruc::*;
+
+    let stream =
+        BfInstructionStream::<u8>::optimized_from_text(b",>,>,>.,>.,,>,>".iter().copied(), None)
+            .unwrap();
+
+    let n = |v| ReadN(NonZeroU32::new(v).unwrap());
+
+    assert_eq!(&*stream, &[n(3), Write, Read, IncPtr, Write, Read, n(2)]);
+}
+
+#[test]
+fn test_fold_const_writes() {
+    use BfInstruc::*;
+
+    let stream = |code: &[u8]| {
+        BfInstructionStream::<u8>::optimized_from_text(code.iter().copied(), None).unwrap()
+    };
+    let c = |bytes: &[u8]| WriteConst(ConstBytes::new(bytes));
+
+    assert_eq!(
+        &*stream(b"[-]++.+.>[-]+.<.>."),
+        &[
+            Set(2),
+            Inc,
+            c(&[2, 3]),
+            IncPtr,
+            Set(1),
+            DecPtr,
+            IncPtr,
+            c(&[1, 3, 1])
+        ]
+    );
+    assert_eq!(&*stream(b"[-]......."), &[Zero, c(&[0; 6]), c(&[0])]);
+    assert_eq!(&*stream(b"[-].,."), &[Zero, Write, Read, Write]);
+    assert_eq!(std::mem::size_of::<BfInstruc<u32>>(), 8);
+}
+
+#[test]
+fn test_try_from_instructions() {
+    use BfInstruc::*;
+
+    let stream =
+        BfInstructionStream::<u8>::try_from_instructions(vec![Inc, LStart(7), Dec, LEnd(0)], None)
+            .unwrap();
+
+    assert_eq!(&*stream, &[Inc, LStart(3), Dec, LEnd(1)]);
+    assert_eq!(stream.reccomended_array_size(), 30_000);
+
+    assert!(matches!(
+        BfInstructionStream::<u8>::try_from_instructions(vec![LEnd(0), LStart(0)], None),
+        Err(BfCompError::LoopEndBeforeLoopStart)
+    ));
+    assert!(matches!(
+        BfInstructionStream::<u8>::try_from_instructions(vec![LStart(0)], Some(10)),
+        Err(BfCompError::LoopCountMismatch)
+    ));
+}
+
+#[test]
+fn test_if_conversion() {
+    let c = |code: &[u8]| {
+        let mut out = vec![];
+        BfInstructionStream::<u8>::optimized_from_text(code.iter().copied(), None)
+            .unwrap()
+            .render_c_with(CCodegenOpts::default(), &mut out)
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    assert!(c(b",[>+.<[-]]").contains("if (*a != 0) {"));
+    assert!(!c(b",[>+.<-]").contains("if (*a != 0) {"));
+}
+
+#[test]
+fn test_opt_fuel() {
+    use BfInstruc::*;
+
+    let optimize = |fuel| {
+        set_opt_fuel(fuel);
+        let stream =
+            BfInstructionStream::<u8>::optimized_from_text(b"++>++[-]".iter().copied(), None)
+                .unwrap();
+        set_opt_fuel(None);
+        stream.0
+    };
+
+    assert_eq!(
+        optimize(Some(0)),
+        [Inc, Inc, IncPtr, Inc, Inc, LStart(7), Dec, LEnd(5)]
+    );
+    assert_eq!(
+        optimize(Some(2)),
+        [IncBy(2), IncPtr, IncBy(2), LStart(5), Dec, LEnd(3)]
+    );
+    assert_eq!(optimize(Some(3)), [IncBy(2), IncPtr, IncBy(2), Zero]);
+    assert_eq!(last_rewrite_pass(), "static_optimize");
+    assert_eq!(optimize(Some(4)), optimize(None));
+    assert_eq!(last_rewrite_pass(), "eliminate_dead_stores");
+}
+
+#[test]
+fn test_eliminate_dead_stores() {
+    use BfInstruc::*;
+
+    let stream = |code: &[u8]| {
+        BfInstructionStream::<u8>::optimized_from_text(code.iter().copied(), None).unwrap()
+    };
+
+    assert_eq!(
+        &*stream(b">+<+>[-]++<[-]"),
+        &[IncPtr, DecPtr, IncPtr, Set(2), DecPtr, Zero]
+    );
+    // the first store is read by the write, or kept in case moving to a new cell fails
+    assert_eq!(&*stream(b"+.[-]"), &[Inc, Write, Zero]);
+    assert_eq!(&*stream(b"+>[-]<[-]"), &[Inc, IncPtr, Zero, DecPtr, Zero]);
+    assert_eq!(&*stream(b"+[[-]]"), &[Inc, LStart(3), Zero, LEnd(1)]);
+}
+
+#[test]
+fn test_traceable_from_text() {
+    use BfInstruc::*;
+
+    let code = b"[-]+.+.";
+
+    let folded = BfInstructionStream::<u8>::optimized_from_text_spanned(code, None).unwrap();
+    assert!(folded.iter().any(|i| matches!(i, WriteConst(_))));
+
+    // each write stays where the source has it, with its own span
+    let traceable = BfInstructionStream::<u8>::traceable_from_text(code, None).unwrap();
+    assert_eq!(&*traceable, &[Set(1), Write, Inc, Write]);
+    assert_eq!(traceable.spans().unwrap()[3], 6..7);
+}
+
+#[test]
+fn test_current_pass() {
+    assert_eq!(current_pass(), "none");
+
+    // the unbalanced loop fails in insert_bf_jump_points, which must not stay the current pass
+    assert!(BfInstructionStream::<u8>::optimized_from_text(b"+[".iter().copied(), None).is_err());
+    assert_eq!(current_pass(), "none");
+
+    let code = BfInstructionStream::<u8>::optimized_from_text(b"+.".iter().copied(), None).unwrap();
+    code.render_c(&mut vec![]).unwrap();
+    assert_eq!(current_pass(), "none");
+}
+
+#[test]
+fn test_c_tape_len() {
+    let render = |code: &[u8], opts: CCodegenOpts| {
+        let stream =
+            BfInstructionStream::<u8>::optimized_from_text(code.iter().copied(), None).unwrap();
+
+        let mut c = vec![];
+        stream.render_c_with(opts, &mut c).unwrap();
+        String::from_utf8(c).unwrap()
+    };
+
+    let opts = CCodegenOpts::default();
+
+    // the pointer provably stays on the first three cells
+    assert!(render(b",>+>[-<+>]<<.", opts).contains("#define ARRSIZE 3\n"));
+
+    // a loop that moves the pointer can reach any cell
+    assert!(render(b",[>,]", opts).contains("#define ARRSIZE 30000\n"));
+
+    let resumable = CCodegenOpts {
+        resumable: true,
+        ..opts
+    };
+    assert!(render(b",>+>[-<+>]<<.", resumable).contains("#define ARRSIZE 30000\n"));
+}
+
+#[test]
+fn test_dedup_loops() {
+    use crate::interpreter::BrainFuckExecutorBuilder;
+
+    // each copy of the loop is 19 instructions once grouped, with different counts before it
+    let repeated = "[->+.>++.>+++.>++++.>+++++.<<<<<]";
+    let source = format!("+++{repeated}>>>>>>++{repeated}<<<<<<+{repeated}");
+
+    let run = |code: &BfInstructionStream<u8>| {
+        let mut exec = BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .array_len(code.reccomended_array_size())
+            .stream_in(&[][..])
+            .stream_out(vec![])
+            .build()
+            .unwrap();
+        exec.run(code).unwrap();
+        (exec.stdout, exec.data)
+    };
+
+    let plain =
+        BfInstructionStream::<u8>::optimized_from_text_spanned(source.as_bytes(), None).unwrap();
+
+    let mut deduped =
+        BfInstructionStream::<u8>::optimized_from_text_spanned(source.as_bytes(), None).unwrap();
+    deduped.dedup_loops().unwrap();
+
+    // three calls, the program's return, then one block and its return
+    assert_eq!(deduped.len(), plain.len() - 3 * 19 + 3 + 1 + 19 + 1);
+    assert_eq!(
+        deduped
+            .iter()
+            .filter(|i| matches!(i, BfInstruc::Call(_)))
+            .count(),
+        3
+    );
+    assert_eq!(deduped.spans().unwrap().len(), deduped.len());
+    assert_eq!(run(&deduped), run(&plain));
+
+    // a second pass leaves the blocks alone
+    let len = deduped.len();
+    deduped.dedup_loops().unwrap();
+    assert_eq!(deduped.len(), len);
+
+    assert!(deduped.render_c(&mut vec![]).is_err());
+
+    // hand built calls may not target the start of the stream or past its end
+    for at in [0, 2] {
+        assert_eq!(
+            BfInstructionStream::<u8>::try_from_instructions(
+                vec![BfInstruc::Call(at), BfInstruc::Return],
+                None
+            )
+            .err(),
+            Some(BfCompError::CallOutOfRange)
+        );
+    }
+}
+
+#[test]
+fn test_render_bf_wide_cells() {
+    use BfInstruc::*;
+
+    fn render<T: BfOptimizable>(code: &[u8]) -> String {
+        let stream =
+            BfInstructionStream::<T>::optimized_from_text(code.iter().copied(), None).unwrap();
+
+        let mut bf = vec![];
+        stream.render_bf(&mut bf).unwrap();
+
+        String::from_utf8(bf).unwrap()
+    }
+
+    // values past half the cell range are written as the shorter subtraction
+    assert_eq!(render::<u64>(b"[-]-."), "[-]-.\n");
+    assert_eq!(render::<u32>(b"[-]--"), "[-]--\n");
+    assert_eq!(render::<I32>(b"[-]-->+++<---"), "[-]-->+++<---\n");
+
+    let stream = BfInstructionStream::<u64>::try_from_instructions(
+        vec![IncBy(u64::MAX - 2), DecBy(u64::MAX), Set(1 << 63)],
+        None,
+    )
+    .unwrap();
+
+    // no direction is short for half the range of a 64 bit cell
+    assert!(stream.render_bf(&mut vec![]).is_err());
+
+    let stream = BfInstructionStream::<u64>::try_from_instructions(
+        vec![IncBy(u64::MAX - 2), DecBy(u64::MAX)],
+        None,
+    )
+    .unwrap();
+    let mut bf = vec![];
+    stream.render_bf(&mut bf).unwrap();
+
+    assert_eq!(bf, b"---+\n");
+}
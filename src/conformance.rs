@@ -0,0 +1,206 @@
+//! A built in corpus of canonical programs that any engine can be checked against
+
+use core::fmt;
+
+use crate::compiler::BfOptimizable;
+use crate::engine::BfEngine;
+
+/// A single canonical program and its expected output
+pub struct ConformanceCase {
+    pub name: &'static str,
+    pub program: &'static [u8],
+    pub input: &'static [u8],
+    /// expected output given the engines cell width in bits
+    pub expected: fn(u32) -> &'static [u8],
+}
+
+const HELLO_WORLD: &[u8] = b"++++++++[>++++[>++>+++>+++>+<<<<-]>+>+>->>+[<]<-]>>.>---.+++++++..+++.>>.<-.<.+++.------.--------.>>+.>++.";
+
+/// prints `A` if 256 does not wrap to zero, then `B` if 65536 does not wrap to zero
+const CELL_SIZE_PROBE: &[u8] = b"
+    ++++++++++++++++[>++++++++++++++++<-]>
+    [[-]>[-]++++++++[<++++++++>-]<+.[-]]
+    ++++++++++++++++[>++++++++++++++++[>++++++++++++++++[>++++++++++++++++<-]<-]<-]>>>
+    [[-]>[-]++++++++[<++++++++>-]<++.[-]]
+";
+
+/// 64 nested loops that are each entered exactly once, followed by printing `A`
+const DEEP_NESTING: &[u8] = b"
+    +[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[[
+    -]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]]
+    ++++++++[>++++++++<-]>+.
+";
+
+/// loops that are never entered may contain anything, including other loops and IO
+const SKIPPED_LOOPS: &[u8] = b"[.[,.]-[>]<]>[[[.]]]<++++++++[>++++++++<-]>++.";
+
+/// the classic comment loop idiom, with brainfuck characters inside of the comment
+const COMMENT_LOOP: &[u8] =
+    b"[this is a comment, with brainfuck in it. <<<< ----]++++++++[>++++++++<-]>+++.";
+
+/// a loop whose exit depends on the cell it returns to rather than the one it started on
+const MOVING_LOOP: &[u8] =
+    b">+>+>+>+>+[<]>[.>]++++++++++++++++++++++++++++++++++++++++++++++++++++.";
+
+/// reads input until EOF, echoing it back
+const ECHO: &[u8] = b",[.,]";
+
+/// a run of reads that outlasts its input, so the last cells read as EOF
+const BULK_READ: &[u8] = b",>,>,>,>,>,<<<<<.>.>.>.>.";
+
+/// decrementing zero wraps to the cell maximum, whose low byte is always 0xff
+const WRAP: &[u8] = b"-.+.";
+
+/// The built in corpus of canonical programs
+pub const CORPUS: &[ConformanceCase] = &[
+    ConformanceCase {
+        name: "hello world",
+        program: HELLO_WORLD,
+        input: b"",
+        expected: |_| b"Hello World!\n",
+    },
+    ConformanceCase {
+        name: "cell size probe",
+        program: CELL_SIZE_PROBE,
+        input: b"",
+        expected: |bits| match bits {
+            ..=8 => b"",
+            9..=16 => b"A",
+            _ => b"AB",
+        },
+    },
+    ConformanceCase {
+        name: "deep nesting",
+        program: DEEP_NESTING,
+        input: b"",
+        expected: |_| b"A",
+    },
+    ConformanceCase {
+        name: "skipped loops",
+        program: SKIPPED_LOOPS,
+        input: b"",
+        expected: |_| b"B",
+    },
+    ConformanceCase {
+        name: "comment loop",
+        program: COMMENT_LOOP,
+        input: b"",
+        expected: |_| b"C",
+    },
+    ConformanceCase {
+        name: "moving loop",
+        program: MOVING_LOOP,
+        input: b"",
+        expected: |_| b"\x01\x01\x01\x01\x014",
+    },
+    ConformanceCase {
+        name: "echo",
+        program: ECHO,
+        input: b"echo\xff",
+        expected: |_| b"echo\xff",
+    },
+    ConformanceCase {
+        name: "bulk read",
+        program: BULK_READ,
+        input: b"abc",
+        expected: |_| b"abc\0\0",
+    },
+    ConformanceCase {
+        name: "wrapping",
+        program: WRAP,
+        input: b"",
+        expected: |_| b"\xff\x00",
+    },
+];
+
+/// A corpus case whose output did not match what was expected
+#[derive(Debug)]
+pub struct Mismatch {
+    pub name: &'static str,
+    pub expected: &'static [u8],
+    pub got: Result<Vec<u8>, String>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: expected \"{}\", ",
+            self.name,
+            self.expected.escape_ascii()
+        )?;
+
+        match &self.got {
+            Ok(got) => write!(f, "got \"{}\"", got.escape_ascii()),
+            Err(e) => write!(f, "got error: {e}"),
+        }
+    }
+}
+
+/// Runs every case in [`CORPUS`] for at most `limit` instructions, each on a fresh engine that
+/// `engine` makes for the prepared program and the input of the case, returning all cases that did
+/// not match. Reads past the end of the input must behave as EOF, and `output` returns everything
+/// an engine has written.
+pub fn check<E: BfEngine>(
+    limit: u64,
+    mut engine: impl FnMut(&E::Program, &'static [u8]) -> E,
+    output: impl Fn(&E) -> &[u8],
+) -> Vec<Mismatch> {
+    CORPUS
+        .iter()
+        .filter_map(|case| {
+            let expected = (case.expected)(E::Cell::BITS);
+
+            let got = E::prepare(case.program, None)
+                .map_err(|e| e.to_string())
+                .and_then(|program| {
+                    let mut engine = engine(&program, case.input);
+                    engine
+                        .run_limited(&program, limit)
+                        .map_err(|e| e.to_string())?;
+
+                    Ok(output(&engine).to_vec())
+                });
+
+            match got {
+                Ok(got) if got == expected => None,
+                got => Some(Mismatch {
+                    name: case.name,
+                    expected,
+                    got,
+                }),
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_interpreter_conformance() {
+    use crate::compiler::{BfInstructionStream, Masked};
+    use crate::interpreter::BrainFuckExecutorBuilder;
+
+    fn check_interpreter<T: BfOptimizable>() -> Vec<Mismatch> {
+        check(
+            10_000_000,
+            |program: &BfInstructionStream<T>, input| {
+                BrainFuckExecutorBuilder::<T, _, _>::new()
+                    .stream_in(input)
+                    .stream_out(vec![])
+                    .array_len(program.reccomended_array_size())
+                    .build()
+                    .unwrap()
+            },
+            |engine| &engine.stdout,
+        )
+    }
+
+    for mismatches in [
+        check_interpreter::<u8>(),
+        check_interpreter::<u16>(),
+        check_interpreter::<u32>(),
+        check_interpreter::<u64>(),
+        check_interpreter::<Masked<12>>(),
+    ] {
+        assert!(mismatches.is_empty(), "{mismatches:?}");
+    }
+}
@@ -0,0 +1,63 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    io::{self, Write},
+};
+
+use bfirs::compiler::{BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::BrainFuckExecutorBuilder;
+
+/// Runs `code` with reads returning EOF and output discarded, reporting how many instructions were
+/// executed and how often each kind of instruction ran
+pub fn count<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    limit: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let code = BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?;
+
+    let mut execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .array_len(code.reccomended_array_size())
+        .stream_in(io::empty())
+        .stream_out(io::sink())
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    let limit = limit.unwrap_or(u64::MAX);
+
+    let mut histogram = BTreeMap::<&'static str, u64>::new();
+    let mut total = 0u64;
+    let mut idx = (!code.is_empty()).then_some(0);
+
+    while let Some(i) = idx {
+        if total == limit {
+            break;
+        }
+
+        *histogram.entry(code[i].name()).or_default() += 1;
+        total += 1;
+
+        idx = execenv.step(&code, i)?;
+    }
+
+    let mut histogram: Vec<_> = histogram.into_iter().collect();
+    histogram.sort_by_key(|&(_, count)| Reverse(count));
+
+    let mut out = io::stdout().lock();
+
+    writeln!(out, "{total} instructions executed")?;
+
+    if idx.is_some() {
+        writeln!(out, "instruction limit reached before the program finished")?;
+    }
+
+    for (name, count) in histogram {
+        // precision loss is irrelevant for a human readable percentage
+        #[allow(clippy::cast_precision_loss)]
+        let percent = count as f64 / total as f64 * 100.0;
+
+        writeln!(out, "  {name:<10} {count:>14} {percent:>6.2}%")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,592 @@
+//! An interactive debugger that steps through a program one instruction at a time
+//!
+//! Commands are read a line at a time from stdin, and the program writes to stdout as it runs.
+//! Prompts and reports go to stderr so they never mix with what the program writes.
+//!
+//! With `--tui` it instead takes over the terminal, redrawing the source, tape and output after
+//! every key press.
+
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, BufRead, Write},
+    ops::Range,
+};
+
+#[cfg(unix)]
+use std::{
+    fmt::Write as _,
+    io::{IsTerminal, Read},
+};
+
+use bfirs::compiler::{BfInstructionStream, BfOptimizable, Eof};
+use bfirs::interpreter::{BrainFuckExecutor, BrainFuckExecutorBuilder};
+use bfirs::token::LineIndex;
+use clap::Args;
+
+#[cfg(unix)]
+use crate::terminal::{InputMode, TerminalGuard};
+use crate::PathIoError;
+
+/// cells either side of the pointer shown by `print cells` without a range
+const VIEW_RADIUS: usize = 8;
+
+const HELP: &str = "\
+step [N]          run the next N instructions, 1 by default
+continue          run until a breakpoint or the end of the program
+break OFFSET      stop before running code built from the source byte at OFFSET
+delete OFFSET     remove the breakpoint at OFFSET
+print cells A..B  show cells A up to but not including B, or those around the pointer
+where             show the source the next instruction was built from
+help              show this message
+quit              stop debugging";
+
+#[cfg(unix)]
+const TUI_KEYS: &str =
+    " bf debug | s step | f step 100 | c continue, any key stops | b breakpoint | q quit";
+
+/// instructions run between redraws and checks for a key press while continuing in the TUI
+#[cfg(unix)]
+const CONTINUE_CHUNK: u64 = 10_000;
+
+#[derive(Args)]
+/// step through a program interactively, reading debugger commands from stdin
+pub struct DebugArgs {
+    /// file to read the program's input from, it reads no input otherwise
+    #[arg(long, value_name = "FILE")]
+    input: Option<String>,
+
+    /// take over the terminal with a view of the source, tape and output, driven by single key
+    /// presses instead of typed commands
+    #[arg(long)]
+    tui: bool,
+}
+
+/// where the program is between commands
+enum State {
+    /// the instruction at this index runs next
+    At(usize),
+    Finished,
+    /// the program errored, leaving its tape to inspect
+    Errored,
+}
+
+struct Debugger<'a, CellSize: BfOptimizable> {
+    source: &'a [u8],
+    lines: LineIndex,
+    code: BfInstructionStream<CellSize>,
+    execenv: BrainFuckExecutor<CellSize, io::Cursor<Vec<u8>>, Box<dyn Write>>,
+    state: State,
+    breakpoints: BTreeSet<usize>,
+    /// instructions run so far
+    ran: u64,
+}
+
+pub fn debug<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    eof: Eof,
+    args: &DebugArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = code;
+    let code = BfInstructionStream::<CellSize>::optimized_from_text_spanned(code, arr_len)?;
+
+    let input = match &args.input {
+        Some(path) => fs::read(path).map_err(|e| PathIoError(path.clone(), e))?,
+        None => vec![],
+    };
+
+    // the TUI draws output in a pane of its own, from what is captured
+    let stdout: Box<dyn Write> = if args.tui {
+        Box::new(io::sink())
+    } else {
+        Box::new(io::stdout())
+    };
+
+    let execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .array_len(code.reccomended_array_size())
+        .stream_in(io::Cursor::new(input))
+        .stream_out(stdout)
+        .capture_output(args.tui)
+        .eof(eof)
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    let state = if code.is_empty() {
+        State::Finished
+    } else {
+        State::At(0)
+    };
+
+    let mut debugger = Debugger {
+        source,
+        lines: LineIndex::new(source),
+        code,
+        execenv,
+        state,
+        breakpoints: BTreeSet::new(),
+        ran: 0,
+    };
+
+    if args.tui {
+        #[cfg(unix)]
+        return Ok(debugger.tui()?);
+        #[cfg(not(unix))]
+        return Err("--tui is only supported on unix".into());
+    }
+
+    let mut err = io::stderr().lock();
+    writeln!(err, "type help for a list of commands")?;
+    debugger.report_where(&mut err)?;
+
+    let mut line = String::new();
+
+    loop {
+        write!(err, "(bf) ")?;
+        err.flush()?;
+
+        line.clear();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            writeln!(err)?;
+            return Ok(());
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+
+        match words[..] {
+            [] => {}
+            ["quit" | "q"] => return Ok(()),
+            ["help" | "h"] => writeln!(err, "{HELP}")?,
+            ["where" | "w"] => debugger.report_where(&mut err)?,
+            ["step" | "s"] => debugger.step(1, &mut err)?,
+            ["step" | "s", n] => match n.parse() {
+                Ok(n) => debugger.step(n, &mut err)?,
+                Err(e) => writeln!(err, "bad step count {n:?}: {e}")?,
+            },
+            ["continue" | "c"] => debugger.step(u64::MAX, &mut err)?,
+            ["break" | "b", offset] => match debugger.parse_offset(offset) {
+                Ok(offset) => {
+                    debugger.breakpoints.insert(offset);
+                    writeln!(err, "breakpoint at source offset {offset}")?;
+                }
+                Err(e) => writeln!(err, "{e}")?,
+            },
+            ["delete" | "d", offset] => match debugger.parse_offset(offset) {
+                Ok(offset) if debugger.breakpoints.remove(&offset) => {
+                    writeln!(err, "removed the breakpoint at source offset {offset}")?;
+                }
+                Ok(offset) => writeln!(err, "no breakpoint at source offset {offset}")?,
+                Err(e) => writeln!(err, "{e}")?,
+            },
+            ["print" | "p", "cells"] => {
+                let ptr = debugger.execenv.ptr;
+                let cells = ptr.saturating_sub(VIEW_RADIUS)..ptr + VIEW_RADIUS + 1;
+
+                debugger.print_cells(cells, &mut err)?;
+            }
+            ["print" | "p", "cells", range] => match parse_range(range) {
+                Some(cells) => debugger.print_cells(cells, &mut err)?,
+                None => writeln!(err, "bad cell range {range:?}, expected A..B")?,
+            },
+            _ => writeln!(
+                err,
+                "unknown command {:?}, type help for a list",
+                line.trim()
+            )?,
+        }
+    }
+}
+
+fn parse_range(range: &str) -> Option<Range<usize>> {
+    let (start, end) = range.split_once("..")?;
+
+    Some(start.parse().ok()?..end.parse().ok()?)
+}
+
+impl<CellSize: BfOptimizable> Debugger<'_, CellSize> {
+    fn spans(&self) -> &[Range<usize>] {
+        self.code
+            .spans()
+            .expect("spanned streams always have spans")
+    }
+
+    fn parse_offset(&self, offset: &str) -> Result<usize, String> {
+        match offset.parse() {
+            Ok(offset) if offset < self.source.len() => Ok(offset),
+            Ok(offset) => Err(format!(
+                "source offset {offset} is past the end of the {} byte source",
+                self.source.len()
+            )),
+            Err(e) => Err(format!("bad source offset {offset:?}: {e}")),
+        }
+    }
+
+    /// Runs up to `count` instructions then reports where the program is
+    fn step(&mut self, count: u64, err: &mut impl Write) -> io::Result<()> {
+        self.run(count, false, err)?;
+        self.report_where(err)
+    }
+
+    /// Runs up to `count` instructions, stopping early at the end of the program, on an error, or
+    /// before an instruction built from a breakpoint's source byte. Breakpoints are only checked
+    /// before the first instruction when `break_first` is set, so a run can leave the breakpoint
+    /// it stopped at. Returns whether it stopped early.
+    fn run(&mut self, count: u64, break_first: bool, err: &mut impl Write) -> io::Result<bool> {
+        for n in 0..count {
+            let State::At(idx) = self.state else {
+                if n == 0 {
+                    writeln!(err, "the program is not running")?;
+                }
+
+                return Ok(true);
+            };
+
+            let span = self.spans()[idx].clone();
+
+            if (n > 0 || break_first) && self.breakpoints.range(span.clone()).next().is_some() {
+                writeln!(err, "stopped at a breakpoint")?;
+                return Ok(true);
+            }
+
+            match self.execenv.step(&self.code, idx) {
+                Ok(next) => {
+                    self.ran += 1;
+                    self.state = next.map_or(State::Finished, State::At);
+                }
+                Err(e) => {
+                    self.state = State::Errored;
+
+                    let (line, col) = self.lines.line_col(span.start);
+                    writeln!(err, "the program errored at {line}:{col}: {e}")?;
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn report_where(&self, err: &mut impl Write) -> io::Result<()> {
+        let idx = match self.state {
+            State::At(idx) => idx,
+            State::Finished => {
+                return writeln!(err, "the program finished after {} instructions", self.ran);
+            }
+            State::Errored => {
+                return writeln!(err, "the program errored after {} instructions", self.ran);
+            }
+        };
+
+        let span = self.spans()[idx].clone();
+        let start = span.start;
+        let (line, col) = self.lines.line_col(start);
+
+        writeln!(
+            err,
+            "next is {} at source offset {start} ({line}:{col}), built from {:?}",
+            self.code[idx].name(),
+            String::from_utf8_lossy(&self.source[span]),
+        )?;
+        writeln!(
+            err,
+            "{} instructions run, pointer at cell {}",
+            self.ran, self.execenv.ptr
+        )
+    }
+
+    fn print_cells(&self, cells: Range<usize>, err: &mut impl Write) -> io::Result<()> {
+        let (ptr, tape) = self.execenv.state();
+        let end = cells.end.min(tape.len());
+
+        if cells.start >= end {
+            return writeln!(err, "no cells in that range, the tape has {}", tape.len());
+        }
+
+        for (cell, value) in (cells.start..end).zip(&tape[cells.start..end]) {
+            let marker = if cell == ptr { ">" } else { " " };
+            writeln!(err, "{marker} {cell:>8}: {value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Switches stdout to the alternate screen with the cursor hidden, switching back when dropped
+#[cfg(unix)]
+struct Screen;
+
+#[cfg(unix)]
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        let mut out = io::stdout().lock();
+        write!(out, "\x1b[?1049h\x1b[?25l")?;
+        out.flush()?;
+
+        Ok(Self)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut out = io::stdout().lock();
+        _ = write!(out, "\x1b[?25h\x1b[?1049l");
+        _ = out.flush();
+    }
+}
+
+/// columns and rows of the terminal on stdout, or 80 by 24 if it can not be asked
+#[cfg(unix)]
+fn terminal_size() -> (usize, usize) {
+    // SAFETY: winsize is plain old data, and is only read after the ioctl fills it in
+    let mut size = unsafe { std::mem::zeroed::<libc::winsize>() };
+
+    // SAFETY: TIOCGWINSZ writes a winsize through the pointer it is given
+    let ok = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &raw mut size) } == 0;
+
+    if ok && size.ws_col > 0 && size.ws_row > 0 {
+        (usize::from(size.ws_col), usize::from(size.ws_row))
+    } else {
+        (80, 24)
+    }
+}
+
+/// Returns true if a key press is waiting on stdin
+#[cfg(unix)]
+fn key_pending() -> io::Result<bool> {
+    let mut fd = libc::pollfd {
+        fd: libc::STDIN_FILENO,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    // SAFETY: fd is a single valid pollfd for the duration of the call
+    match unsafe { libc::poll(&raw mut fd, 1, 0) } {
+        -1 => Err(io::Error::last_os_error()),
+        ready => Ok(ready > 0),
+    }
+}
+
+#[cfg(unix)]
+fn read_key() -> io::Result<Option<u8>> {
+    let mut key = [0];
+
+    match io::stdin().lock().read(&mut key)? {
+        0 => Ok(None),
+        _ => Ok(Some(key[0])),
+    }
+}
+
+/// replaces what would move the cursor or could be wider than a column, keeping rows aligned
+#[cfg(unix)]
+fn printable(byte: u8) -> char {
+    if byte == b' ' || byte.is_ascii_graphic() {
+        char::from(byte)
+    } else {
+        '.'
+    }
+}
+
+/// pads or cuts `text` to exactly `width` columns, for the plain ASCII rows the TUI draws
+#[cfg(unix)]
+fn fit(text: &str, width: usize) -> String {
+    format!("{text:width$.width$}")
+}
+
+#[cfg(unix)]
+impl<CellSize: BfOptimizable> Debugger<'_, CellSize> {
+    /// Runs the full screen debugger until it is quit or stdin closes
+    fn tui(&mut self) -> io::Result<()> {
+        if !io::stdout().is_terminal() {
+            return Err(io::Error::other("--tui needs a terminal on stdout"));
+        }
+
+        let mode = InputMode {
+            char_input: true,
+            echo: false,
+        };
+
+        let Some(_terminal) = TerminalGuard::apply(mode)? else {
+            return Err(io::Error::other("--tui needs a terminal on stdin"));
+        };
+
+        let _screen = Screen::enter()?;
+
+        let mut output = vec![];
+        let mut message = vec![];
+
+        loop {
+            output.extend(self.execenv.take_output());
+            self.draw(&output, &message)?;
+
+            let Some(key) = read_key()? else {
+                return Ok(());
+            };
+
+            message.clear();
+
+            match key {
+                b'q' => return Ok(()),
+                b's' | b' ' => _ = self.run(1, false, &mut message)?,
+                b'f' => _ = self.run(100, false, &mut message)?,
+                b'c' => {
+                    let mut break_first = false;
+
+                    while !self.run(CONTINUE_CHUNK, break_first, &mut message)? {
+                        break_first = true;
+
+                        output.extend(self.execenv.take_output());
+                        self.draw(&output, &message)?;
+
+                        if key_pending()? {
+                            read_key()?;
+                            break;
+                        }
+                    }
+                }
+                b'b' => match self.state {
+                    State::At(idx) => {
+                        let offset = self.spans()[idx].start;
+
+                        if self.breakpoints.remove(&offset) {
+                            writeln!(message, "removed the breakpoint at source offset {offset}")?;
+                        } else {
+                            self.breakpoints.insert(offset);
+                            writeln!(message, "breakpoint at source offset {offset}")?;
+                        }
+                    }
+                    _ => writeln!(message, "the program is not running")?,
+                },
+                _ => writeln!(message, "unknown key, see the top line for the keys")?,
+            }
+        }
+    }
+
+    /// Redraws the whole screen: keys, source, tape, output, then a message and where the
+    /// program is
+    fn draw(&self, output: &[u8], message: &[u8]) -> io::Result<()> {
+        const REVERSE: &str = "\x1b[7m";
+        const BREAKPOINT: &str = "\x1b[41m";
+        const RESET: &str = "\x1b[0m";
+
+        let (width, height) = terminal_size();
+
+        // the fixed rows are the keys, two for the tape, the output header, the message and where
+        let panes = height.saturating_sub(6).max(2);
+        let output_rows = (panes / 3).max(1);
+        let source_rows = panes - output_rows;
+
+        let mut rows = vec![format!("{REVERSE}{}{RESET}", fit(TUI_KEYS, width))];
+
+        let span = match self.state {
+            State::At(idx) => self.spans()[idx].clone(),
+            State::Finished | State::Errored => 0..0,
+        };
+
+        // source lines around the next instruction, scrolled sideways when it is off screen
+        let lines: Vec<&[u8]> = self.source.split(|&b| b == b'\n').collect();
+        let (line, col) = self.lines.line_col(span.start.min(self.source.len()));
+        let first = (line - 1).saturating_sub(source_rows / 2);
+        let text_width = width.saturating_sub(7).max(1);
+        let skip = if col > text_width {
+            col - text_width / 2
+        } else {
+            0
+        };
+
+        let mut line_start: usize = lines[..first].iter().map(|l| l.len() + 1).sum();
+
+        for (n, text) in lines.iter().enumerate().skip(first).take(source_rows) {
+            let mut row = format!("{:>5} ", n + 1);
+
+            for (at, &byte) in text.iter().enumerate().skip(skip).take(text_width) {
+                let offset = line_start + at;
+
+                // writing to a String never fails
+                if span.contains(&offset) {
+                    _ = write!(row, "{REVERSE}{}{RESET}", printable(byte));
+                } else if self.breakpoints.contains(&offset) {
+                    _ = write!(row, "{BREAKPOINT}{}{RESET}", printable(byte));
+                } else {
+                    row.push(printable(byte));
+                }
+            }
+
+            rows.push(row);
+            line_start += text.len() + 1;
+        }
+
+        rows.resize(1 + source_rows, String::new());
+
+        // as many cells as fit around the pointer
+        let (ptr, tape) = self.execenv.state();
+        let cell = |i: usize| format!(" {}", tape[i]);
+
+        let (mut lo, mut hi) = (ptr, ptr + 1);
+        let mut used = cell(ptr).len();
+
+        loop {
+            let mut grew = false;
+
+            for side in [hi, lo.wrapping_sub(1)] {
+                if side < tape.len() && used + cell(side).len() <= width {
+                    used += cell(side).len();
+                    grew = true;
+
+                    if side == hi {
+                        hi += 1;
+                    } else {
+                        lo -= 1;
+                    }
+                }
+            }
+
+            if !grew {
+                break;
+            }
+        }
+
+        let header = format!(
+            "-- tape, cells {lo}..{hi} of {}, pointer at {ptr} ",
+            tape.len()
+        );
+        rows.push(fit(&format!("{header:-<width$}"), width));
+
+        let mut values = String::new();
+        for i in lo..hi {
+            if i == ptr {
+                _ = write!(values, "{REVERSE}{}{RESET}", cell(i));
+            } else {
+                values += &cell(i);
+            }
+        }
+        rows.push(values);
+
+        rows.push(fit(&format!("{:-<width$}", "-- output "), width));
+
+        let output_lines: Vec<&[u8]> = output.split(|&b| b == b'\n').collect();
+        let shown = output_lines.len().saturating_sub(output_rows);
+
+        for text in &output_lines[shown..] {
+            rows.push(text.iter().take(width).map(|&b| printable(b)).collect());
+        }
+
+        rows.resize(
+            rows.len() + output_rows - (output_lines.len() - shown),
+            String::new(),
+        );
+
+        let message = String::from_utf8_lossy(message);
+        rows.push(fit(message.lines().last().unwrap_or(""), width));
+
+        let mut status = vec![];
+        self.report_where(&mut status)?;
+        let status = String::from_utf8_lossy(&status);
+        let status = status.lines().collect::<Vec<_>>().join(", ");
+        rows.push(format!("{REVERSE}{}{RESET}", fit(&status, width)));
+
+        let mut out = io::stdout().lock();
+        write!(out, "\x1b[H{}\x1b[J", rows.join("\x1b[K\r\n"))?;
+        out.flush()
+    }
+}
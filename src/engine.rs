@@ -0,0 +1,60 @@
+//! A common interface over the engines that can execute brainfuck
+
+use std::io;
+
+use crate::compiler::{BfCompError, BfInstructionStream, BfOptimizable};
+use crate::interpreter::{BfExecError, BrainFuckExecutor};
+
+/// An engine that can execute brainfuck, allowing callers to treat engines interchangeably
+pub trait BfEngine {
+    /// The type of a single cell on the tape
+    type Cell: BfOptimizable;
+
+    /// A program in the form this engine executes
+    type Program;
+
+    /// Compiles brainfuck source text into the form this engine executes
+    ///
+    /// # Errors
+    /// Errors if the source could not be compiled
+    fn prepare(source: &[u8], array_len: Option<u32>) -> Result<Self::Program, BfCompError>;
+
+    /// Runs `program` until it completes, this function is not guaranteed to halt
+    ///
+    /// # Errors
+    /// Any error raised while running the program
+    fn run(&mut self, program: &Self::Program) -> Result<(), BfExecError>;
+
+    /// Runs `program` for at most `limit` instructions, erroring with
+    /// [`BfExecErrorTy::NotEnoughInstructions`](crate::interpreter::BfExecErrorTy::NotEnoughInstructions)
+    /// if it does not complete in time
+    ///
+    /// # Errors
+    /// Any error raised while running the program
+    fn run_limited(&mut self, program: &Self::Program, limit: u64) -> Result<(), BfExecError>;
+
+    /// Returns the data pointer and tape
+    fn state(&self) -> (usize, &[Self::Cell]);
+}
+
+impl<T: BfOptimizable, I: io::Read, O: io::Write> BfEngine for BrainFuckExecutor<T, I, O> {
+    type Cell = T;
+    type Program = BfInstructionStream<T>;
+
+    fn prepare(source: &[u8], array_len: Option<u32>) -> Result<Self::Program, BfCompError> {
+        BfInstructionStream::optimized_from_text(source.iter().copied(), array_len)
+    }
+
+    fn run(&mut self, program: &Self::Program) -> Result<(), BfExecError> {
+        BrainFuckExecutor::run(self, program)
+    }
+
+    fn run_limited(&mut self, program: &Self::Program, limit: u64) -> Result<(), BfExecError> {
+        self.instruction_limit = limit;
+        BrainFuckExecutor::run_limited(self, program)
+    }
+
+    fn state(&self) -> (usize, &[Self::Cell]) {
+        BrainFuckExecutor::state(self)
+    }
+}
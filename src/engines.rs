@@ -0,0 +1,200 @@
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+use bfirs::compiler::{BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::{BfExecError, BfExecErrorTy, BrainFuckExecutorBuilder};
+
+use crate::run::cached_executable;
+
+/// what a probe run produced, or why it stopped early
+type Outcome = Result<Vec<u8>, String>;
+
+/// A small program whose behaviour differs between engines that make different choices
+struct Probe {
+    name: &'static str,
+    program: &'static [u8],
+    input: &'static [u8],
+    /// whether the probe leaves the tape, which compiled C does not check
+    leaves_tape: bool,
+    describe: fn(&Outcome) -> String,
+}
+
+fn describe_error(outcome: &Outcome) -> String {
+    match outcome {
+        Ok(_) => "continues".to_owned(),
+        Err(e) => e.clone(),
+    }
+}
+
+/// a short name for how an interpreter run stopped, to fit in the matrix
+fn short_error(e: &BfExecError) -> String {
+    match e.source {
+        BfExecErrorTy::Overflow => "overflow error".to_owned(),
+        BfExecErrorTy::Underflow => "underflow error".to_owned(),
+        _ => e.to_string(),
+    }
+}
+
+const PROBES: &[Probe] = &[
+    Probe {
+        name: "cell width",
+        // prints A if 256 does not wrap to zero, then B if 65536 does not
+        program: b"++++++++++++++++[>++++++++++++++++<-]>[[-]>[-]++++++++[<++++++++>-]<+.[-]]
+            ++++++++++++++++[>++++++++++++++++[>++++++++++++++++[>++++++++++++++++<-]<-]<-]>>>
+            [[-]>[-]++++++++[<++++++++>-]<++.[-]]",
+        input: b"",
+        leaves_tape: false,
+        describe: |outcome| match outcome.as_deref() {
+            Ok(b"") => "8 bit".to_owned(),
+            Ok(b"A") => "9 to 16 bit".to_owned(),
+            // telling 64 bit cells apart would take billions of instructions
+            Ok(b"AB") => "17 bit or wider".to_owned(),
+            other => format!("unexpected {other:?}"),
+        },
+    },
+    Probe {
+        name: "decrement 0",
+        program: b"-.",
+        input: b"",
+        leaves_tape: false,
+        describe: |outcome| match outcome.as_deref() {
+            Ok([255]) => "wraps".to_owned(),
+            other => format!("unexpected {other:?}"),
+        },
+    },
+    Probe {
+        name: "read at EOF",
+        program: b"+,.",
+        input: b"",
+        leaves_tape: false,
+        describe: |outcome| match outcome.as_deref() {
+            Ok([0]) => "sets 0".to_owned(),
+            Ok([1]) => "unchanged".to_owned(),
+            Ok([255]) => "sets -1".to_owned(),
+            other => format!("unexpected {other:?}"),
+        },
+    },
+    Probe {
+        name: "left of cell 0",
+        program: b"<",
+        input: b"",
+        leaves_tape: true,
+        describe: describe_error,
+    },
+    Probe {
+        name: "past tape end",
+        program: b"+[>+]",
+        input: b"",
+        leaves_tape: true,
+        describe: describe_error,
+    },
+];
+
+fn run_interpreter<CellSize: BfOptimizable>(
+    program: &[u8],
+    input: &[u8],
+    stepped: bool,
+) -> Outcome {
+    let code = BfInstructionStream::<CellSize>::optimized_from_text(program.iter().copied(), None)
+        .map_err(|e| e.to_string())?;
+
+    let mut execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .array_len(code.reccomended_array_size())
+        .stream_in(input)
+        .stream_out(vec![])
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    if stepped {
+        let mut idx = (!code.is_empty()).then_some(0);
+
+        while let Some(i) = idx {
+            idx = execenv.step(&code, i).map_err(|e| short_error(&e))?;
+        }
+    } else {
+        execenv.run(&code).map_err(|e| short_error(&e))?;
+    }
+
+    Ok(execenv.stdout)
+}
+
+/// compiles `program` to C and runs it natively, or `None` if no working C compiler is available
+fn run_c<CellSize: BfOptimizable>(program: &[u8], input: &[u8]) -> Option<Outcome> {
+    let code =
+        BfInstructionStream::<CellSize>::optimized_from_text(program.iter().copied(), None).ok()?;
+
+    let mut c = vec![];
+    code.render_c(&mut c).ok()?;
+
+    let exe = cached_executable(&c)?;
+
+    let mut child = Command::new(exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let wrote = child.stdin.take().map(|mut stdin| stdin.write_all(input));
+    let out = child.wait_with_output().ok()?;
+
+    Some(match wrote {
+        Some(Ok(())) if out.status.success() => Ok(out.stdout),
+        _ => Err(format!("exited unsuccessfully ({})", out.status)),
+    })
+}
+
+/// Runs every probe on every available engine at this cell width, printing how each behaves
+pub fn engines<CellSize: BfOptimizable>() -> Result<(), Box<dyn std::error::Error>> {
+    let has_c = run_c::<CellSize>(b"", b"").is_some();
+
+    let mut out = io::stdout().lock();
+
+    write!(
+        out,
+        "{:<16} {:<16} {:<16}",
+        "probe", "interpreter", "stepper"
+    )?;
+    if has_c {
+        write!(out, " c")?;
+    }
+    writeln!(out)?;
+
+    for probe in PROBES {
+        let interpreter = (probe.describe)(&run_interpreter::<CellSize>(
+            probe.program,
+            probe.input,
+            false,
+        ));
+        let stepper = (probe.describe)(&run_interpreter::<CellSize>(
+            probe.program,
+            probe.input,
+            true,
+        ));
+
+        write!(out, "{:<16} {interpreter:<16} {stepper:<16}", probe.name)?;
+
+        if has_c {
+            // compiled C does not check pointer bounds, running these would be undefined behaviour
+            let c = if probe.leaves_tape {
+                "unchecked, undefined".to_owned()
+            } else {
+                run_c::<CellSize>(probe.program, probe.input).map_or_else(
+                    || "compile failed".to_owned(),
+                    |outcome| (probe.describe)(&outcome),
+                )
+            };
+
+            write!(out, " {c}")?;
+        }
+
+        writeln!(out)?;
+    }
+
+    if !has_c {
+        writeln!(out, "no working C compiler found, the c engine was skipped")?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use core::fmt;
+use std::io::{self, Write};
+
+use bfirs::compiler::{BfInstruc, BfInstructionStream, BfOptimizable};
+use bfirs::token::{is_op, LineIndex};
+
+const SNIPPET_LEN: usize = 20;
+
+fn describe<T: fmt::Display>(instruc: &BfInstruc<T>, ops: usize) -> Option<String> {
+    use BfInstruc::*;
+
+    Some(match instruc {
+        IncBy(v) => format!("grouped {ops} increments into a single add of {v}"),
+        DecBy(v) => format!("grouped {ops} decrements into a single subtract of {v}"),
+        IncPtrBy(v) => format!("grouped {ops} right moves into a single move of {v}"),
+        DecPtrBy(v) => format!("grouped {ops} left moves into a single move of {v}"),
+        Zero => "folded clear loop to Zero".to_owned(),
+        Set(v) => format!("folded clear loop and constant into Set {v}"),
+        ReadN(v) => format!("grouped {v} read and move pairs into a single bulk read"),
+        WriteConst(v) => format!(
+            "folded constant writes into a write of \"{}\"",
+            v.as_bytes().escape_ascii()
+        ),
+        _ => return None,
+    })
+}
+
+/// Prints every region of `code` that the optimizer changed, and what it changed it to
+pub fn explain<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stream = BfInstructionStream::<CellSize>::optimized_from_text_spanned(code, arr_len)?;
+    let spans = stream.spans().expect("spanned streams always have spans");
+
+    let lines = LineIndex::new(code);
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+
+    for (instruc, span) in stream.iter().zip(spans) {
+        let ops: Vec<u8> = code[span.clone()]
+            .iter()
+            .copied()
+            .filter(|&b| is_op(b))
+            .collect();
+
+        if ops.len() < 2 {
+            continue;
+        }
+
+        let Some(description) = describe(instruc, ops.len()) else {
+            continue;
+        };
+
+        let (line, col) = lines.line_col(span.start);
+
+        let snippet = if ops.len() > SNIPPET_LEN {
+            format!("{}...", String::from_utf8_lossy(&ops[..SNIPPET_LEN - 3]))
+        } else {
+            String::from_utf8_lossy(&ops).into_owned()
+        };
+
+        writeln!(
+            out,
+            "{line:>5}:{col:<4} {snippet:<SNIPPET_LEN$} {description}"
+        )?;
+    }
+
+    writeln!(
+        out,
+        "{} source instructions optimized into {} instructions",
+        code.iter().filter(|&&b| is_op(b)).count(),
+        stream.len()
+    )?;
+
+    out.flush()?;
+
+    Ok(())
+}
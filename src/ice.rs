@@ -0,0 +1,137 @@
+use core::fmt;
+use std::{
+    cell::{Cell, RefCell},
+    panic::{self, AssertUnwindSafe},
+    sync::Once,
+};
+
+use bfirs::compiler;
+
+const ISSUE_TRACKER: &str = "https://github.com/ultrabear/bfirs/issues";
+
+thread_local! {
+    static PANIC_DETAILS: RefCell<Option<(String, Option<String>, &'static str)>> =
+        const { RefCell::new(None) };
+    /// whether this thread is inside [`catch`]
+    static CATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// An internal compiler error, raised when the optimizer or a synthesizer panics
+#[derive(thiserror::Error, Debug)]
+pub struct Ice {
+    message: String,
+    location: Option<String>,
+    pass: &'static str,
+    program_hash: u64,
+    program_len: usize,
+}
+
+impl fmt::Display for Ice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "internal compiler error: {}", self.message)?;
+
+        if let Some(location) = &self.location {
+            writeln!(f, "  at: {location}")?;
+        }
+
+        writeln!(f, "  during pass: {}", self.pass)?;
+        writeln!(
+            f,
+            "  program hash: {:016x} (fnv1a-64, {} bytes)",
+            self.program_hash, self.program_len
+        )?;
+        write!(
+            f,
+            "this is a bug in bf, please file an issue at {ISSUE_TRACKER} including the program hash above, the program if possible, and the arguments passed to bf"
+        )
+    }
+}
+
+/// FNV-1a, chosen because it is stable across rust versions and platforms so reported hashes can be compared
+pub fn fnv1a(data: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    data.iter()
+        .fold(OFFSET, |hash, &b| (hash ^ u64::from(b)).wrapping_mul(PRIME))
+}
+
+/// Installs the panic hook that records panics inside [`catch`] for its report. Panics anywhere
+/// else, including on other threads, go to the hook that was installed before it. Only the first
+/// call has any effect.
+pub fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let prev_hook = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            if !CATCHING.with(Cell::get) {
+                prev_hook(info);
+                return;
+            }
+
+            let payload = info.payload();
+
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| (*s).to_owned())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "<non string panic payload>".to_owned());
+
+            let location = info.location().map(ToString::to_string);
+
+            // read the pass before unwinding restores the one that was running before it
+            let pass = compiler::current_pass();
+
+            PANIC_DETAILS.with(|details| *details.borrow_mut() = Some((message, location, pass)));
+        }));
+    });
+}
+
+/// Runs `func`, converting any panic raised inside of it into an [`Ice`] report for the given program
+///
+/// # Errors
+/// This function errors if `func` panics
+pub fn catch<R>(program: &[u8], func: impl FnOnce() -> R) -> Result<R, Ice> {
+    install_hook();
+
+    let was_catching = CATCHING.with(|catching| catching.replace(true));
+    let res = panic::catch_unwind(AssertUnwindSafe(func));
+    CATCHING.with(|catching| catching.set(was_catching));
+
+    res.map_err(|_| {
+        let (message, location, pass) = PANIC_DETAILS
+            .with(|details| details.borrow_mut().take())
+            .unwrap_or_else(|| ("<panic details were lost>".to_owned(), None, "unknown"));
+
+        Ice {
+            message,
+            location,
+            pass,
+            program_hash: fnv1a(program),
+            program_len: program.len(),
+        }
+    })
+}
+
+#[test]
+fn test_catch() {
+    // threads catching at the same time each get the report for their own panic
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let ice = catch(b"+", || panic!("panic {i}")).unwrap_err();
+                assert_eq!(ice.message, format!("panic {i}"));
+                assert_eq!(ice.pass, "none");
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(catch(b"+", || 1).unwrap(), 1);
+    assert!(!CATCHING.with(Cell::get));
+}
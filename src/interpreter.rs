@@ -1,8 +1,16 @@
 use core::fmt;
-use std::{hint::black_box, io};
+use std::{
+    hint::black_box,
+    io::{self, IsTerminal, Write},
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use thiserror::Error;
 
-use crate::compiler::BfOptimizable;
+use crate::compiler::{BfExecState, BfOptimizable, Eof};
 
 use super::compiler::BfInstruc;
 
@@ -23,6 +31,25 @@ pub struct BrainFuckExecutorBuilder<T, I, O> {
     starting_ptr: Option<usize>,
     fill: Option<T>,
     instruction_limit: Option<u64>,
+    flush_interval: Option<time::Duration>,
+    flush_before_read: Option<bool>,
+    tape: Option<Box<[T]>>,
+    capture_output: bool,
+    wrap_tape: bool,
+    eof: Eof,
+    io: IoMode,
+    cancel: Option<CancelToken>,
+}
+
+/// How `.` and `,` turn cells into output and input into cells
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IoMode {
+    /// a cell is written as its low byte, and each byte read is stored as is
+    #[default]
+    Bytes,
+    /// a cell is written as a decimal number and a newline, and reads parse a decimal number,
+    /// skipping whitespace before it and consuming the byte after it
+    Numeric,
 }
 
 impl<T: Clone, I: io::Read, O: io::Write> Default for BrainFuckExecutorBuilder<T, I, O> {
@@ -41,32 +68,73 @@ impl<T: Clone, I: io::Read, O: io::Write> BrainFuckExecutorBuilder<T, I, O> {
             starting_ptr: None,
             fill: None,
             instruction_limit: None,
+            flush_interval: None,
+            flush_before_read: None,
+            tape: None,
+            capture_output: false,
+            wrap_tape: false,
+            eof: Eof::Zero,
+            io: IoMode::Bytes,
+            cancel: None,
         }
     }
 
     /// Builds the executor
     ///
     /// # Errors
-    /// This function will error if no stream in/out is specified or if no array size is specified
-    pub fn build(self) -> Result<BrainFuckExecutor<T, I, O>, ExecutorBuilderError>
+    /// This function will error if no stream in/out is specified or if neither an array size nor a
+    /// tape is specified
+    pub fn build(mut self) -> Result<BrainFuckExecutor<T, I, O>, ExecutorBuilderError>
     where
         T: Default,
     {
-        use ExecutorBuilderError::{NoArraySize, NoStreamIn, NoStreamOut};
+        let data = match self.tape.take() {
+            Some(tape) => tape,
+            // a zero filled tape of plain integers is allocated zeroed, which the OS commits a page
+            // at a time as the program first touches it, so huge tapes cost only what is used
+            None => vec![
+                self.fill.take().unwrap_or_default();
+                self.array_len.ok_or(ExecutorBuilderError::NoArraySize)?
+            ]
+            .into_boxed_slice(),
+        };
+
+        self.build_with_tape(data)
+    }
+
+    /// Builds the executor over `tape`, ignoring `array_len`, `fill` and any tape given to
+    /// [`Self::tape`]
+    ///
+    /// # Errors
+    /// This function will error if no stream in/out is specified
+    pub fn build_with_tape<D>(
+        self,
+        tape: D,
+    ) -> Result<BrainFuckExecutor<T, I, O, D>, ExecutorBuilderError> {
+        use ExecutorBuilderError::{NoStreamIn, NoStreamOut};
 
         let s_out = self.stdout.ok_or(NoStreamOut)?;
         let s_in = self.stdin.ok_or(NoStreamIn)?;
-        let array_len = self.array_len.ok_or(NoArraySize)?;
 
         Ok(BrainFuckExecutor {
-            data: std::iter::repeat(self.fill.unwrap_or_default())
-                .take(array_len)
-                .collect(),
+            data: tape,
             stdin: s_in,
             stdout: s_out,
             ptr: self.starting_ptr.unwrap_or(0),
             last_flush: time::Instant::now(),
+            flush_interval: self
+                .flush_interval
+                .unwrap_or(BrainFuckExecutor::<T, I, O, D>::DEFAULT_FLUSH_INTERVAL),
+            flush_before_read: self.flush_before_read.unwrap_or(true),
             instruction_limit: self.instruction_limit.unwrap_or(0),
+            instructions_run: 0,
+            captured: self.capture_output.then(Vec::new),
+            wrap_tape: self.wrap_tape,
+            eof: self.eof,
+            io: self.io,
+            cancel: self.cancel,
+            calls: vec![],
+            cells: PhantomData,
         })
     }
 
@@ -111,6 +179,124 @@ impl<T: Clone, I: io::Read, O: io::Write> BrainFuckExecutorBuilder<T, I, O> {
 
         self
     }
+
+    /// Sets the minimum time between output flushes while writing, a zero interval flushes after
+    /// every write
+    #[must_use]
+    pub const fn flush_interval(mut self, interval: time::Duration) -> Self {
+        self.flush_interval = Some(interval);
+
+        self
+    }
+
+    /// Sets whether output is flushed before every read so prompts are visible, defaults to true
+    #[must_use]
+    pub const fn flush_before_read(mut self, flush: bool) -> Self {
+        self.flush_before_read = Some(flush);
+
+        self
+    }
+
+    /// Uses `tape` as the initial data array, overriding `array_len` and `fill`
+    #[must_use]
+    pub fn tape(mut self, tape: Box<[T]>) -> Self {
+        self.tape = Some(tape);
+
+        self
+    }
+
+    /// Tees everything written to the output stream into a buffer read by
+    /// [`BrainFuckExecutor::take_output`], so output from before an error is still available
+    #[must_use]
+    pub const fn capture_output(mut self, capture: bool) -> Self {
+        self.capture_output = capture;
+
+        self
+    }
+
+    /// Makes the pointer wrap around to the other end of the tape instead of failing with
+    /// [`BfExecErrorTy::Overflow`] or [`BfExecErrorTy::Underflow`] when it leaves the tape
+    #[must_use]
+    pub const fn wrap_tape(mut self, wrap: bool) -> Self {
+        self.wrap_tape = wrap;
+
+        self
+    }
+
+    /// Sets what reads store once input has run out, defaults to zero
+    #[must_use]
+    pub const fn eof(mut self, eof: Eof) -> Self {
+        self.eof = eof;
+
+        self
+    }
+
+    /// Sets how cells are written and input is read, defaults to bytes
+    #[must_use]
+    pub const fn io(mut self, io: IoMode) -> Self {
+        self.io = io;
+
+        self
+    }
+
+    /// Checks `token` every [`BrainFuckExecutor::CANCEL_CHECK_INTERVAL`] instructions while
+    /// running, stopping with [`BfExecErrorTy::Cancelled`] once it is cancelled
+    #[must_use]
+    pub fn cancel(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+
+        self
+    }
+}
+
+/// A flag shared between threads that stops every executor holding it, so a long running program
+/// can be aborted without killing the process
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops executors holding this token at their next check, this can not be undone
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns a guard that cancels this token when dropped. Holding the guard in an async task
+    /// that awaits a blocking run, such as one started by tokio's `spawn_blocking`, stops the run
+    /// when the task is aborted or its future is dropped.
+    #[must_use]
+    pub fn drop_guard(self) -> CancelOnDrop {
+        CancelOnDrop(self)
+    }
+}
+
+/// Cancels its [`CancelToken`] when dropped, see [`CancelToken::drop_guard`]
+#[derive(Debug)]
+pub struct CancelOnDrop(CancelToken);
+
+impl CancelOnDrop {
+    /// Drops the guard without cancelling, returning the token
+    #[must_use]
+    pub fn disarm(self) -> CancelToken {
+        let token = self.0.clone();
+        std::mem::forget(self);
+        token
+    }
+}
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
 }
 
 #[derive(Debug, Error)]
@@ -135,26 +321,177 @@ pub enum BfExecErrorTy {
     InitOverflow,
     #[error("not enough instructions to complete this task, halted before completion")]
     NotEnoughInstructions,
+    #[error("execution was cancelled before completion")]
+    Cancelled,
     #[error("an IO error was encountered {0:?}")]
     IOError(#[from] io::Error),
 }
 
 use std::time;
 
-pub struct BrainFuckExecutor<T, I, O>
+/// Reads a decimal number for [`IoMode::Numeric`], returning whether it was negative and its
+/// digits, or `None` if input ended before one started. Whitespace before the number is skipped
+/// and the byte after it is consumed.
+pub(crate) fn read_decimal(stdin: &mut impl io::Read) -> io::Result<Option<(bool, Vec<u8>)>> {
+    let mut byte = [0];
+    let mut next =
+        || -> io::Result<Option<u8>> { Ok((stdin.read(&mut byte)? != 0).then_some(byte[0])) };
+
+    let mut b = next()?;
+    while b.is_some_and(|b| b.is_ascii_whitespace()) {
+        b = next()?;
+    }
+
+    let negative = b == Some(b'-');
+    if negative {
+        b = next()?;
+    }
+
+    let mut digits = vec![];
+
+    while let Some(d @ b'0'..=b'9') = b {
+        digits.push(d);
+        b = next()?;
+    }
+
+    match (digits.is_empty(), b, negative) {
+        (true, None, false) => Ok(None),
+        (true, _, _) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "expected a decimal number in input",
+        )),
+        _ => Ok(Some((negative, digits))),
+    }
+}
+
+/// The cell holding the low bits of `value`
+pub(crate) fn wrap_u64<T: BfOptimizable>(value: u64) -> T {
+    T::try_from(value & (u64::MAX >> (64 - T::BITS)))
+        .unwrap_or_else(|_| unreachable!("masked to the width of a cell"))
+}
+
+/// The cell a number read by [`read_decimal`] wraps to
+pub(crate) fn wrap_decimal<T: BfOptimizable>(negative: bool, digits: &[u8]) -> T {
+    let mut value = digits.iter().fold(0u64, |value, d| {
+        value.wrapping_mul(10).wrapping_add(u64::from(d - b'0'))
+    });
+
+    if negative {
+        value = value.wrapping_neg();
+    }
+
+    wrap_u64(value)
+}
+
+/// Storage for the cells of a [`BrainFuckExecutor`], so the same instruction loop runs over a flat
+/// tape or over a [`ChunkedTape`](crate::chunked::ChunkedTape)
+pub trait Tape<T> {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the cell at `idx`
+    ///
+    /// # Panics
+    /// Panics if `idx` is past the end of the tape
+    fn get(&self, idx: usize) -> T;
+
+    /// Returns the cell at `idx` to write to
+    ///
+    /// # Panics
+    /// Panics if `idx` is past the end of the tape
+    fn get_mut(&mut self, idx: usize) -> &mut T;
+
+    /// Returns the cell at `idx` without checking it is within the tape, which storage that can
+    /// skip the check overrides
+    ///
+    /// # Safety
+    /// `idx` must be less than [`Tape::len`]
+    unsafe fn get_unchecked(&self, idx: usize) -> T {
+        self.get(idx)
+    }
+
+    /// Returns the cell at `idx` to write to without checking it is within the tape
+    ///
+    /// # Safety
+    /// `idx` must be less than [`Tape::len`]
+    unsafe fn get_unchecked_mut(&mut self, idx: usize) -> &mut T {
+        self.get_mut(idx)
+    }
+}
+
+impl<T: Copy> Tape<T> for Box<[T]> {
+    fn len(&self) -> usize {
+        <[T]>::len(self)
+    }
+
+    fn get(&self, idx: usize) -> T {
+        self[idx]
+    }
+
+    fn get_mut(&mut self, idx: usize) -> &mut T {
+        &mut self[idx]
+    }
+
+    unsafe fn get_unchecked(&self, idx: usize) -> T {
+        // SAFETY: The caller has asserted that `idx` is within the tape
+        *<[T]>::get_unchecked(self, idx)
+    }
+
+    unsafe fn get_unchecked_mut(&mut self, idx: usize) -> &mut T {
+        // SAFETY: The caller has asserted that `idx` is within the tape
+        <[T]>::get_unchecked_mut(self, idx)
+    }
+}
+
+pub struct BrainFuckExecutor<T, I, O, D = Box<[T]>>
 where
     O: io::Write,
     I: io::Read,
 {
     pub stdout: O,
     pub stdin: I,
-    pub data: Box<[T]>,
+    pub data: D,
     pub ptr: usize,
     pub last_flush: time::Instant,
+    pub flush_interval: time::Duration,
+    pub flush_before_read: bool,
     pub instruction_limit: u64,
+    /// instructions run by limited runs so far, which the clock extension reads
+    pub instructions_run: u64,
+    /// a copy of all output written, when capturing is enabled by the builder
+    pub captured: Option<Vec<u8>>,
+    /// the pointer wraps around the ends of the tape rather than leaving it, when set by the
+    /// builder
+    pub wrap_tape: bool,
+    /// what reads store once input has run out
+    pub eof: Eof,
+    /// how cells are written and input is read
+    pub io: IoMode,
+    /// checked between chunks of instructions while running, when set by the builder
+    pub cancel: Option<CancelToken>,
+    /// where each running block was called from, innermost last, see
+    /// [`BfInstructionStream::dedup_loops`](crate::compiler::BfInstructionStream::dedup_loops)
+    pub calls: Vec<usize>,
+    /// the cell type, which only appears in the tape storage
+    cells: PhantomData<T>,
+}
+
+impl<T, I: io::Read, O: io::Write, D> BrainFuckExecutor<T, I, O, D> {
+    /// Flush interval used when none is given to the builder, based on a 60 fps update
+    pub const DEFAULT_FLUSH_INTERVAL: time::Duration = time::Duration::from_millis(16);
+
+    /// Instructions run between checks of the cancellation token, a few milliseconds of work
+    pub const CANCEL_CHECK_INTERVAL: u64 = 1 << 20;
+
+    /// Cells either side of the pointer that `#` dumps with the debug extension
+    pub const DEBUG_RADIUS: usize = 8;
 }
 
 impl BrainFuckExecutor<(), io::Stdin, io::Stdout> {
+    /// Output is only flushed before reads when stdin is a terminal
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
     pub fn new_stdio<T: Clone + Default>(
@@ -164,11 +501,13 @@ impl BrainFuckExecutor<(), io::Stdin, io::Stdout> {
             .stream_in(io::stdin())
             .stream_out(io::stdout())
             .array_len(array_len)
+            .flush_before_read(io::stdin().is_terminal())
             .build()
             // This panic should not occur because the builder has been constructed with at least the minimum amount of required fields
             .expect("this panic should not occur, minimum builder fields are present")
     }
 
+    /// Output is only flushed before reads when stdin is a terminal
     #[allow(clippy::missing_panics_doc)]
     #[must_use]
     pub fn new_stdio_locked<'i, 'o, T: Clone + Default>(
@@ -178,6 +517,7 @@ impl BrainFuckExecutor<(), io::Stdin, io::Stdout> {
             .stream_in(io::stdin().lock())
             .stream_out(io::stdout().lock())
             .array_len(array_len)
+            .flush_before_read(io::stdin().is_terminal())
             .build()
             // This panic should not occur because the builder has been constructed with at least the minimum amount of required fields
             .expect("this panic should not occur, minimum builder fields are present")
@@ -193,7 +533,7 @@ impl fmt::Display for Overflow {
     }
 }
 
-impl<T, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
+impl<T, I: io::Read, O: io::Write, D> BrainFuckExecutor<T, I, O, D> {
     /// Adds to instruction limit that is decremented each time `run_limited` is run
     ///
     /// # Errors
@@ -207,6 +547,16 @@ impl<T, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
         self.instruction_limit
     }
 
+    /// Returns all output captured since the last call, empty if capturing is not enabled
+    pub fn take_output(&mut self) -> Vec<u8> {
+        self.captured
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+impl<T, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
     pub const fn state(&self) -> (usize, &[T]) {
         (self.ptr, &self.data)
     }
@@ -220,11 +570,11 @@ impl<T, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
     }
 }
 
-impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
+impl<T: BfOptimizable, I: io::Read, O: io::Write, D: Tape<T>> BrainFuckExecutor<T, I, O, D> {
     unsafe fn cur_unchecked(&self) -> T {
         // SAFETY: The caller has asserted that the current pointer is a valid index
         debug_assert!(self.ptr < self.data.len());
-        *self.data.get_unchecked(self.ptr)
+        self.data.get_unchecked(self.ptr)
     }
 
     unsafe fn map_current(&mut self, func: impl FnOnce(T) -> T) {
@@ -233,17 +583,33 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
         *self.data.get_unchecked_mut(self.ptr) = func(self.cur_unchecked());
     }
 
-    fn inc_ptr_by(&mut self, v: usize) -> Result<(), BfExecErrorTy> {
+    fn inc_ptr_by<const CHECK_BOUNDS: bool>(&mut self, v: usize) -> Result<(), BfExecErrorTy> {
         self.ptr += v;
-        if self.ptr >= self.data.len() {
+        if CHECK_BOUNDS && self.ptr >= self.data.len() {
+            if self.wrap_tape {
+                self.ptr %= self.data.len();
+                return Ok(());
+            }
+
             self.ptr -= v;
             return Err(BfExecErrorTy::Overflow);
         }
         Ok(())
     }
 
-    fn dec_ptr_by(&mut self, v: usize) -> Result<(), BfExecErrorTy> {
-        self.ptr = self.ptr.checked_sub(v).ok_or(BfExecErrorTy::Underflow)?;
+    fn dec_ptr_by<const CHECK_BOUNDS: bool>(&mut self, v: usize) -> Result<(), BfExecErrorTy> {
+        if CHECK_BOUNDS {
+            self.ptr = match self.ptr.checked_sub(v) {
+                Some(ptr) => ptr,
+                None if self.wrap_tape => {
+                    let len = self.data.len();
+                    (self.ptr + len - v % len) % len
+                }
+                None => return Err(BfExecErrorTy::Underflow),
+            };
+        } else {
+            self.ptr -= v;
+        }
         Ok(())
     }
 
@@ -254,8 +620,79 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
     fn write(&mut self, v: u8) -> Result<(), BfExecErrorTy> {
         let _ = self.stdout.write(&[v])?;
 
-        // based on 60 fps update (actual 62.5)
-        if self.last_flush.elapsed().as_millis() > 16 {
+        // only output that reached stdout is captured
+        if let Some(captured) = &mut self.captured {
+            captured.push(v);
+        }
+
+        self.flush_if_due()
+    }
+
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn write_cell(&mut self, v: T) -> Result<(), BfExecErrorTy> {
+        match self.io {
+            IoMode::Bytes => self.write(v.truncate_u8()),
+            IoMode::Numeric => self.write_all(format!("{v}\n").as_bytes()),
+        }
+    }
+
+    fn write_const(&mut self, v: &[u8]) -> Result<(), BfExecErrorTy> {
+        match self.io {
+            IoMode::Bytes => self.write_all(v),
+            IoMode::Numeric => {
+                for &b in v {
+                    // constant writes hold the bit pattern of a cell that fit in a byte
+                    let cell = T::try_from(u64::from(b))
+                        .unwrap_or_else(|_| unreachable!("every cell width holds a byte"));
+
+                    self.write_cell(cell)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the pointer and the cells within [`Self::DEBUG_RADIUS`] of it to stderr, after
+    /// flushing stdout so the dump lands after any output before it
+    fn debug_dump(&mut self) -> Result<(), BfExecErrorTy> {
+        self.stdout.flush()?;
+        self.last_flush = time::Instant::now();
+
+        let start = self.ptr.saturating_sub(Self::DEBUG_RADIUS);
+        let end = (self.ptr + Self::DEBUG_RADIUS).min(self.data.len() - 1);
+
+        let mut stderr = io::stderr().lock();
+        write!(stderr, "#: ptr {}, cells {start}..={end}:", self.ptr)?;
+
+        for at in start..=end {
+            if at == self.ptr {
+                write!(stderr, " [{}]", self.data.get(at))?;
+            } else {
+                write!(stderr, " {}", self.data.get(at))?;
+            }
+        }
+
+        writeln!(stderr)?;
+
+        Ok(())
+    }
+
+    fn write_all(&mut self, v: &[u8]) -> Result<(), BfExecErrorTy> {
+        self.stdout.write_all(v)?;
+
+        if let Some(captured) = &mut self.captured {
+            captured.extend_from_slice(v);
+        }
+
+        self.flush_if_due()
+    }
+
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn flush_if_due(&mut self) -> Result<(), BfExecErrorTy> {
+        if self.last_flush.elapsed() >= self.flush_interval {
             self.stdout.flush()?;
             self.last_flush = time::Instant::now();
         }
@@ -263,23 +700,150 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
         Ok(())
     }
 
-    fn read(&mut self) -> Result<u8, BfExecErrorTy> {
+    /// the value a read past the end of input stores, or `None` if it leaves the cell alone
+    fn eof_value(&self) -> Option<T> {
+        match self.eof {
+            Eof::Zero => Some(T::ZERO),
+            Eof::MinusOne => Some(T::ZERO.wrapping_sub(T::from(1))),
+            Eof::Unchanged => None,
+        }
+    }
+
+    fn read(&mut self) -> Result<Option<T>, BfExecErrorTy> {
         // flush so the end user always gets prompts
-        self.stdout.flush()?;
+        if self.flush_before_read {
+            self.stdout.flush()?;
+        }
+
+        if self.io == IoMode::Numeric {
+            return self.read_number();
+        }
 
         let mut v = [0];
-        let _ = self.stdin.read(&mut v)?;
-        Ok(v[0])
+        match self.stdin.read(&mut v)? {
+            0 => Ok(self.eof_value()),
+            _ => Ok(Some(v[0].into())),
+        }
+    }
+
+    /// reads a decimal number, wrapping it to the width of a cell
+    fn read_number(&mut self) -> Result<Option<T>, BfExecErrorTy> {
+        let Some((negative, digits)) = read_decimal(&mut self.stdin)? else {
+            return Ok(self.eof_value());
+        };
+
+        Ok(Some(wrap_decimal(negative, &digits)))
+    }
+
+    /// Reads `n` bytes into the current and following cells and moves the pointer past them, as
+    /// `n` repetitions of `,>` would. Input is read in chunks rather than a byte at a time.
+    fn read_n<const CHECK_BOUNDS: bool>(&mut self, n: usize) -> Result<(), BfExecErrorTy> {
+        // the unfolded instructions fill every cell up to the end of the tape before failing to
+        // move off of it
+        let fits = self.ptr + n < self.data.len();
+
+        // a wrapping pointer comes back around to cells that were already read into, and numbers
+        // are read one at a time
+        if (CHECK_BOUNDS && !fits && self.wrap_tape) || self.io == IoMode::Numeric {
+            for _ in 0..n {
+                if let Some(v) = self.read()? {
+                    *self.data.get_mut(self.ptr) = v;
+                }
+                self.inc_ptr_by::<true>(1)?;
+            }
+
+            return Ok(());
+        }
+        let cells = if CHECK_BOUNDS && !fits {
+            self.data.len() - self.ptr
+        } else {
+            n
+        };
+
+        if self.flush_before_read {
+            self.stdout.flush()?;
+        }
+
+        let mut buf = [0u8; 256];
+        let mut done = 0;
+        let eof = self.eof_value();
+
+        while done < cells {
+            let chunk = &mut buf[..(cells - done).min(256)];
+            let mut got = 0;
+
+            while got < chunk.len() {
+                match self.stdin.read(&mut chunk[got..])? {
+                    0 => break,
+                    read => got += read,
+                }
+            }
+
+            let start = self.ptr + done;
+
+            for (at, &b) in chunk[..got].iter().enumerate() {
+                *self.data.get_mut(start + at) = b.into();
+            }
+
+            // input past EOF is stored like `read` stores it
+            if let Some(v) = eof {
+                for at in got..chunk.len() {
+                    *self.data.get_mut(start + at) = v;
+                }
+            }
+
+            done += chunk.len();
+        }
+
+        if CHECK_BOUNDS && !fits {
+            self.ptr = self.data.len() - 1;
+            return Err(BfExecErrorTy::Overflow);
+        }
+
+        self.ptr += n;
+        Ok(())
+    }
+
+    /// instructions run before the current one, for a run that started with `start_limit`
+    /// instructions left. Only limited runs count their instructions.
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn instructions_so_far<const LIMIT_INSTRUCTIONS: bool>(&self, start_limit: u64) -> u64 {
+        if LIMIT_INSTRUCTIONS {
+            self.instructions_run + (start_limit - self.instruction_limit)
+        } else {
+            self.instructions_run
+        }
+    }
+
+    // adds what a limited run ran to `instructions_run`, inlined like the loop it wraps
+    #[allow(clippy::inline_always)]
+    #[inline(always)]
+    fn internal_run<const LIMIT_INSTRUCTIONS: bool, const CHECK_BOUNDS: bool>(
+        &mut self,
+        stream: &[BfInstruc<T>],
+        idx: usize,
+    ) -> Result<(), BfExecError> {
+        let start_limit = self.instruction_limit;
+        let res =
+            self.internal_run_from::<LIMIT_INSTRUCTIONS, CHECK_BOUNDS>(stream, idx, start_limit);
+
+        if LIMIT_INSTRUCTIONS {
+            self.instructions_run += start_limit - self.instruction_limit;
+        }
+
+        res
     }
 
     // this inline(always) measurably increases performance (8.9s to 7.2s on mandelbrot) most probably
     // because if its not inlined it cant get enough context to optimize for what its being called
     // with (like the runtime const arguments that run and run_limited pass)
     #[inline(always)]
-    fn internal_run<const LIMIT_INSTRUCTIONS: bool>(
+    fn internal_run_from<const LIMIT_INSTRUCTIONS: bool, const CHECK_BOUNDS: bool>(
         &mut self,
         stream: &[BfInstruc<T>],
         mut idx: usize,
+        start_limit: u64,
     ) -> Result<(), BfExecError> {
         use BfInstruc::*;
 
@@ -322,12 +886,13 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
                         self.map_current(|c| c.wrapping_sub(T::from(1)));
                         Ok(())
                     }
-                    IncPtr => self.inc_ptr_by(1),
-                    DecPtr => self.dec_ptr_by(1),
-                    Write => self.write(self.cur_unchecked().truncate_u8()),
+                    IncPtr => self.inc_ptr_by::<CHECK_BOUNDS>(1),
+                    DecPtr => self.dec_ptr_by::<CHECK_BOUNDS>(1),
+                    Write => self.write_cell(self.cur_unchecked()),
                     Read => {
-                        let v = self.read()?.into();
-                        self.map_current(|_| v);
+                        if let Some(v) = self.read()? {
+                            self.map_current(|_| v);
+                        }
                         Ok(())
                     }
                     LStart(end) => {
@@ -350,8 +915,37 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
                         self.map_current(|c| c.wrapping_sub(val));
                         Ok(())
                     }
-                    IncPtrBy(val) => self.inc_ptr_by(val.get() as usize),
-                    DecPtrBy(val) => self.dec_ptr_by(val.get() as usize),
+                    IncPtrBy(val) => self.inc_ptr_by::<CHECK_BOUNDS>(val.get() as usize),
+                    DecPtrBy(val) => self.dec_ptr_by::<CHECK_BOUNDS>(val.get() as usize),
+                    Set(val) => {
+                        self.map_current(|_| val);
+                        Ok(())
+                    }
+                    ReadN(val) => self.read_n::<CHECK_BOUNDS>(val.get() as usize),
+                    WriteConst(bytes) => self.write_const(bytes.as_bytes()),
+                    Debug => self.debug_dump(),
+                    Halt => {
+                        // past the end, so the loop stops once this instruction is counted
+                        idx = stream.len();
+                        Ok(())
+                    }
+                    Call(start) => {
+                        self.calls.push(idx);
+                        // blocks never start the stream, and are entered by stepping past the
+                        // instruction before them
+                        idx = start as usize - 1;
+                        Ok(())
+                    }
+                    Return => {
+                        // returning from the program itself ends it, like halting
+                        idx = self.calls.pop().unwrap_or(stream.len());
+                        Ok(())
+                    }
+                    Clock => {
+                        let run = self.instructions_so_far::<LIMIT_INSTRUCTIONS>(start_limit);
+                        self.map_current(|_| wrap_u64(run));
+                        Ok(())
+                    }
                 })()
                 .map_err(|source| BfExecError { source, idx })?;
             }
@@ -366,12 +960,82 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
         Ok(())
     }
 
+    /// Runs the stream from `idx`, flushing all prior output if execution stops with an error so
+    /// nothing written before the error is left sitting in a buffer
+    fn run_flushing<const LIMIT_INSTRUCTIONS: bool, const CHECK_BOUNDS: bool>(
+        &mut self,
+        stream: &[BfInstruc<T>],
+        idx: usize,
+    ) -> Result<(), BfExecError> {
+        // a run from the start never returns into blocks left running by an earlier failed run
+        if idx == 0 {
+            self.calls.clear();
+        }
+
+        let res = match self.cancel.clone() {
+            Some(token) => {
+                self.run_cancellable::<LIMIT_INSTRUCTIONS, CHECK_BOUNDS>(stream, idx, &token)
+            }
+            None => self.internal_run::<LIMIT_INSTRUCTIONS, CHECK_BOUNDS>(stream, idx),
+        };
+
+        if res.is_err() {
+            // the original error is more useful than any error flushing raises
+            _ = self.stdout.flush();
+        }
+
+        res
+    }
+
+    /// Runs the stream from `idx` in chunks of [`BrainFuckExecutor::CANCEL_CHECK_INTERVAL`]
+    /// instructions through the limited loop, checking `token` between them. This keeps the
+    /// check out of the main loops, which only pay for it when a token is set.
+    fn run_cancellable<const LIMIT_INSTRUCTIONS: bool, const CHECK_BOUNDS: bool>(
+        &mut self,
+        stream: &[BfInstruc<T>],
+        mut idx: usize,
+        token: &CancelToken,
+    ) -> Result<(), BfExecError> {
+        let saved = self.instruction_limit;
+        // an unlimited run has a limit it will never reach
+        let mut left = if LIMIT_INSTRUCTIONS { saved } else { u64::MAX };
+
+        let res = loop {
+            if token.is_cancelled() {
+                break Err(BfExecError {
+                    source: BfExecErrorTy::Cancelled,
+                    idx,
+                });
+            }
+
+            let chunk = left.min(Self::CANCEL_CHECK_INTERVAL);
+            self.instruction_limit = chunk;
+
+            let res = self.internal_run::<true, CHECK_BOUNDS>(stream, idx);
+            left -= chunk - self.instruction_limit;
+
+            match res {
+                Err(BfExecError {
+                    source: BfExecErrorTy::NotEnoughInstructions,
+                    idx: at,
+                }) if left > 0 => idx = at,
+                res => break res,
+            }
+        };
+
+        self.instruction_limit = if LIMIT_INSTRUCTIONS { left } else { saved };
+
+        res
+    }
+
     /// Runs brainfuck stream unbounded, this function is not guaranteed to halt.
     ///
+    /// All output written before an error is flushed before the error is returned.
+    ///
     /// # Errors
     /// This function will error if there is an error in the in/out streams or if the data pointer overflows/underflows.
     pub fn run(&mut self, stream: &[BfInstruc<T>]) -> Result<(), BfExecError> {
-        self.internal_run::<false>(stream, 0)
+        self.run_flushing::<false, true>(stream, 0)
     }
 
     /// Runs brainfuck with a limited instruction count specified by [`BrainFuckExecutor::instructions_left`], this function will eventually halt.
@@ -381,7 +1045,7 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
     /// # Errors
     /// This function will error if there is an error in the in/out streams, if the data pointer overflows/underflows, or if the instruction limit is reached before execution ends.
     pub fn run_limited(&mut self, stream: &[BfInstruc<T>]) -> Result<(), BfExecError> {
-        self.internal_run::<true>(stream, 0)
+        self.run_flushing::<true, true>(stream, 0)
     }
 
     /// Runs brainfuck with a limited instruction count specified by [`BrainFuckExecutor::instructions_left`], this function will eventually halt.
@@ -398,9 +1062,74 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
         stream: &[BfInstruc<T>],
         start: usize,
     ) -> Result<(), BfExecError> {
-        self.internal_run::<true>(stream, start)
+        self.run_flushing::<true, true>(stream, start)
     }
 
+    /// Runs brainfuck stream unbounded without checking that the data pointer stays within the
+    /// tape, this function is not guaranteed to halt.
+    ///
+    /// # Safety
+    /// The data pointer must never leave the tape while running `stream` from the current state,
+    /// even when [`BrainFuckExecutor::wrap_tape`] is set, as unchecked moves never wrap.
+    /// [`analysis::prove_pointer_bounds`](crate::analysis::prove_pointer_bounds) can establish
+    /// this for a fresh executor whose pointer starts at zero.
+    ///
+    /// # Errors
+    /// This function will error if there is an error in the in/out streams.
+    pub unsafe fn run_unchecked(&mut self, stream: &[BfInstruc<T>]) -> Result<(), BfExecError> {
+        self.run_flushing::<false, false>(stream, 0)
+    }
+
+    /// Runs brainfuck with a limited instruction count without checking that the data pointer
+    /// stays within the tape, as [`BrainFuckExecutor::run_limited`] does with checks.
+    ///
+    /// # Safety
+    /// The data pointer must never leave the tape while running `stream` from the current state,
+    /// even when [`BrainFuckExecutor::wrap_tape`] is set, as unchecked moves never wrap.
+    /// [`analysis::prove_pointer_bounds`](crate::analysis::prove_pointer_bounds) can establish
+    /// this for a fresh executor whose pointer starts at zero.
+    ///
+    /// # Errors
+    /// This function will error if there is an error in the in/out streams, or if the instruction limit is reached before execution ends.
+    pub unsafe fn run_limited_unchecked(
+        &mut self,
+        stream: &[BfInstruc<T>],
+    ) -> Result<(), BfExecError> {
+        self.run_flushing::<true, false>(stream, 0)
+    }
+
+    /// Executes the single instruction at `idx`, returning the index of the next instruction to
+    /// execute, or `None` once execution has reached the end of the stream.
+    ///
+    /// The instruction limit is left untouched, allowing callers to observe every instruction
+    /// executed without the overhead of doing so in the normal run loop. Output is flushed after
+    /// every step.
+    ///
+    /// # Errors
+    /// This function will error if there is an error in the in/out streams or if the data pointer overflows/underflows.
+    pub fn step(
+        &mut self,
+        stream: &[BfInstruc<T>],
+        idx: usize,
+    ) -> Result<Option<usize>, BfExecError> {
+        let limit = std::mem::replace(&mut self.instruction_limit, 1);
+        // reuse the limited run loop rather than inlining another copy of it, which measurably
+        // slows down the main loops
+        let res = self.run_limited_from(stream, idx);
+        self.instruction_limit = limit;
+
+        match res {
+            Ok(()) => Ok(None),
+            Err(BfExecError {
+                source: BfExecErrorTy::NotEnoughInstructions,
+                idx,
+            }) => Ok(Some(idx)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
     /// provides a calculated at runtime estimate of instruction throughput for the given mode using 100k iterations,
     /// does not take cache locality into account so will likely return higher numbers than real world data
     #[must_use]
@@ -463,6 +1192,48 @@ impl<T: BfOptimizable, I: io::Read, O: io::Write> BrainFuckExecutor<T, I, O> {
     }
 }
 
+/// The final state of a program run to completion by [`BrainFuckExecutor::run_collect`]
+#[derive(Debug)]
+pub struct BfRunResult<'a, T> {
+    /// everything the program wrote, taken out of the executors output buffer
+    pub output: Vec<u8>,
+    pub ptr: usize,
+    pub tape: &'a [T],
+}
+
+impl<T: BfOptimizable> BfRunResult<'_, T> {
+    /// The state of a completed run, as consumed by the C renderers
+    #[must_use]
+    pub const fn exec_state(&self) -> BfExecState<'_, T> {
+        BfExecState {
+            cursor: self.ptr,
+            data: self.tape,
+            instruction_pointer: None,
+        }
+    }
+}
+
+impl<T: BfOptimizable, I: io::Read> BrainFuckExecutor<T, I, Vec<u8>> {
+    /// Runs brainfuck stream unbounded like [`BrainFuckExecutor::run`], returning the output it
+    /// wrote along with the final pointer and tape. The output buffer is left empty, so the
+    /// executor can be run again.
+    ///
+    /// # Errors
+    /// This function will error if there is an error reading input or if the data pointer overflows/underflows.
+    pub fn run_collect(
+        &mut self,
+        stream: &[BfInstruc<T>],
+    ) -> Result<BfRunResult<'_, T>, BfExecError> {
+        self.run(stream)?;
+
+        Ok(BfRunResult {
+            output: std::mem::take(&mut self.stdout),
+            ptr: self.ptr,
+            tape: &self.data,
+        })
+    }
+}
+
 #[test]
 fn test_exec_env() {
     use super::compiler::BfInstructionStream;
@@ -520,3 +1291,404 @@ fn test_exec_env() {
     run_code("-");
     run_code(">>");
 }
+
+#[test]
+fn test_flush_on_error() {
+    use super::compiler::BfInstructionStream;
+
+    let code = BfInstructionStream::optimized_from_text(
+        b"++++++++[>++++++++<-]>+.>>".iter().copied(),
+        None,
+    )
+    .unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(io::empty())
+        .stream_out(io::BufWriter::new(Vec::new()))
+        .array_len(3)
+        .build()
+        .unwrap();
+
+    assert!(matches!(
+        env.run(&code),
+        Err(BfExecError {
+            source: BfExecErrorTy::Overflow,
+            ..
+        })
+    ));
+
+    assert!(env.stdout.buffer().is_empty());
+    assert_eq!(env.stdout.get_ref(), b"A");
+}
+
+#[test]
+fn test_step() {
+    use super::compiler::BfInstructionStream;
+
+    let code =
+        BfInstructionStream::optimized_from_text(b"++++[>++++<-]>.".iter().copied(), None).unwrap();
+
+    let new_env = || {
+        BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(io::empty())
+            .stream_out(Vec::new())
+            .array_len(30_000)
+            .limit(1_000)
+            .build()
+            .unwrap()
+    };
+
+    let mut stepped = new_env();
+    let mut steps = 0;
+    let mut idx = Some(0);
+
+    while let Some(i) = idx {
+        idx = stepped.step(&code, i).unwrap();
+        steps += 1;
+    }
+
+    let mut ran = new_env();
+    ran.run_limited(&code).unwrap();
+
+    assert_eq!(steps, 1_000 - ran.instructions_left());
+    assert_eq!(stepped.instructions_left(), 1_000);
+    assert_eq!(stepped.stdout, ran.stdout);
+    assert_eq!(stepped.state(), ran.state());
+}
+
+#[test]
+fn test_injected_tape() {
+    use super::compiler::BfInstructionStream;
+
+    let code = BfInstructionStream::optimized_from_text(b">.>+.".iter().copied(), None).unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(io::empty())
+        .stream_out(Vec::new())
+        .tape(vec![0, b'a', b'b'].into_boxed_slice())
+        .flush_interval(time::Duration::ZERO)
+        .build()
+        .unwrap();
+
+    env.run(&code).unwrap();
+
+    assert_eq!(env.stdout, b"ac");
+}
+
+#[test]
+fn test_run_collect() {
+    use super::compiler::BfInstructionStream;
+
+    let code = BfInstructionStream::optimized_from_text(b"+++.>++.".iter().copied(), None).unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(io::empty())
+        .stream_out(Vec::new())
+        .array_len(4)
+        .build()
+        .unwrap();
+
+    let result = env.run_collect(&code).unwrap();
+
+    assert_eq!(result.output, [3, 2]);
+    assert_eq!((result.ptr, result.tape), (1, &[3, 2, 0, 0][..]));
+    assert!(env.stdout.is_empty());
+}
+
+#[test]
+fn test_capture_output() {
+    use super::compiler::BfInstructionStream;
+
+    let code = BfInstructionStream::optimized_from_text(b"+.+.<".iter().copied(), None).unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(io::empty())
+        .stream_out(io::sink())
+        .array_len(1)
+        .capture_output(true)
+        .build()
+        .unwrap();
+
+    assert!(env.run(&code).is_err());
+    assert_eq!(env.take_output(), [1, 2]);
+    assert!(env.take_output().is_empty());
+
+    // output that fails to reach stdout is not captured
+    let mut out = [0; 1];
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(io::empty())
+        .stream_out(&mut out[..])
+        .array_len(1)
+        .capture_output(true)
+        .build()
+        .unwrap();
+
+    // both writes fold into one constant write, which does not fit in a one byte output
+    let code = BfInstructionStream::optimized_from_text(b"[-]+.+.".iter().copied(), None).unwrap();
+    assert!(env.run(&code).is_err());
+    assert!(env.take_output().is_empty());
+}
+
+#[test]
+fn test_cancel_token() {
+    use super::compiler::BfInstructionStream;
+
+    let build = |token: &CancelToken| {
+        BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(io::empty())
+            .stream_out(Vec::new())
+            .array_len(1)
+            .cancel(token.clone())
+            .build()
+            .unwrap()
+    };
+
+    let spin = BfInstructionStream::optimized_from_text(b"+[]".iter().copied(), None).unwrap();
+    let token = CancelToken::new();
+    let mut env = build(&token);
+
+    let canceller = std::thread::spawn({
+        let guard = token.clone().drop_guard();
+        move || {
+            std::thread::sleep(time::Duration::from_millis(20));
+            drop(guard);
+        }
+    });
+
+    let err = env.run(&spin).unwrap_err();
+    assert!(matches!(err.source, BfExecErrorTy::Cancelled));
+    canceller.join().unwrap();
+
+    // a limited run over several chunks stops at the same place as one without a token
+    let nest =
+        BfInstructionStream::optimized_from_text(b"-[>-[>-[>+<-]<-]<-]".iter().copied(), None)
+            .unwrap();
+    let limit = BrainFuckExecutor::<u8, io::Empty, Vec<u8>>::CANCEL_CHECK_INTERVAL * 5 / 2;
+
+    let mut plain = BrainFuckExecutor::new_stdio::<u8>(4);
+    plain.add_instruction_limit(limit).unwrap();
+    let expected = plain.run_limited(&nest).unwrap_err().idx;
+
+    let mut env = build(&CancelToken::new());
+    env.data = vec![0; 4].into_boxed_slice();
+    env.add_instruction_limit(limit).unwrap();
+    assert_eq!(env.run_limited(&nest).unwrap_err().idx, expected);
+    assert_eq!(env.state(), plain.state());
+    assert_eq!(env.instructions_left(), 0);
+}
+
+#[test]
+fn test_wrap_tape() {
+    use super::compiler::BfInstructionStream;
+
+    let run = |code: &[u8], input: &'static [u8]| {
+        let code = BfInstructionStream::optimized_from_text(code.iter().copied(), Some(3)).unwrap();
+
+        let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(input)
+            .stream_out(vec![])
+            .array_len(3)
+            .wrap_tape(true)
+            .build()
+            .unwrap();
+
+        env.run(&code).unwrap();
+        (env.ptr, env.data, env.stdout)
+    };
+
+    assert_eq!(
+        run(b"<+<<<<++>>>>>+++", b""),
+        (0, Box::from([3, 2, 1]), vec![])
+    );
+    assert_eq!(run(b">>,>,>,>", b"xyz"), (2, Box::from(*b"yzx"), vec![]));
+
+    // a tape length apart is the same cell, so constant writes can not assume they differ
+    assert_eq!(
+        run(b",[-]+>>>[-]<<<..", b""),
+        (0, Box::from([0, 0, 0]), vec![0, 0])
+    );
+}
+
+#[test]
+fn test_eof() {
+    use super::compiler::{BfInstructionStream, Eof};
+
+    let run = |code: &[u8], eof: Eof| {
+        let code = BfInstructionStream::optimized_from_text(code.iter().copied(), Some(5)).unwrap();
+
+        let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(&b"x"[..])
+            .stream_out(io::sink())
+            .array_len(5)
+            .fill(5)
+            .eof(eof)
+            .build()
+            .unwrap();
+
+        env.run(&code).unwrap();
+        env.data
+    };
+
+    // single reads, and a run of reads folded into one bulk read
+    for code in [&b",>,>,>,"[..], b",>,>,>,>"] {
+        assert_eq!(run(code, Eof::Zero)[..4], *b"x\0\0\0");
+        assert_eq!(run(code, Eof::MinusOne)[..4], *b"x\xff\xff\xff");
+        assert_eq!(run(code, Eof::Unchanged)[..4], *b"x\x05\x05\x05");
+    }
+}
+
+#[test]
+fn test_numeric_io() {
+    use super::compiler::{BfInstructionStream, I8};
+
+    // echoes three numbers, the last one wrapping, then writes constants
+    let code =
+        BfInstructionStream::optimized_from_text(b",.>,.>,.>>[-]---.+.".iter().copied(), None)
+            .unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<I8, _, _>::new()
+        .stream_in(&b"  12 -7\n130"[..])
+        .stream_out(vec![])
+        .array_len(code.reccomended_array_size())
+        .io(IoMode::Numeric)
+        .build()
+        .unwrap();
+
+    env.run(&code).unwrap();
+
+    assert_eq!(env.stdout, b"12\n-7\n-126\n-3\n-2\n");
+
+    let mut env = BrainFuckExecutorBuilder::<I8, _, _>::new()
+        .stream_in(&b"abc"[..])
+        .stream_out(vec![])
+        .array_len(1)
+        .io(IoMode::Numeric)
+        .build()
+        .unwrap();
+
+    assert!(env.run(&code).is_err());
+}
+
+#[test]
+fn test_halt() {
+    use super::compiler::BfInstructionStream;
+    use crate::token::Extensions;
+
+    let extensions = Extensions {
+        halt: true,
+        ..Extensions::default()
+    };
+
+    // halts from inside a loop that would otherwise never end
+    let code =
+        BfInstructionStream::<u8>::optimized_from_text_extended(b"+[.@]+.", None, extensions)
+            .unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(&[][..])
+        .stream_out(vec![])
+        .array_len(code.reccomended_array_size())
+        .build()
+        .unwrap();
+
+    env.run(&code).unwrap();
+
+    assert_eq!(env.stdout, [1]);
+}
+
+#[test]
+fn test_debug() {
+    use super::compiler::{BfInstruc, BfInstructionStream};
+    use crate::token::Extensions;
+
+    let extensions = Extensions {
+        debug: true,
+        ..Extensions::default()
+    };
+
+    // dumps go to stderr, leaving the tape and output as if they were not there
+    let code =
+        BfInstructionStream::<u8>::optimized_from_text_extended(b"++#>+#.", None, extensions)
+            .unwrap();
+
+    assert_eq!(code.iter().filter(|&&i| i == BfInstruc::Debug).count(), 2);
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(&[][..])
+        .stream_out(vec![])
+        .array_len(code.reccomended_array_size())
+        .build()
+        .unwrap();
+
+    env.run(&code).unwrap();
+
+    assert_eq!(env.stdout, [1]);
+    assert_eq!(env.data[..2], [2, 1]);
+}
+
+#[test]
+fn test_clock() {
+    use super::compiler::BfInstructionStream;
+    use crate::token::Extensions;
+
+    let code = BfInstructionStream::<u8>::optimized_from_text_extended(
+        b"+>+<^.>++++[-]^.",
+        Some(2),
+        Extensions {
+            clock: true,
+            ..Extensions::default()
+        },
+    )
+    .unwrap();
+
+    let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .stream_in(&[][..])
+        .stream_out(vec![])
+        .array_len(2)
+        .limit(100)
+        .build()
+        .unwrap();
+
+    env.run_limited(&code).unwrap();
+
+    // the zeroing loop is one instruction once optimized, and the adds before it are dead
+    assert_eq!(env.stdout, [4, 8]);
+    assert_eq!(env.instructions_run, 10);
+}
+
+#[test]
+fn test_flush_before_read() {
+    use super::compiler::BfInstructionStream;
+
+    struct CountFlushes(usize);
+
+    impl io::Write for CountFlushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.0 += 1;
+            Ok(())
+        }
+    }
+
+    let code = BfInstructionStream::optimized_from_text(b",.,.".iter().copied(), None).unwrap();
+
+    let flushes = |flush_before_read| {
+        let mut env = BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(&b"ab"[..])
+            .stream_out(CountFlushes(0))
+            .array_len(1)
+            .flush_before_read(flush_before_read)
+            .build()
+            .unwrap();
+
+        env.run(&code).unwrap();
+
+        env.stdout.0
+    };
+
+    // one flush per read, on top of whatever the run does on its own
+    assert_eq!(flushes(true) - flushes(false), 2);
+}
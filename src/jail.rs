@@ -0,0 +1,125 @@
+//! An OS level jail for running untrusted programs, applied to the whole process once everything
+//! a run needs is open, so a bug in the interpreter can not reach beyond stdio
+
+use std::io;
+
+/// `AUDIT_ARCH_*` of the architecture syscall numbers are filtered for, only `x86_64` and `aarch64`
+/// are supported
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(target_arch = "aarch64")]
+const AUDIT_ARCH: u32 = 0xc000_00b7;
+
+/// offsets into `struct seccomp_data`
+const DATA_NR: u32 = 0;
+const DATA_ARCH: u32 = 4;
+
+/// Syscalls a running interpreter needs: stdio, memory, signals and exiting. Everything else,
+/// opening files included, fails with `EPERM`.
+const ALLOWED: &[libc::c_long] = &[
+    libc::SYS_read,
+    libc::SYS_write,
+    libc::SYS_readv,
+    libc::SYS_writev,
+    libc::SYS_close,
+    libc::SYS_fstat,
+    libc::SYS_lseek,
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_madvise,
+    libc::SYS_futex,
+    libc::SYS_sigaltstack,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_clock_gettime,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+];
+
+/// Resource limits of a jailed process
+#[derive(Clone, Copy)]
+pub struct Jail {
+    /// seconds of CPU time before the process is killed
+    pub cpu_secs: u64,
+    /// bytes of address space, past which allocations fail
+    pub memory: u64,
+}
+
+fn check(ret: libc::c_int) -> io::Result<()> {
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+impl Jail {
+    /// Applies the limits, then a seccomp filter allowing only [`ALLOWED`] syscalls. Neither can
+    /// be undone for the rest of the process.
+    pub fn enter(self) -> io::Result<()> {
+        use libc::{BPF_ABS, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W};
+
+        // opcodes are defined wider than the field they go in
+        #[allow(clippy::cast_possible_truncation)]
+        let op = |code: u32, jt: u8, jf: u8, k: u32| libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        };
+
+        // the type of resources differs between C libraries, so it is left to inference
+        let set_limit = |resource, limit| {
+            let limit = libc::rlimit {
+                rlim_cur: limit,
+                rlim_max: limit,
+            };
+
+            // SAFETY: limit is a valid rlimit for the duration of the call
+            check(unsafe { libc::setrlimit(resource, &raw const limit) })
+        };
+
+        set_limit(libc::RLIMIT_CPU, self.cpu_secs)?;
+        set_limit(libc::RLIMIT_AS, self.memory)?;
+        // nothing new can be opened, though the filter already refuses to
+        set_limit(libc::RLIMIT_NOFILE, 0)?;
+        set_limit(libc::RLIMIT_NPROC, 0)?;
+
+        let mut filter = vec![
+            op(BPF_LD | BPF_W | BPF_ABS, 0, 0, DATA_ARCH),
+            op(BPF_JMP | BPF_JEQ | BPF_K, 1, 0, AUDIT_ARCH),
+            op(BPF_RET | BPF_K, 0, 0, libc::SECCOMP_RET_KILL_PROCESS),
+            op(BPF_LD | BPF_W | BPF_ABS, 0, 0, DATA_NR),
+        ];
+
+        for &nr in ALLOWED {
+            let nr = u32::try_from(nr).expect("syscall numbers are small");
+
+            filter.push(op(BPF_JMP | BPF_JEQ | BPF_K, 0, 1, nr));
+            filter.push(op(BPF_RET | BPF_K, 0, 0, libc::SECCOMP_RET_ALLOW));
+        }
+
+        #[allow(clippy::cast_sign_loss)]
+        let eperm = libc::EPERM as u32;
+        filter.push(op(BPF_RET | BPF_K, 0, 0, libc::SECCOMP_RET_ERRNO | eperm));
+
+        let program = libc::sock_fprog {
+            len: u16::try_from(filter.len()).expect("the filter is a fixed size"),
+            filter: filter.as_mut_ptr(),
+        };
+
+        // SAFETY: prctl is given the arguments these options expect, and program points to a
+        // filter that lives until the call returns, after which the kernel holds its own copy
+        unsafe {
+            check(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0))?;
+            check(libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &raw const program,
+            ))
+        }
+    }
+}
@@ -0,0 +1,21 @@
+#![warn(clippy::pedantic)]
+#![allow(clippy::enum_glob_use)]
+
+pub mod analysis;
+#[cfg(feature = "bignum")]
+pub mod bignum;
+pub mod builder;
+pub mod cells;
+pub mod chunked;
+pub mod cimport;
+pub mod compiler;
+pub mod conformance;
+pub mod engine;
+pub mod interpreter;
+pub mod pool;
+pub mod sandbox;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod token;
+
+pub use sandbox::{run_with_cancel, run_with_limits};
@@ -1,25 +1,109 @@
 #![warn(clippy::pedantic)]
 #![allow(clippy::enum_glob_use)]
 
-pub mod compiler;
+/// Evaluates `$body` with `$Cell` naming the cell type `$mode` selects, expanding it once per
+/// cell type
+macro_rules! with_cell {
+    ($mode:expr, |$Cell:ident| $body:expr) => {
+        match $mode {
+            $crate::Mode::U8 => {
+                type $Cell = u8;
+                $body
+            }
+            $crate::Mode::U16 => {
+                type $Cell = u16;
+                $body
+            }
+            $crate::Mode::U32 => {
+                type $Cell = u32;
+                $body
+            }
+            $crate::Mode::U64 => {
+                type $Cell = u64;
+                $body
+            }
+            $crate::Mode::U12 => {
+                type $Cell = bfirs::compiler::Masked<12>;
+                $body
+            }
+            $crate::Mode::U24 => {
+                type $Cell = bfirs::compiler::Masked<24>;
+                $body
+            }
+            $crate::Mode::Big => {
+                Err("unbounded cells are only supported by plain interpret".into())
+            }
+            $crate::Mode::I8 => {
+                type $Cell = bfirs::compiler::I8;
+                $body
+            }
+            $crate::Mode::I16 => {
+                type $Cell = bfirs::compiler::I16;
+                $body
+            }
+            $crate::Mode::I32 => {
+                type $Cell = bfirs::compiler::I32;
+                $body
+            }
+        }
+    };
+}
+
+mod access;
+mod bench;
+mod budget;
+mod count;
+mod debugger;
+mod engines;
+mod explain;
+mod ice;
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod jail;
+#[cfg(all(feature = "perf", target_os = "linux"))]
+mod perf;
+mod pragma;
+mod profile_memory;
+mod reduce;
+mod run;
+mod sample;
+mod tape_file;
+mod temp_dir;
+#[cfg(unix)]
+mod terminal;
+mod trace;
+mod verify_c;
+mod verify_opt;
 
 use core::fmt;
 use std::{
-    fs::File,
-    io::{self, Write},
-    process::ExitCode,
+    fs::{self, File},
+    io::{self, IsTerminal, Read, Write},
+    num::NonZeroUsize,
+    process::{self, Command, ExitCode, Stdio},
     time::{Duration, Instant},
 };
 
+use bfirs::compiler::{
+    self, scan_source, BfExecState, BfInstructionStream, BfOptimizable, CCodegenOpts, CStd, Eof,
+};
 use clap_complete::{generate, Shell};
-use compiler::{BfCompError, BfExecState, BfInstructionStream, BfOptimizable};
 
-pub mod interpreter;
-
-use either::Either;
-use interpreter::{BfExecError, BfExecErrorTy, BrainFuckExecutor, BrainFuckExecutorBuilder};
+use bfirs::analysis;
+use bfirs::bignum::{BigExecutor, BigProgram};
+use bfirs::chunked::ChunkedTape;
+use bfirs::cimport;
+use bfirs::engine::BfEngine;
+use bfirs::interpreter::{
+    BfExecError, BfExecErrorTy, BrainFuckExecutor, BrainFuckExecutorBuilder, IoMode,
+};
+use bfirs::sandbox::{Limits, Termination};
+use bfirs::token::Extensions;
 
-use clap::{Args, CommandFactory, Parser};
+use clap::{Args, CommandFactory, Parser, ValueEnum};
+use trace::TraceFormat;
 
 #[derive(clap::ValueEnum, Clone, Copy)]
 enum Mode {
@@ -29,6 +113,145 @@ enum Mode {
     U16,
     #[value(name = "32")]
     U32,
+    #[value(name = "64")]
+    U64,
+    #[value(name = "12")]
+    U12,
+    #[value(name = "24")]
+    U24,
+    #[value(name = "big")]
+    Big,
+    #[value(name = "i8")]
+    I8,
+    #[value(name = "i16")]
+    I16,
+    #[value(name = "i32")]
+    I32,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+enum TapeEnds {
+    /// leaving the tape is an error
+    #[default]
+    Error,
+    /// the pointer wraps around to the other end of the tape
+    Wrap,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+enum OnLimit {
+    /// stop with an error
+    #[default]
+    Error,
+    /// write where the program stopped to the --resume file, so a later run continues from there
+    Snapshot,
+    /// ask on the terminal how many more instructions to run
+    Prompt,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+enum IoArg {
+    /// cells are written and read as bytes
+    #[default]
+    Bytes,
+    /// cells are written as decimal numbers on their own line, and reads parse a decimal number
+    Numeric,
+}
+
+impl From<IoArg> for IoMode {
+    fn from(value: IoArg) -> Self {
+        match value {
+            IoArg::Bytes => IoMode::Bytes,
+            IoArg::Numeric => IoMode::Numeric,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default, PartialEq, Eq)]
+enum EofArg {
+    /// the cell is set to 0
+    #[default]
+    Zero,
+    /// the cell is set to -1
+    MinusOne,
+    /// the cell keeps its value
+    Unchanged,
+}
+
+impl From<EofArg> for Eof {
+    fn from(value: EofArg) -> Self {
+        match value {
+            EofArg::Zero => Eof::Zero,
+            EofArg::MinusOne => Eof::MinusOne,
+            EofArg::Unchanged => Eof::Unchanged,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default)]
+enum ReadFlush {
+    /// flush only when stdin is a terminal
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// bytes given on the command line as hex, like `616263`
+#[derive(Clone, Default)]
+struct HexBytes(Vec<u8>);
+
+fn parse_hex(hex: &str) -> Result<HexBytes, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err("hex input must have an even number of digits".into());
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            std::str::from_utf8(pair)
+                .ok()
+                .filter(|pair| pair.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+                .ok_or_else(|| format!("invalid hex byte {:?}", String::from_utf8_lossy(pair)))
+        })
+        .collect::<Result<_, _>>()
+        .map(HexBytes)
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+enum CStdArg {
+    #[value(name = "c89")]
+    C89,
+    #[value(name = "c99")]
+    C99,
+    #[value(name = "c11")]
+    C11,
+    #[value(name = "c23")]
+    C23,
+}
+
+impl CStdArg {
+    /// the flag selecting this standard in gcc compatible compilers
+    fn cc_flag(self) -> &'static str {
+        match self {
+            CStdArg::C89 => "-std=c89",
+            CStdArg::C99 => "-std=c99",
+            CStdArg::C11 => "-std=c11",
+            CStdArg::C23 => "-std=c2x",
+        }
+    }
+}
+
+impl From<CStdArg> for CStd {
+    fn from(value: CStdArg) -> Self {
+        match value {
+            CStdArg::C89 => CStd::C89,
+            CStdArg::C99 => CStd::C99,
+            CStdArg::C11 => CStd::C11,
+            CStdArg::C23 => CStd::C23,
+        }
+    }
 }
 
 #[derive(Parser)]
@@ -37,7 +260,8 @@ struct TopLevel {
     #[command(subcommand)]
     sub: CompileSwitch,
 
-    /// cellsize to use, defaults to 8
+    /// cellsize to use, 12 and 24 are masked, i8, i16 and i32 are signed, big never wraps,
+    /// defaults to 8
     #[arg(short, long, global = true)]
     bits: Option<Mode>,
 
@@ -45,6 +269,10 @@ struct TopLevel {
     #[arg(short, long, global = true)]
     size: Option<u32>,
 
+    /// what a read stores once input has run out, defaults to zero
+    #[arg(long, value_enum, global = true)]
+    eof: Option<EofArg>,
+
     /// run the following argument as the input code
     #[arg(short, long, global = true)]
     code: Option<String>,
@@ -52,6 +280,42 @@ struct TopLevel {
     /// file input of code
     #[arg(global = true)]
     file: Option<String>,
+
+    /// stop optimizing after N rewrites, bisect over N with --verify-opt to find the rewrite that
+    /// breaks a program
+    #[arg(long, value_name = "N", global = true)]
+    opt_fuel: Option<u64>,
+}
+
+/// Reports how much optimizer fuel was spent when dropped, so the report is made however the
+/// command ends
+struct FuelReport(u64);
+
+impl FuelReport {
+    fn start(fuel: u64) -> Self {
+        compiler::set_opt_fuel(Some(fuel));
+        Self(fuel)
+    }
+}
+
+impl Drop for FuelReport {
+    fn drop(&mut self) {
+        let left = compiler::opt_fuel().unwrap_or(0);
+        let mut err = io::stderr().lock();
+
+        _ = write!(
+            err,
+            "opt-fuel: {} of {} rewrites made",
+            self.0 - left,
+            self.0
+        );
+
+        if left == 0 && self.0 > 0 {
+            _ = write!(err, ", the last in {}", compiler::last_rewrite_pass());
+        }
+
+        _ = writeln!(err);
+    }
 }
 
 #[derive(clap::Subcommand)]
@@ -60,6 +324,38 @@ enum CompileSwitch {
     Interpret(InterpreterArgs),
     #[command(name = "compile", visible_alias = "c")]
     Compile(CompilerArgs),
+    #[command(name = "run")]
+    Run(run::RunArgs),
+    /// show what the optimizer did to each region of the source
+    #[command(name = "explain")]
+    Explain,
+    /// run semantic probes against every available engine and show how they differ
+    #[command(name = "engines")]
+    Engines,
+    /// reconstruct plain brainfuck from the optimized instruction stream
+    #[command(name = "decompile")]
+    Decompile,
+    /// read C written by `compile` back into brainfuck, for C whose brainfuck source is lost
+    #[command(name = "import-c")]
+    ImportC,
+    #[command(name = "verify-c")]
+    VerifyC(verify_c::VerifyCArgs),
+    #[command(name = "trace-view")]
+    TraceView(trace::TraceViewArgs),
+    #[command(name = "debug")]
+    Debug(debugger::DebugArgs),
+    #[command(name = "snapshot")]
+    Snapshot(tape_file::SnapshotArgs),
+    #[command(name = "plot-access")]
+    PlotAccess(access::PlotAccessArgs),
+    #[command(name = "bench")]
+    Bench(bench::BenchArgs),
+    #[command(name = "halts")]
+    Halts(HaltsArgs),
+    #[command(name = "lint")]
+    Lint(LintArgs),
+    #[command(name = "reduce")]
+    Reduce(reduce::ReduceArgs),
     #[command(name = "completions")]
     Completions(CompletionsArgs),
 }
@@ -71,16 +367,185 @@ struct CompletionsArgs {
     shell: Shell,
 }
 
-#[derive(Args, Copy, Clone)]
+#[derive(Args, Clone, Default)]
 /// run brainfuck in an interpreter
+#[allow(clippy::struct_excessive_bools)]
+// modes that replace a plain run are in the "mode" group, which allows only one of them, and flags
+// that only affect a plain run conflict with the group as a whole
 struct InterpreterArgs {
     /// run a limited amount of instructions
     #[arg(short, long)]
     limit: Option<u64>,
+
+    /// what happens when the program runs out of --limit instructions
+    #[arg(long, value_enum, default_value_t = OnLimit::Error, requires = "limit")]
+    on_limit: OnLimit,
+
+    /// continue from the resume point in FILE if it exists, it is removed once the program
+    /// finishes and written by --on-limit snapshot. Input already read is not replayed.
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "limit",
+        conflicts_with = "tape_file"
+    )]
+    resume: Option<String>,
+
+    /// run with no input and output discarded, reporting only instruction counts by kind
+    #[arg(long, group = "mode")]
+    count_only: bool,
+
+    /// skip pointer bounds checks even when they can not be proven unnecessary, a program that
+    /// leaves the tape will corrupt memory
+    #[arg(long)]
+    trust_bounds: bool,
+
+    /// minimum milliseconds between output flushes, 0 flushes after every write
+    #[arg(long, value_name = "MS")]
+    flush_interval: Option<u64>,
+
+    /// when to flush output before reading input, auto flushes only for interactive stdin
+    #[arg(long, value_enum, default_value_t = ReadFlush::Auto)]
+    read_flush: ReadFlush,
+
+    /// run one instruction at a time, writing every loop entry and exit to FILE as a trace
+    #[arg(long, value_name = "FILE", group = "mode")]
+    trace: Option<String>,
+
+    /// format to write --trace in
+    #[arg(long, value_enum, default_value_t = TraceFormat::Chrome)]
+    trace_format: TraceFormat,
+
+    /// run both the optimized and unoptimized program on all of stdin, failing if their output
+    /// or the way they end differs
+    #[arg(long, group = "mode", conflicts_with = "limit")]
+    verify_opt: bool,
+
+    /// record where the program is every N instructions, reporting the source lines it spent the
+    /// most time on to stderr when it stops
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..),
+          group = "mode")]
+    profile_sample: Option<u64>,
+
+    /// log the cell the pointer is on to FILE every --access-every instructions, for
+    /// `bf plot-access`
+    #[arg(long, value_name = "FILE", group = "mode")]
+    access_log: Option<String>,
+
+    /// instructions between samples of --access-log
+    #[arg(long, value_name = "N", default_value_t = 1000, requires = "access_log",
+          value_parser = clap::value_parser!(u64).range(1..))]
+    access_every: u64,
+
+    /// when stdin is a terminal, hand each byte to the program as it is typed instead of a line
+    /// at a time after enter
+    #[arg(long, visible_alias = "raw-input", conflicts_with = "line_input")]
+    char_input: bool,
+
+    /// when stdin is a terminal, hand input to the program a line at a time after enter, even if
+    /// the terminal was left in another mode
+    #[arg(long)]
+    line_input: bool,
+
+    /// when stdin is a terminal, do not echo what is typed
+    #[arg(long)]
+    no_echo: bool,
+
+    /// report peak memory, compiled stream size and tape usage to stderr when the program stops
+    #[arg(long)]
+    profile_memory: bool,
+
+    /// what happens when the pointer leaves either end of the tape
+    #[arg(long, value_enum, default_value_t = TapeEnds::Error,
+          conflicts_with_all = ["mode", "trust_bounds"])]
+    tape: TapeEnds,
+
+    /// move loops that repeat into shared blocks that each copy calls, shrinking large generated
+    /// programs. Every call and return counts as an instruction toward --limit and --clock.
+    #[arg(long, conflicts_with_all = ["mode", "resume"])]
+    dedup: bool,
+
+    /// keep the tape in blocks that are only allocated once the program writes to them, for huge
+    /// --size tapes that the program touches little of
+    #[arg(long, conflicts_with_all = ["mode", "trust_bounds", "tape_file", "resume", "on_limit",
+                                      "jail", "profile_memory"])]
+    chunked_tape: bool,
+
+    /// how `.` writes cells and `,` reads input
+    #[arg(long, value_enum, default_value_t = IoArg::Bytes, conflicts_with = "mode")]
+    io: IoArg,
+
+    /// write program output to FILE instead of stdout, flushed when the program ends
+    #[arg(short, long, value_name = "FILE", conflicts_with = "mode")]
+    output: Option<String>,
+
+    /// enable `^`, which stores the number of instructions run before it in the current cell,
+    /// wrapping, for self timing programs that behave the same on every machine
+    #[arg(long, conflicts_with = "mode")]
+    clock: bool,
+
+    /// enable `@`, which ends the program successfully
+    #[arg(long, conflicts_with = "mode")]
+    allow_halt: bool,
+
+    /// enable `#`, which dumps the pointer and the cells around it to stderr
+    #[arg(long, conflicts_with = "mode")]
+    debug_char: bool,
+
+    /// load the tape from FILE if it exists, and save it back there when the program stops
+    #[arg(long, value_name = "FILE")]
+    tape_file: Option<String>,
+
+    /// run under seccomp and resource limits once the program and its files are open, so only
+    /// stdio and memory are reachable, linux only
+    #[arg(long, conflicts_with_all = ["tape_file", "resume", "char_input", "line_input", "no_echo",
+                                      "profile_memory", "mode"])]
+    jail: bool,
+
+    /// seconds of CPU time a --jail run may use before it is killed
+    #[arg(long, value_name = "SECS", default_value_t = 10, requires = "jail")]
+    jail_cpu: u64,
+
+    /// MiB of address space a --jail run may use, past which allocations fail
+    #[arg(long, value_name = "MIB", default_value_t = 1024, requires = "jail")]
+    jail_memory: u64,
+
+    /// feed these bytes to the program before reading from stdin
+    #[arg(long, value_name = "BYTES", conflicts_with = "stdin_hex")]
+    stdin_bytes: Option<String>,
+
+    /// use TEXT as all of the program's input, stdin is never read
+    #[arg(long, value_name = "TEXT", conflicts_with_all = ["stdin_bytes", "stdin_hex"])]
+    stdin_str: Option<String>,
+
+    /// feed these hex encoded bytes to the program before reading from stdin
+    #[arg(long, value_name = "HEX", value_parser = parse_hex)]
+    stdin_hex: Option<HexBytes>,
+
+    /// never read stdin, so --stdin-bytes or --stdin-hex is all of the program's input
+    #[arg(long)]
+    no_stdin: bool,
+}
+
+#[derive(Args, Copy, Clone)]
+/// check whether a program terminates within a bounded number of instructions on empty input
+struct HaltsArgs {
+    /// maximum number of instructions to run
+    #[arg(long)]
+    steps: u64,
+}
+
+#[derive(Args, Copy, Clone)]
+/// report loops that trivially can not terminate, and whether the pointer provably stays in bounds
+struct LintArgs {
+    /// treat warnings as errors
+    #[arg(long)]
+    strict: bool,
 }
 
 #[derive(Args)]
 /// compile brainfuck to C
+#[allow(clippy::struct_excessive_bools)]
 struct CompilerArgs {
     /// output C to a file instead of stdout
     #[arg(short, long)]
@@ -89,30 +554,443 @@ struct CompilerArgs {
     /// consteval by prerunning in interpreter for up to N seconds, defaults to O1
     #[arg(short = 'O', long = "opt-level")]
     opt_level: Option<u32>,
+
+    /// outline runs of more than N statements into separate C functions, 0 disables, defaults to 50000
+    #[arg(long)]
+    split_threshold: Option<usize>,
+
+    /// emit the program as a resume function that can enter it at any instruction index
+    #[arg(long)]
+    resumable: bool,
+
+    /// C standard to conform to, defaults to c99
+    #[arg(long = "c-std")]
+    c_std: Option<CStdArg>,
+
+    /// make output independent of machine speed by bounding consteval by instruction count,
+    /// and fail if rendering twice does not produce identical output
+    #[arg(long)]
+    reproducible: bool,
+
+    /// size consteval chunks from the throughput of the first 50ms of the program itself,
+    /// instead of from a synthetic benchmark
+    #[arg(long, conflicts_with = "reproducible")]
+    calibrate: bool,
+
+    /// stop consteval early once it has precomputed more than N bytes of output, defaults to 64MiB
+    #[arg(long, value_name = "N")]
+    consteval_output_cap: Option<usize>,
+
+    /// compile without reading the whole source into memory, for very large sources, implies -O0
+    /// and skips all optimization
+    #[arg(long)]
+    streaming: bool,
+
+    /// consteval by a cheap static pass that only bakes in the output known before the first
+    /// read, instead of prerunning in the interpreter
+    #[arg(long, conflicts_with = "opt_level")]
+    static_prefix: bool,
+
+    /// pipe the C straight into $CC or cc instead of writing it out, --output then names the
+    /// executable
+    #[arg(long)]
+    cc_pipe: bool,
+
+    /// enable `@`, which ends the program successfully
+    #[arg(long, conflicts_with = "streaming")]
+    allow_halt: bool,
+}
+
+impl InterpreterArgs {
+    /// the bytes given by --stdin-bytes, --stdin-hex or --stdin-str, read before stdin
+    fn stdin_prefix(&self) -> Vec<u8> {
+        match (&self.stdin_bytes, &self.stdin_hex, &self.stdin_str) {
+            (Some(bytes), _, _) | (_, _, Some(bytes)) => bytes.as_bytes().to_vec(),
+            (_, Some(HexBytes(bytes)), _) => bytes.clone(),
+            (None, None, None) => vec![],
+        }
+    }
+
+    /// the program's input, stdin after any bytes given on the command line
+    fn stdin(&self) -> io::Chain<io::Cursor<Vec<u8>>, io::Take<io::StdinLock<'static>>> {
+        self.input(io::stdin().lock())
+    }
+
+    /// the program's input, `rest` after any bytes given on the command line
+    fn input<R: io::Read>(&self, rest: R) -> io::Chain<io::Cursor<Vec<u8>>, io::Take<R>> {
+        // --stdin-str is the whole input, so nothing is taken from stdin after it
+        let limit = if self.stdin_str.is_some() || self.no_stdin {
+            0
+        } else {
+            u64::MAX
+        };
+
+        io::Cursor::new(self.stdin_prefix()).chain(rest.take(limit))
+    }
+}
+
+/// interprets with unbounded cells, which have no instruction stream of their own to optimize,
+/// trace or compile
+fn interpret_big(
+    code: &[u8],
+    arr_len: Option<u32>,
+    eof: Eof,
+    args: &InterpreterArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.tape_file.is_some()
+        || args.profile_memory
+        || args.tape == TapeEnds::Wrap
+        || args.clock
+        || args.allow_halt
+        || args.debug_char
+        || args.jail
+        || args.on_limit != OnLimit::Error
+        || args.resume.is_some()
+    {
+        return Err(
+            "--tape-file, --profile-memory, --tape wrap, --clock, --allow-halt, --debug-char, --jail, \
+             --on-limit and --resume do not support unbounded cells"
+                .into(),
+        );
+    }
+
+    let program = BigProgram::from_text(code.iter().copied())?;
+
+    let out: Box<dyn io::Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| PathIoError(path.clone(), e))?),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut execenv = BigExecutor::new(
+        usize::try_from(arr_len.unwrap_or(30_000))?,
+        args.stdin(),
+        io::BufWriter::new(out),
+    );
+
+    execenv.instructions_left = args.limit;
+    execenv.eof = eof;
+    execenv.io = args.io.into();
+    execenv.flush_before_read = match args.read_flush {
+        ReadFlush::Auto => io::stdin().is_terminal(),
+        ReadFlush::Always => true,
+        ReadFlush::Never => false,
+    };
+
+    #[cfg(unix)]
+    let _terminal = if args.char_input || args.line_input || args.no_echo {
+        terminal::TerminalGuard::apply(terminal::InputMode {
+            char_input: args.char_input,
+            echo: !args.no_echo,
+        })?
+    } else {
+        None
+    };
+    #[cfg(not(unix))]
+    if args.char_input || args.line_input || args.no_echo {
+        return Err("terminal input modes are only supported on unix".into());
+    }
+
+    Ok(execenv.run(&program)?)
+}
+
+/// interprets over a tape allocated a block at a time, which has no flat tape to save, profile or
+/// run unchecked
+fn interpret_chunked<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    eof: Eof,
+    args: &InterpreterArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let extensions = Extensions {
+        clock: args.clock,
+        halt: args.allow_halt,
+        debug: args.debug_char,
+    };
+
+    // only the spanned compiler parses extensions, and its spans cost memory that huge programs
+    // may not have to spare
+    let mut code = if extensions == Extensions::default() {
+        BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?
+    } else {
+        BfInstructionStream::optimized_from_text_extended(code, arr_len, extensions)?
+    };
+
+    if args.dedup {
+        code.dedup_loops()?;
+    }
+
+    let out: Box<dyn io::Write> = match &args.output {
+        Some(path) => Box::new(File::create(path).map_err(|e| PathIoError(path.clone(), e))?),
+        None => Box::new(io::stdout().lock()),
+    };
+
+    let mut execenv = BrainFuckExecutorBuilder::new()
+        .stream_in(args.stdin())
+        .stream_out(io::BufWriter::new(out))
+        .flush_before_read(match args.read_flush {
+            ReadFlush::Auto => io::stdin().is_terminal(),
+            ReadFlush::Always => true,
+            ReadFlush::Never => false,
+        })
+        .wrap_tape(args.tape == TapeEnds::Wrap)
+        .eof(eof)
+        .io(args.io.into())
+        .build_with_tape(ChunkedTape::new(code.reccomended_array_size()))
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    if let Some(ms) = args.flush_interval {
+        execenv.flush_interval = Duration::from_millis(ms);
+    } else if args.output.is_some() {
+        // nobody is watching a file fill up, so output is only flushed when it must be
+        execenv.flush_interval = Duration::MAX;
+    }
+
+    #[cfg(unix)]
+    let _terminal = if args.char_input || args.line_input || args.no_echo {
+        terminal::TerminalGuard::apply(terminal::InputMode {
+            char_input: args.char_input,
+            echo: !args.no_echo,
+        })?
+    } else {
+        None
+    };
+    #[cfg(not(unix))]
+    if args.char_input || args.line_input || args.no_echo {
+        return Err("terminal input modes are only supported on unix".into());
+    }
+
+    let result = if let Some(lim) = args.limit {
+        execenv.add_instruction_limit(lim)?;
+        execenv.run_limited(&code)
+    } else if args.clock {
+        // only limited runs count the instructions the clock reads
+        execenv.add_instruction_limit(u64::MAX)?;
+        execenv.run_limited(&code)
+    } else {
+        execenv.run(&code)
+    };
+
+    execenv.stdout.flush()?;
+
+    Ok(result?)
 }
 
 fn interpret<CellSize: BfOptimizable>(
     code: &[u8],
     arr_len: Option<u32>,
-    args: InterpreterArgs,
-) -> Result<(), Either<BfExecError, BfCompError>> {
-    let code = BfInstructionStream::optimized_from_text(code.iter().copied(), arr_len)
-        .map_err(Either::Right)?;
+    eof: Eof,
+    args: &InterpreterArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.on_limit == OnLimit::Snapshot && args.resume.is_none() {
+        return Err("--on-limit snapshot needs a --resume file to write to".into());
+    }
+
+    match &args.output {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| PathIoError(path.clone(), e))?;
+            interpret_to::<CellSize, _>(code, arr_len, eof, args, io::BufWriter::new(file))
+        }
+        None => interpret_to::<CellSize, _>(code, arr_len, eof, args, io::stdout().lock()),
+    }
+}
+
+/// interprets with program output written to `out`
+fn interpret_to<CellSize: BfOptimizable, O: io::Write>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    eof: Eof,
+    args: &InterpreterArgs,
+    out: O,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = code;
 
-    let mut execenv =
-        BrainFuckExecutor::new_stdio_locked::<CellSize>(code.reccomended_array_size());
+    let extensions = Extensions {
+        clock: args.clock,
+        halt: args.allow_halt,
+        debug: args.debug_char,
+    };
+
+    // spans let a run that exhausts its limit say which loop the instructions went to
+    let mut code = if args.limit.is_none() && extensions == Extensions::default() {
+        BfInstructionStream::optimized_from_text(code.iter().copied(), arr_len)?
+    } else {
+        BfInstructionStream::optimized_from_text_extended(code, arr_len, extensions)?
+    };
+
+    if args.dedup {
+        code.dedup_loops()?;
+    }
+
+    let mut builder = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .stream_in(args.stdin())
+        .stream_out(out)
+        .array_len(code.reccomended_array_size())
+        .flush_before_read(io::stdin().is_terminal())
+        .wrap_tape(args.tape == TapeEnds::Wrap)
+        .eof(eof)
+        .io(args.io.into());
 
-    match args.limit {
-        Some(lim) => {
-            execenv.add_instruction_limit(lim).unwrap();
-            execenv.run_limited(&code).map_err(Either::Left)?;
+    if let Some(path) = &args.tape_file {
+        builder = builder.tape(tape_file::load(path, code.reccomended_array_size())?);
+    }
+
+    let mut start = (0, 0);
+
+    if let Some(path) = &args.resume {
+        let point =
+            tape_file::load_resume(path, source, code.reccomended_array_size(), code.len())?;
+
+        if let Some(point) = point {
+            builder = builder.tape(point.tape);
+            start = (point.idx, point.ptr);
         }
-        None => {
-            execenv.run(&code).map_err(Either::Left)?;
+    }
+
+    let mut execenv = builder
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    execenv.ptr = start.1;
+
+    #[cfg(unix)]
+    let _terminal = if args.char_input || args.line_input || args.no_echo {
+        terminal::TerminalGuard::apply(terminal::InputMode {
+            char_input: args.char_input,
+            echo: !args.no_echo,
+        })?
+    } else {
+        None
+    };
+    #[cfg(not(unix))]
+    if args.char_input || args.line_input || args.no_echo {
+        return Err("terminal input modes are only supported on unix".into());
+    }
+
+    if let Some(ms) = args.flush_interval {
+        execenv.flush_interval = Duration::from_millis(ms);
+    } else if args.output.is_some() {
+        // nobody is watching a file fill up, so output is only flushed when it must be
+        execenv.flush_interval = Duration::MAX;
+    }
+
+    match args.read_flush {
+        ReadFlush::Auto => {}
+        ReadFlush::Always => execenv.flush_before_read = true,
+        ReadFlush::Never => execenv.flush_before_read = false,
+    }
+
+    if args.jail {
+        #[cfg(all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ))]
+        jail::Jail {
+            cpu_secs: args.jail_cpu,
+            memory: args.jail_memory.saturating_mul(1 << 20),
         }
+        .enter()?;
+        #[cfg(not(all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        )))]
+        return Err("--jail is only supported on linux, on x86_64 and aarch64".into());
     }
 
-    Ok(())
+    let result = if let Some(lim) = args.limit {
+        run_budgeted(&mut execenv, &code, source, lim, start.0, args)?
+    } else if args.clock {
+        // only limited runs count the instructions the clock reads
+        execenv.add_instruction_limit(u64::MAX)?;
+        execenv.run_limited(&code)
+    } else if args.trust_bounds
+        || analysis::prove_pointer_bounds(&code, execenv.data.len()).is_some()
+    {
+        // SAFETY: either the pointer was proven to stay within the tape, which it starts at the
+        // beginning of, or the user has vouched for it with --trust-bounds
+        unsafe { execenv.run_unchecked(&code) }
+    } else {
+        run_engine(&mut execenv, &code, None)
+    };
+
+    if let Some(path) = &args.tape_file {
+        tape_file::save(path, &execenv.data)?;
+    }
+
+    execenv.stdout.flush()?;
+
+    if args.profile_memory {
+        profile_memory::report(&code, &execenv.data)?;
+    }
+
+    Ok(result?)
+}
+
+/// runs `code` for `limit` instructions from `start`, then handles running out as --on-limit asks
+fn run_budgeted<CellSize: BfOptimizable, I: io::Read, O: io::Write>(
+    execenv: &mut BrainFuckExecutor<CellSize, I, O>,
+    code: &BfInstructionStream<CellSize>,
+    source: &[u8],
+    limit: u64,
+    start: usize,
+    args: &InterpreterArgs,
+) -> Result<Result<(), BfExecError>, Box<dyn std::error::Error>> {
+    let mut result = budget::run_attributed(execenv, code, source, limit, start);
+
+    while let Err(BfExecError {
+        source: BfExecErrorTy::NotEnoughInstructions,
+        idx,
+    }) = result
+    {
+        match args.on_limit {
+            OnLimit::Error => break,
+            OnLimit::Snapshot => {
+                let path = args.resume.as_ref().expect("checked before running");
+
+                let point = tape_file::ResumePoint {
+                    tape: execenv.data.clone(),
+                    ptr: execenv.ptr,
+                    idx,
+                };
+
+                tape_file::save_resume(path, source, &point)?;
+                eprintln!("note: out of instructions, wrote a resume point to {path}");
+
+                return Ok(result);
+            }
+            OnLimit::Prompt => {
+                execenv.stdout.flush()?;
+
+                let Some(more) = budget::prompt_more(idx)? else {
+                    break;
+                };
+
+                result = budget::run_attributed(execenv, code, source, more, idx);
+            }
+        }
+    }
+
+    if let (Ok(()), Some(path)) = (&result, &args.resume) {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(PathIoError(path.clone(), e).into())
+            }
+            _ => {}
+        }
+    }
+
+    Ok(result)
+}
+
+/// runs a prepared program on any engine, for at most `limit` instructions if one is given
+fn run_engine<E: BfEngine>(
+    engine: &mut E,
+    program: &E::Program,
+    limit: Option<u64>,
+) -> Result<(), BfExecError> {
+    match limit {
+        Some(lim) => engine.run_limited(program, lim),
+        None => engine.run(program),
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -124,21 +1002,108 @@ impl io::Read for ErrorReader {
     }
 }
 
+/// instructions evaluated by `--static-prefix` before giving up on the rest of the program
+const STATIC_PREFIX_STEPS: u64 = 1_000_000;
+
+/// consteval throughput assumed by reproducible builds, so the amount of prerunning done does not
+/// depend on the speed of the machine
+const REPRODUCIBLE_INSTRUCTIONS_PER_SECOND: u64 = 100_000_000;
+
+/// instructions per consteval chunk while `--calibrate` is measuring the program
+const CALIBRATION_CHUNK: u64 = 100_000;
+
+/// how long `--calibrate` measures the program before sizing chunks from its throughput
+const CALIBRATION_TIME: Duration = Duration::from_millis(50);
+
+/// how much output consteval may precompute when no cap is given
+const DEFAULT_CONSTEVAL_OUTPUT_CAP: usize = 64 << 20;
+
+/// how much prerunning is done before rendering C
+#[derive(Clone, Copy)]
+struct Consteval {
+    /// seconds to prerun for, zero disables consteval
+    secs: u32,
+    /// bound prerunning by instruction count instead of a wall clock deadline
+    reproducible: bool,
+    /// measure throughput on the program itself instead of a synthetic benchmark
+    calibrate: bool,
+    /// stop prerunning once this many bytes of output have been precomputed
+    output_cap: usize,
+}
+
+impl Default for Consteval {
+    fn default() -> Self {
+        Self {
+            secs: 1,
+            reproducible: false,
+            calibrate: false,
+            output_cap: DEFAULT_CONSTEVAL_OUTPUT_CAP,
+        }
+    }
+}
+
+/// consteval output buffer that refuses writes past its cap, which stops the interpreter at the
+/// write so the C resumes from it
+struct CappedOutput {
+    buf: Vec<u8>,
+    cap: usize,
+}
+
+impl io::Write for CappedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buf.len() + buf.len() > self.cap {
+            return Err(io::Error::other("consteval output cap reached"));
+        }
+
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// instructions run in a tenth of a second at the throughput of `instructions` in `elapsed`, at
+/// least one
+fn per_tenth_second(instructions: u64, elapsed: Duration) -> u64 {
+    let per_tenth = u128::from(instructions) * 100_000 / elapsed.as_micros().max(1);
+
+    u64::try_from(per_tenth).unwrap_or(u64::MAX).max(1)
+}
+
 fn render_c_deadline<CellSize: BfOptimizable>(
     code: &BfInstructionStream<CellSize>,
-    secs: u32,
+    consteval: Consteval,
+    opts: CCodegenOpts,
     fp: &mut dyn io::Write,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
         .array_len(code.reccomended_array_size())
         .stream_in(ErrorReader)
-        .stream_out(vec![])
+        .stream_out(CappedOutput {
+            buf: vec![],
+            cap: consteval.output_cap,
+        })
         .build()
         .unwrap();
 
-    let est =
+    let Consteval {
+        secs,
+        reproducible,
+        calibrate,
+        ..
+    } = consteval;
+
+    let mut est = if calibrate {
+        CALIBRATION_CHUNK
+    } else if reproducible {
+        REPRODUCIBLE_INSTRUCTIONS_PER_SECOND / 10
+    } else {
         u64::try_from(BrainFuckExecutor::<CellSize, ErrorReader, Vec<u8>>::estimate_instructions_per_second(
-        )).map_err(|_| "computer is too fast!! (u64::MAX overflowed when calculating instructions per second throughput)")? / 10;
+        )).map_err(|_| "computer is too fast!! (u64::MAX overflowed when calculating instructions per second throughput)")?
+        / 10
+    };
 
     let start = std::time::Instant::now();
     let deadline = start + Duration::from_secs(u64::from(secs));
@@ -146,17 +1111,20 @@ fn render_c_deadline<CellSize: BfOptimizable>(
     execenv.add_instruction_limit(est)?;
 
     let mut s_idx = 0;
+    let mut chunks = 1u64;
+    let mut calibrating = calibrate;
 
     loop {
         match execenv.run_limited_from(code, s_idx) {
             Ok(()) => {
-                code.render_interpreted_c(
+                code.render_interpreted_c_with(
+                    opts,
                     &BfExecState {
                         cursor: execenv.ptr,
                         data: &execenv.data,
                         instruction_pointer: None,
                     },
-                    &execenv.stdout,
+                    &execenv.stdout.buf,
                     fp,
                 )?;
                 break;
@@ -164,18 +1132,20 @@ fn render_c_deadline<CellSize: BfOptimizable>(
             Err(BfExecError { source, idx }) => match source {
                 err @ (BfExecErrorTy::Overflow
                 | BfExecErrorTy::Underflow
-                | BfExecErrorTy::InitOverflow) => {
+                | BfExecErrorTy::InitOverflow
+                | BfExecErrorTy::Cancelled) => {
                     return Err(format!("consteval: {err}").into());
                 }
-                // we know this cant be the Write impl, as Vec::write wont error
+                // either a read, or a write refused by the output cap, neither of which ran
                 BfExecErrorTy::IOError(_) => {
-                    code.render_interpreted_c(
+                    code.render_interpreted_c_with(
+                        opts,
                         &BfExecState {
                             cursor: execenv.ptr,
                             data: &execenv.data,
                             instruction_pointer: Some(idx),
                         },
-                        &execenv.stdout,
+                        &execenv.stdout.buf,
                         fp,
                     )?;
                     break;
@@ -183,20 +1153,34 @@ fn render_c_deadline<CellSize: BfOptimizable>(
                 BfExecErrorTy::NotEnoughInstructions => {
                     s_idx = idx;
 
-                    if Instant::now() > deadline {
-                        code.render_interpreted_c(
+                    if calibrating && start.elapsed() >= CALIBRATION_TIME {
+                        calibrating = false;
+
+                        est = per_tenth_second(chunks * CALIBRATION_CHUNK, start.elapsed());
+                    }
+
+                    let out_of_time = if reproducible {
+                        chunks >= u64::from(secs) * 10
+                    } else {
+                        Instant::now() > deadline
+                    };
+
+                    if out_of_time {
+                        code.render_interpreted_c_with(
+                            opts,
                             &BfExecState {
                                 cursor: execenv.ptr,
                                 data: &execenv.data,
                                 instruction_pointer: Some(idx),
                             },
-                            &execenv.stdout,
+                            &execenv.stdout.buf,
                             fp,
                         )?;
                         break;
                     }
 
                     execenv.add_instruction_limit(est)?;
+                    chunks += 1;
                 }
             },
         };
@@ -205,33 +1189,386 @@ fn render_c_deadline<CellSize: BfOptimizable>(
     Ok(())
 }
 
+/// the C compiler to use, from `$CC` or falling back to `cc`
+fn system_cc() -> String {
+    std::env::var("CC").unwrap_or_else(|_| "cc".to_owned())
+}
+
+/// where generated C is written
+enum COutput {
+    File(Box<dyn io::Write>),
+    /// a C compiler reading the C from its stdin
+    Cc {
+        cc: String,
+        child: process::Child,
+        stdin: io::BufWriter<process::ChildStdin>,
+    },
+}
+
+impl COutput {
+    /// opens the file C should be written to, stdout, or a C compiler when piping
+    fn open(args: &CompilerArgs) -> Result<Self, PathIoError> {
+        if args.cc_pipe {
+            let cc = system_cc();
+
+            let mut command = Command::new(&cc);
+            command.args(["-O2", "-x", "c"]);
+
+            if let Some(std) = args.c_std {
+                command.arg(std.cc_flag());
+            }
+
+            if let Some(fname) = &args.output {
+                command.arg("-o").arg(fname);
+            }
+
+            let mut child = command
+                .arg("-")
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|e| PathIoError(cc.clone(), e))?;
+
+            let stdin = io::BufWriter::new(child.stdin.take().expect("stdin was piped"));
+
+            return Ok(Self::Cc { cc, child, stdin });
+        }
+
+        Ok(Self::File(match &args.output {
+            Some(fname) => Box::new(io::BufWriter::new(
+                File::create(fname).map_err(|e| PathIoError(fname.clone(), e))?,
+            )),
+            None => Box::new(io::BufWriter::new(io::stdout())),
+        }))
+    }
+
+    fn writer(&mut self) -> &mut dyn io::Write {
+        match self {
+            Self::File(fp) => fp,
+            Self::Cc { stdin, .. } => stdin,
+        }
+    }
+
+    /// flushes all written C given rendering it succeeded, and waits for the C compiler to finish
+    /// if piping
+    fn finish(
+        self,
+        rendered: Result<(), Box<dyn std::error::Error>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Self::File(mut fp) => {
+                rendered?;
+                fp.flush()?;
+            }
+            Self::Cc {
+                cc,
+                mut child,
+                stdin,
+            } => {
+                // closing stdin lets the compiler see the end of the C
+                let closed = rendered.and_then(|()| {
+                    stdin.into_inner().map_err(io::IntoInnerError::into_error)?;
+                    Ok(())
+                });
+
+                let status = child.wait().map_err(|e| PathIoError(cc.clone(), e))?;
+
+                // a compiler that exits early breaks the pipe, its own failure is more useful
+                if !status.success() {
+                    return Err(format!("{cc} failed to compile the generated C ({status})").into());
+                }
+
+                closed?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// opens the code given on the command line, or the file it names, without reading it
+fn open_source<'a>(
+    code: Option<&'a str>,
+    file: Option<&str>,
+) -> Result<Box<dyn io::Read + 'a>, PathIoError> {
+    Ok(match (code, file) {
+        (Some(code), _) => Box::new(code.as_bytes()),
+        (None, Some(f)) => Box::new(File::open(f).map_err(|e| PathIoError(f.to_owned(), e))?),
+        (None, None) => Box::new(io::empty()),
+    })
+}
+
+/// compiles to C without holding the source in memory, reading it once to scan it and again to
+/// write the C
+fn compile_streaming<CellSize: BfOptimizable>(
+    code: Option<&str>,
+    file: Option<&str>,
+    skip: usize,
+    arr_len: Option<u32>,
+    eof: Eof,
+    args: &CompilerArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.opt_level.is_some_and(|secs| secs != 0) {
+        return Err("--streaming can not consteval, pass -O0 or leave out -O".into());
+    }
+
+    // the pragma line is left out of both passes, as its values may contain instructions
+    let open = || -> Result<Box<dyn io::Read + '_>, Box<dyn std::error::Error>> {
+        let mut source = open_source(code, file)?;
+        io::copy(&mut (&mut source).take(skip as u64), &mut io::sink())?;
+        Ok(source)
+    };
+
+    let scan = scan_source(open()?)?;
+
+    let mut out = COutput::open(args)?;
+
+    let opts = CCodegenOpts {
+        std: args.c_std.map_or_else(CStd::default, CStd::from),
+        eof,
+        ..CCodegenOpts::default()
+    };
+
+    let rendered = BfInstructionStream::<CellSize>::render_c_streaming(
+        open()?,
+        &scan,
+        arr_len,
+        opts,
+        out.writer(),
+    )
+    .map_err(Into::into);
+
+    out.finish(rendered)
+}
+
+/// renders C with the output known before the first read baked in
+fn render_static_prefix<CellSize: BfOptimizable>(
+    code: &BfInstructionStream<CellSize>,
+    opts: CCodegenOpts,
+    fp: &mut dyn io::Write,
+) -> io::Result<()> {
+    let prefix =
+        analysis::known_output_prefix(code, code.reccomended_array_size(), STATIC_PREFIX_STEPS);
+
+    code.render_interpreted_c_with(
+        opts,
+        &BfExecState {
+            cursor: prefix.ptr,
+            data: &prefix.tape,
+            instruction_pointer: prefix.resume,
+        },
+        &prefix.output,
+        fp,
+    )
+}
+
 fn compile<CellSize: BfOptimizable>(
     code: &[u8],
     arr_len: Option<u32>,
-    args: CompilerArgs,
+    eof: Eof,
+    args: &CompilerArgs,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    let code = BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?;
+    let code = if args.allow_halt {
+        let extensions = Extensions {
+            halt: true,
+            ..Extensions::default()
+        };
 
-    let mut fp: Box<dyn io::Write> = match args.output {
-        Some(fname) => Box::new(io::BufWriter::new(
-            File::create(&fname).map_err(|e| PathIoError(fname, e))?,
-        )),
-        None => Box::new(io::BufWriter::new(io::stdout())),
+        BfInstructionStream::<CellSize>::optimized_from_text_extended(code, arr_len, extensions)?
+    } else {
+        BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?
     };
 
-    let secs = args.opt_level.unwrap_or(1);
+    let mut out = COutput::open(args)?;
+    let fp = out.writer();
+
+    let mut opts = CCodegenOpts {
+        resumable: args.resumable,
+        std: args.c_std.map_or_else(CStd::default, CStd::from),
+        eof,
+        ..CCodegenOpts::default()
+    };
 
-    if secs != 0 {
-        render_c_deadline(&code, secs, &mut fp)?;
+    if let Some(n) = args.split_threshold {
+        opts.split_threshold = NonZeroUsize::new(n);
+    }
+
+    let rendered = if args.static_prefix {
+        render_static_prefix(&code, opts, fp).map_err(Into::into)
     } else {
-        code.render_c(&mut *fp)?;
+        let consteval = Consteval {
+            secs: args.opt_level.unwrap_or(1),
+            reproducible: args.reproducible,
+            calibrate: args.calibrate,
+            output_cap: args
+                .consteval_output_cap
+                .unwrap_or(DEFAULT_CONSTEVAL_OUTPUT_CAP),
+        };
+
+        render(&code, consteval, opts, fp)
+    };
+
+    out.finish(rendered)
+}
+
+fn halts<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    args: HaltsArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let limits = Limits {
+        instructions: args.steps,
+        array_len: arr_len,
+    };
+
+    let outcome = bfirs::run_with_limits::<CellSize>(code, b"", limits);
+
+    match outcome.termination {
+        Termination::Completed => {
+            writeln!(
+                io::stdout(),
+                "terminated after {} instructions",
+                outcome.instructions_executed
+            )?;
+            Ok(())
+        }
+        // halts never cancels, so a cancelled run is one that was still going
+        Termination::BudgetExhausted { .. } | Termination::Cancelled { .. } => {
+            Err(format!("still running after {} instructions", args.steps).into())
+        }
+        Termination::Error(e) => Err(format!(
+            "errored after {} instructions: {e}",
+            outcome.instructions_executed
+        )
+        .into()),
+    }
+}
+
+fn lint<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    args: LintArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let stream = BfInstructionStream::<CellSize>::optimized_from_text_spanned(code, arr_len)?;
+    let spans = stream.spans().expect("spanned streams always have spans");
+
+    let lints = analysis::find_nontermination(&stream);
+
+    let mut out = io::stdout().lock();
+
+    for lint in &lints {
+        let start = spans[lint.idx].start;
+        let line_start = code[..start]
+            .iter()
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |pos| pos + 1);
+        let line = code[..start]
+            .iter()
+            .fold(1, |accu, &b| accu + usize::from(b == b'\n'));
+        let col = start - line_start + 1;
+
+        let level = if args.strict { "error" } else { "warning" };
+
+        writeln!(out, "{line}:{col}: {level}: {lint}")?;
+    }
+
+    let array_len = stream.reccomended_array_size();
+
+    match analysis::prove_pointer_bounds(&stream, array_len) {
+        Some(bounds) => writeln!(
+            out,
+            "proved the pointer stays within cells {}..={} of {array_len}",
+            bounds.min, bounds.max
+        )?,
+        None => writeln!(
+            out,
+            "could not prove the pointer stays within the {array_len} cell tape"
+        )?,
+    }
+
+    if args.strict && !lints.is_empty() {
+        return Err(format!("{} non-terminating loops found", lints.len()).into());
     }
 
+    Ok(())
+}
+
+fn decompile<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let code = BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?;
+
+    let mut fp = io::BufWriter::new(io::stdout().lock());
+    code.render_bf(&mut fp)?;
+    fp.flush()?;
+
+    Ok(())
+}
+
+/// writes brainfuck for C written by `compile`, under a pragma line keeping its cell type and
+/// tape size
+fn import_c<CellSize: BfOptimizable>(
+    code: &[u8],
+    mode: Mode,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let code = cimport::import_c::<CellSize>(&String::from_utf8_lossy(code))?;
+
+    let mut fp = io::BufWriter::new(io::stdout().lock());
+
+    write!(fp, "#!bfirs size={}", code.reccomended_array_size())?;
+    if let Some(bits) = mode
+        .to_possible_value()
+        .filter(|_| !matches!(mode, Mode::U8))
+    {
+        write!(fp, " bits={}", bits.get_name())?;
+    }
+    writeln!(fp)?;
+
+    code.render_bf(&mut fp)?;
     fp.flush()?;
 
     Ok(())
 }
 
+/// renders C using consteval for up to `consteval.secs` seconds, or no consteval if it is zero.
+///
+/// When `consteval.reproducible` is set consteval runs for a fixed instruction count instead of a
+/// wall clock deadline, and the C is rendered twice to assert the output is byte for byte identical.
+fn render<CellSize: BfOptimizable>(
+    code: &BfInstructionStream<CellSize>,
+    consteval: Consteval,
+    opts: CCodegenOpts,
+    fp: &mut dyn io::Write,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let render_once = |fp: &mut dyn io::Write| -> Result<(), Box<dyn std::error::Error>> {
+        if consteval.secs != 0 {
+            render_c_deadline(code, consteval, opts, fp)
+        } else {
+            Ok(code.render_c_with(opts, fp)?)
+        }
+    };
+
+    if consteval.reproducible {
+        let mut first = vec![];
+        let mut second = vec![];
+
+        render_once(&mut first)?;
+        render_once(&mut second)?;
+
+        if first != second {
+            return Err(
+                "reproducible: rendering the same program twice produced different C".into(),
+            );
+        }
+
+        fp.write_all(&first)?;
+    } else {
+        render_once(fp)?;
+    }
+
+    Ok(())
+}
+
 #[derive(thiserror::Error, Debug)]
 struct PathIoError(String, #[source] io::Error);
 
@@ -241,6 +1578,118 @@ impl fmt::Display for PathIoError {
     }
 }
 
+/// runs the interpreter subcommand in whichever mode its flags select
+fn interpret_mode(
+    code: &[u8],
+    bits: Option<Mode>,
+    size: Option<u32>,
+    eof: Eof,
+    args: InterpreterArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mode = bits.unwrap_or(Mode::U8);
+
+    let other_mode = args.verify_opt
+        || args.count_only
+        || args.trace.is_some()
+        || args.profile_sample.is_some()
+        || args.access_log.is_some();
+    if eof != Eof::Zero && other_mode {
+        return Err(
+            "--verify-opt, --count-only, --trace, --profile-sample and --access-log only \
+                    support --eof zero"
+                .into(),
+        );
+    }
+
+    match args {
+        args if args.verify_opt => {
+            ice::catch(code, || {
+                with_cell!(mode, |Cell| verify_opt::verify_opt::<Cell>(
+                    code, size, &args
+                ))
+            })??;
+        }
+        args if args.count_only => {
+            ice::catch(code, || {
+                with_cell!(mode, |Cell| count::count::<Cell>(code, size, args.limit))
+            })??;
+        }
+        InterpreterArgs {
+            trace: Some(path),
+            trace_format,
+            limit,
+            ..
+        } => ice::catch(code, || {
+            with_cell!(mode, |Cell| trace::trace::<Cell>(
+                code,
+                size,
+                limit,
+                &path,
+                trace_format
+            ))
+        })??,
+        InterpreterArgs {
+            profile_sample: Some(every),
+            limit,
+            ..
+        } => ice::catch(code, || {
+            with_cell!(mode, |Cell| sample::sample::<Cell>(
+                code, size, every, limit
+            ))
+        })??,
+        InterpreterArgs {
+            access_log: Some(path),
+            access_every,
+            limit,
+            ..
+        } => ice::catch(code, || {
+            with_cell!(mode, |Cell| access::record::<Cell>(
+                code,
+                size,
+                access_every,
+                limit,
+                &path
+            ))
+        })??,
+        args if args.chunked_tape => ice::catch(code, || {
+            with_cell!(mode, |Cell| interpret_chunked::<Cell>(
+                code, size, eof, &args
+            ))
+        })??,
+        args if matches!(mode, Mode::Big) => interpret_big(code, size, eof, &args)?,
+        args => ice::catch(code, || {
+            with_cell!(mode, |Cell| interpret::<Cell>(code, size, eof, &args))
+        })??,
+    }
+
+    Ok(())
+}
+
+/// reads the code given on the command line, or the file it names
+fn read_source(code: Option<String>, file: Option<String>) -> Result<Vec<u8>, PathIoError> {
+    Ok(match code {
+        Some(code) => Vec::from(code),
+        None => match file {
+            Some(f) => std::fs::read(&f).map_err(|e| PathIoError(f, e))?,
+            None => vec![],
+        },
+    })
+}
+
+/// reads the code given on the command line, or the file it names, along with its pragmas
+fn load_source(
+    code: Option<String>,
+    file: Option<String>,
+) -> Result<(Vec<u8>, pragma::Pragmas), Box<dyn std::error::Error>> {
+    let mut code = read_source(code, file)?;
+
+    // drop the pragma line but keep its newline, so line numbers in diagnostics stay the same
+    let (pragmas, skip) = pragma::parse(&code)?;
+    code.drain(..skip);
+
+    Ok((code, pragmas))
+}
+
 fn inner_main() -> Result<(), Box<dyn std::error::Error>> {
     let parse: TopLevel = TopLevel::parse();
 
@@ -250,17 +1699,53 @@ fn inner_main() -> Result<(), Box<dyn std::error::Error>> {
         file,
         bits,
         size,
+        eof,
+        opt_fuel,
     } = parse;
 
-    let code = match code {
-        Some(code) => Vec::from(code),
-        None => match file {
-            Some(f) => std::fs::read(&f).map_err(|e| PathIoError(f, e))?,
-            None => vec![],
+    let _fuel = opt_fuel.map(FuelReport::start);
+
+    if let CompileSwitch::Compile(
+        args @ CompilerArgs {
+            streaming: true, ..
         },
-    };
+    ) = sub
+    {
+        let (code, file) = (code.as_deref(), file.as_deref());
+
+        let (pragmas, skip) = pragma::parse(&pragma::read_line(open_source(code, file)?)?)?;
+        let (mode, size, eof) = (
+            bits.or(pragmas.bits).unwrap_or(Mode::U8),
+            size.or(pragmas.size),
+            eof.or(pragmas.eof).unwrap_or_default().into(),
+        );
+
+        return ice::catch(b"", || {
+            with_cell!(mode, |Cell| compile_streaming::<Cell>(
+                code, file, skip, size, eof, &args
+            ))
+        })?;
+    }
+
+    // the oracle runs programs as written, so the pragma line stays in what is reduced
+    if let CompileSwitch::Reduce(args) = sub {
+        return reduce::reduce(&read_source(code, file)?, &args);
+    }
+
+    let (code, pragmas) = load_source(code, file)?;
+    let (bits, size) = (bits.or(pragmas.bits), size.or(pragmas.size));
+    let mode = bits.unwrap_or(Mode::U8);
+    let eof = Eof::from(eof.or(pragmas.eof).unwrap_or_default());
+
+    // these run programs on input of their own, where only the default makes sense to compare
+    if eof != Eof::Zero && matches!(sub, CompileSwitch::Halts(_) | CompileSwitch::Bench(_)) {
+        return Err("halts and bench only support --eof zero".into());
+    }
 
     match sub {
+        CompileSwitch::Reduce(_) => unreachable!("reduce is handled before loading the source"),
+        CompileSwitch::Snapshot(args) => tape_file::snapshot(&args)?,
+        CompileSwitch::PlotAccess(args) => access::plot(&args)?,
         CompileSwitch::Completions(args) => {
             let mut cmd = TopLevel::command();
             let cname = cmd.get_name().to_owned();
@@ -272,22 +1757,55 @@ fn inner_main() -> Result<(), Box<dyn std::error::Error>> {
 
             io::stdout().write_all(&out)?;
         }
-        CompileSwitch::Compile(args) => match bits.unwrap_or(Mode::U8) {
-            Mode::U8 => compile::<u8>(&code, size, args),
-            Mode::U16 => compile::<u16>(&code, size, args),
-            Mode::U32 => compile::<u32>(&code, size, args),
-        }?,
-        CompileSwitch::Interpret(args) => match bits.unwrap_or(Mode::U8) {
-            Mode::U8 => interpret::<u8>(&code, size, args),
-            Mode::U16 => interpret::<u16>(&code, size, args),
-            Mode::U32 => interpret::<u32>(&code, size, args),
-        }?,
+        CompileSwitch::Compile(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| compile::<Cell>(&code, size, eof, &args))
+        })??,
+        CompileSwitch::Interpret(args) => interpret_mode(&code, bits, size, eof, args)?,
+        CompileSwitch::Run(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| run::run::<Cell>(&code, size, eof, &args))
+        })??,
+        CompileSwitch::Explain => ice::catch(&code, || {
+            with_cell!(mode, |Cell| explain::explain::<Cell>(&code, size))
+        })??,
+        CompileSwitch::Halts(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| halts::<Cell>(&code, size, args))
+        })??,
+        CompileSwitch::Lint(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| lint::<Cell>(&code, size, args))
+        })??,
+        CompileSwitch::Engines => ice::catch(&code, || {
+            with_cell!(mode, |Cell| engines::engines::<Cell>())
+        })??,
+        CompileSwitch::Decompile => ice::catch(&code, || {
+            with_cell!(mode, |Cell| decompile::<Cell>(&code, size))
+        })??,
+        CompileSwitch::ImportC => ice::catch(&code, || {
+            with_cell!(mode, |Cell| import_c::<Cell>(&code, mode))
+        })??,
+        CompileSwitch::VerifyC(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| verify_c::verify_c::<Cell>(
+                &code, size, eof, &args
+            ))
+        })??,
+        CompileSwitch::TraceView(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| trace::view::<Cell>(&code, size, &args))
+        })??,
+        CompileSwitch::Debug(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| debugger::debug::<Cell>(
+                &code, size, eof, &args
+            ))
+        })??,
+        CompileSwitch::Bench(args) => ice::catch(&code, || {
+            with_cell!(mode, |Cell| bench::bench::<Cell>(&code, size, &args))
+        })??,
     }
 
     Ok(())
 }
 
 fn main() -> ExitCode {
+    ice::install_hook();
+
     match inner_main() {
         Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
@@ -300,3 +1818,133 @@ fn main() -> ExitCode {
         }
     }
 }
+
+#[cfg(unix)]
+#[test]
+fn test_cc_pipe_finish() {
+    let spawn = |script: &str| {
+        let mut child = Command::new("sh")
+            .args(["-c", script])
+            .stdin(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let stdin = io::BufWriter::new(child.stdin.take().unwrap());
+
+        COutput::Cc {
+            cc: "sh".to_owned(),
+            child,
+            stdin,
+        }
+    };
+
+    // larger than a pipe buffer, so a compiler that never reads it breaks the pipe
+    let c = vec![b' '; 1 << 20];
+
+    let mut out = spawn("cat > /dev/null");
+    let rendered = out.writer().write_all(&c).map_err(Into::into);
+    out.finish(rendered).unwrap();
+
+    let mut out = spawn("exit 3");
+    let rendered = out.writer().write_all(&c).map_err(Into::into);
+    let err = out.finish(rendered).unwrap_err().to_string();
+    assert!(err.contains("sh failed to compile"), "{err}");
+
+    // a failed render is still reported when the compiler is fine with what it got
+    let out = spawn("cat > /dev/null");
+    assert!(out.finish(Err("render failed".into())).is_err());
+}
+
+#[test]
+fn test_calibrate() {
+    assert_eq!(
+        per_tenth_second(1_000_000, Duration::from_millis(50)),
+        2_000_000
+    );
+    assert_eq!(per_tenth_second(0, Duration::from_millis(50)), 1);
+    assert_eq!(per_tenth_second(u64::MAX, Duration::ZERO), u64::MAX);
+
+    // a program that finishes while calibrating is prerun completely, as without calibration
+    let code =
+        BfInstructionStream::<u8>::optimized_from_text(b"++++[>++++<-]>.".iter().copied(), None)
+            .unwrap();
+
+    let render_with = |calibrate| {
+        let mut c = vec![];
+
+        let consteval = Consteval {
+            calibrate,
+            ..Consteval::default()
+        };
+
+        render(&code, consteval, CCodegenOpts::default(), &mut c).unwrap();
+
+        c
+    };
+
+    assert_eq!(render_with(true), render_with(false));
+}
+
+#[test]
+fn test_stdin_prefix() {
+    assert_eq!(parse_hex("00ff7A").unwrap().0, [0, 0xff, 0x7a]);
+    assert!(parse_hex("").unwrap().0.is_empty());
+    assert!(parse_hex("abc").is_err());
+    assert!(parse_hex("+1").is_err());
+    assert!(parse_hex("zz").is_err());
+
+    // the input a program sees when stdin holds "cd"
+    let input = |flags: &[&str]| {
+        let cli = TopLevel::try_parse_from(["bf", "i", "-c", ","].iter().chain(flags)).unwrap();
+
+        let CompileSwitch::Interpret(args) = cli.sub else {
+            unreachable!("parsed an interpret command");
+        };
+
+        let mut input = vec![];
+        args.input(&b"cd"[..]).read_to_end(&mut input).unwrap();
+
+        input
+    };
+
+    assert_eq!(input(&["--stdin-bytes", "ab"]), b"abcd");
+    assert_eq!(input(&["--stdin-hex", "6162"]), b"abcd");
+    assert_eq!(input(&[]), b"cd");
+
+    // stdin is not read after a whole input
+    assert_eq!(input(&["--stdin-str", "ab"]), b"ab");
+    assert_eq!(input(&["--stdin-hex", "6162", "--no-stdin"]), b"ab");
+    assert_eq!(input(&["--stdin-bytes", "ab", "--no-stdin"]), b"ab");
+    assert!(input(&["--no-stdin"]).is_empty());
+
+    // the two prefixes conflict
+    assert!(
+        TopLevel::try_parse_from(["bf", "i", "--stdin-hex", "61", "--stdin-bytes", "a"]).is_err()
+    );
+}
+
+#[test]
+fn test_consteval_output_cap() {
+    use bfirs::compiler::BfInstruc;
+
+    let code =
+        BfInstructionStream::<u8>::optimized_from_text(b"+.+.+.".iter().copied(), None).unwrap();
+
+    let mut execenv = BrainFuckExecutorBuilder::<u8, _, _>::new()
+        .array_len(1)
+        .stream_in(ErrorReader)
+        .stream_out(CappedOutput {
+            buf: vec![],
+            cap: 2,
+        })
+        .build()
+        .unwrap();
+
+    // the write past the cap fails without being buffered, so consteval resumes at it
+    let err = execenv.run(&code).unwrap_err();
+
+    assert!(matches!(err.source, BfExecErrorTy::IOError(_)));
+    assert_eq!(code[err.idx], BfInstruc::Write);
+    assert_eq!(execenv.stdout.buf, [1, 2]);
+    assert_eq!(execenv.data[0], 3);
+}
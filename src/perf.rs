@@ -0,0 +1,115 @@
+//! Hardware performance counters for `bench`, read through the linux `perf_event_open` syscall
+
+use std::{
+    fs::File,
+    io::{self, Read},
+    os::fd::{AsRawFd, FromRawFd},
+};
+
+/// The leading fields of the kernels `perf_event_attr`, which it accepts at this size as
+/// `PERF_ATTR_SIZE_VER0`
+#[repr(C)]
+#[derive(Default)]
+struct PerfEventAttr {
+    kind: u32,
+    size: u32,
+    config: u64,
+    sample_period: u64,
+    sample_type: u64,
+    read_format: u64,
+    flags: u64,
+    wakeup_events: u32,
+    bp_type: u32,
+    config1: u64,
+}
+
+const PERF_TYPE_HARDWARE: u32 = 0;
+
+const FLAG_DISABLED: u64 = 1 << 0;
+const FLAG_EXCLUDE_KERNEL: u64 = 1 << 5;
+const FLAG_EXCLUDE_HV: u64 = 1 << 6;
+
+const PERF_FLAG_FD_CLOEXEC: libc::c_ulong = 1 << 3;
+
+const PERF_EVENT_IOC_ENABLE: libc::Ioctl = 0x2400;
+const PERF_EVENT_IOC_DISABLE: libc::Ioctl = 0x2401;
+const PERF_EVENT_IOC_RESET: libc::Ioctl = 0x2403;
+
+/// The reported events, by name and `PERF_COUNT_HW_*` config
+const EVENTS: [(&str, u64); 4] = [
+    ("cycles", 0),
+    ("instructions", 1),
+    ("cache misses", 3),
+    ("branch misses", 5),
+];
+
+/// Opens a disabled counter for the hardware event `config` on this thread, in user space only
+fn open(config: u64) -> io::Result<File> {
+    let attr = PerfEventAttr {
+        kind: PERF_TYPE_HARDWARE,
+        size: u32::try_from(size_of::<PerfEventAttr>()).expect("attr size fits in u32"),
+        config,
+        flags: FLAG_DISABLED | FLAG_EXCLUDE_KERNEL | FLAG_EXCLUDE_HV,
+        ..Default::default()
+    };
+
+    // SAFETY: attr is a valid perf_event_attr of the size it declares, and outlives the call
+    let fd = unsafe {
+        libc::syscall(
+            libc::SYS_perf_event_open,
+            &attr,
+            0,
+            -1,
+            -1,
+            PERF_FLAG_FD_CLOEXEC,
+        )
+    };
+
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let fd = i32::try_from(fd).expect("file descriptors fit in an i32");
+
+    // SAFETY: the kernel just handed us this descriptor, nothing else owns it
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+fn ioctl(counter: &File, request: libc::Ioctl) -> io::Result<()> {
+    // SAFETY: perf event ioctls with a zero argument only act on the descriptor itself
+    if unsafe { libc::ioctl(counter.as_raw_fd(), request, 0) } < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Runs `func` with every counter enabled, returning its result and the count of each event
+pub fn measure<R>(func: impl FnOnce() -> R) -> io::Result<(R, Vec<(&'static str, u64)>)> {
+    let counters = EVENTS
+        .iter()
+        .map(|&(name, config)| Ok((name, open(config)?)))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    for (_, counter) in &counters {
+        ioctl(counter, PERF_EVENT_IOC_RESET)?;
+        ioctl(counter, PERF_EVENT_IOC_ENABLE)?;
+    }
+
+    let ret = func();
+
+    for (_, counter) in &counters {
+        ioctl(counter, PERF_EVENT_IOC_DISABLE)?;
+    }
+
+    let counts = counters
+        .into_iter()
+        .map(|(name, mut counter)| {
+            let mut count = [0; 8];
+            counter.read_exact(&mut count)?;
+            Ok((name, u64::from_ne_bytes(count)))
+        })
+        .collect::<io::Result<_>>()?;
+
+    Ok((ret, counts))
+}
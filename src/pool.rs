@@ -0,0 +1,141 @@
+//! Reusable executors for services that run many small programs
+
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use crate::compiler::BfOptimizable;
+use crate::interpreter::{BrainFuckExecutor, BrainFuckExecutorBuilder};
+
+/// A pool of tapes that executors borrow and return, so that each run reuses an allocation instead
+/// of zeroing a fresh multi megabyte tape. Tapes are cleared as they are returned.
+pub struct ExecutorPool<T> {
+    tapes: Mutex<Vec<Box<[T]>>>,
+    array_len: usize,
+    max_idle: usize,
+}
+
+impl<T: BfOptimizable> ExecutorPool<T> {
+    /// Creates a pool of tapes `array_len` cells long, keeping at most `max_idle` unused tapes
+    #[must_use]
+    pub const fn new(array_len: usize, max_idle: usize) -> Self {
+        Self {
+            tapes: Mutex::new(vec![]),
+            array_len,
+            max_idle,
+        }
+    }
+
+    /// Allocates tapes up front until `count` are idle, bounded by the idle limit
+    pub fn preallocate(&self, count: usize) {
+        let mut tapes = self.lock();
+
+        while tapes.len() < count.min(self.max_idle) {
+            tapes.push(vec![T::ZERO; self.array_len].into_boxed_slice());
+        }
+    }
+
+    /// Returns how many tapes are waiting to be reused
+    #[must_use]
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Returns an executor on a zeroed tape, which goes back to the pool when it is dropped
+    #[must_use]
+    // this will not panic: all required arguments have been provided to the builder
+    #[allow(clippy::missing_panics_doc)]
+    pub fn executor<I: io::Read, O: io::Write>(
+        &self,
+        stream_in: I,
+        stream_out: O,
+    ) -> PooledExecutor<'_, T, I, O> {
+        let tape = self
+            .lock()
+            .pop()
+            .unwrap_or_else(|| vec![T::ZERO; self.array_len].into_boxed_slice());
+
+        let executor = BrainFuckExecutorBuilder::new()
+            .stream_in(stream_in)
+            .stream_out(stream_out)
+            .tape(tape)
+            .build()
+            .expect("this panic should not occur, minimum builder fields are present");
+
+        PooledExecutor {
+            pool: self,
+            executor,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Box<[T]>>> {
+        // the tape list is always valid, even if a thread panicked while holding it
+        self.tapes
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// An executor borrowed from an [`ExecutorPool`], dereferencing to the executor itself
+pub struct PooledExecutor<'p, T: BfOptimizable, I: io::Read, O: io::Write> {
+    pool: &'p ExecutorPool<T>,
+    executor: BrainFuckExecutor<T, I, O>,
+}
+
+impl<T: BfOptimizable, I: io::Read, O: io::Write> Deref for PooledExecutor<'_, T, I, O> {
+    type Target = BrainFuckExecutor<T, I, O>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.executor
+    }
+}
+
+impl<T: BfOptimizable, I: io::Read, O: io::Write> DerefMut for PooledExecutor<'_, T, I, O> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.executor
+    }
+}
+
+impl<T: BfOptimizable, I: io::Read, O: io::Write> Drop for PooledExecutor<'_, T, I, O> {
+    fn drop(&mut self) {
+        let mut tape = std::mem::take(&mut self.executor.data);
+
+        // a tape swapped out by the caller is not ours to keep
+        if tape.len() != self.pool.array_len {
+            return;
+        }
+
+        if self.pool.idle() >= self.pool.max_idle {
+            return;
+        }
+
+        // zero the tape without holding the lock, so other threads are not kept waiting on it
+        tape.fill(T::ZERO);
+
+        let mut tapes = self.pool.lock();
+
+        if tapes.len() < self.pool.max_idle {
+            tapes.push(tape);
+        }
+    }
+}
+
+#[test]
+fn test_executor_pool() {
+    use crate::compiler::BfInstructionStream;
+
+    let pool = ExecutorPool::<u8>::new(4, 2);
+    pool.preallocate(5);
+    assert_eq!(pool.idle(), 2);
+
+    let code = BfInstructionStream::optimized_from_text(b"+++>+.".iter().copied(), None).unwrap();
+
+    for _ in 0..3 {
+        let mut env = pool.executor(io::empty(), vec![]);
+        assert_eq!(env.state(), (0, &[0; 4][..]));
+        env.run(&code).unwrap();
+        assert_eq!(env.stdout, [1]);
+    }
+
+    assert_eq!(pool.idle(), 2);
+}
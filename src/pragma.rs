@@ -0,0 +1,77 @@
+//! Execution parameters embedded in a `#!bfirs` first line of the source
+
+use std::io::{self, BufRead};
+
+use clap::ValueEnum;
+
+use crate::{EofArg, Mode};
+
+const PREFIX: &[u8] = b"#!bfirs";
+
+/// longest pragma line read from a source that is not loaded whole
+const MAX_LINE: u64 = 4096;
+
+/// Parameters a program asks for, explicit command line flags take precedence over these
+#[derive(Default)]
+pub struct Pragmas {
+    pub bits: Option<Mode>,
+    pub size: Option<u32>,
+    pub eof: Option<EofArg>,
+}
+
+/// Parses the pragma line at the start of `code` if there is one, returning the pragmas and how
+/// many bytes of `code` the line covers, not counting its newline. The line must be dropped before
+/// compiling, as values like `eof=-1` contain brainfuck instructions.
+pub fn parse(code: &[u8]) -> Result<(Pragmas, usize), Box<dyn std::error::Error>> {
+    let mut pragmas = Pragmas::default();
+
+    let Some(line) = code.strip_prefix(PREFIX) else {
+        return Ok((pragmas, 0));
+    };
+
+    let line = &line[..line.iter().position(|&b| b == b'\n').unwrap_or(line.len())];
+
+    for word in String::from_utf8_lossy(line).split_whitespace() {
+        let Some((key, value)) = word.split_once('=') else {
+            return Err(format!("#!bfirs pragma `{word}` is not of the form key=value").into());
+        };
+
+        match key {
+            "bits" => {
+                pragmas.bits = Some(Mode::from_str(value, false).map_err(|_| {
+                    format!("#!bfirs pragma bits={value}, expected 8, 12, 16, 24, 32, 64, big, i8, i16 or i32")
+                })?);
+            }
+            "size" => {
+                pragmas.size = Some(
+                    value
+                        .parse()
+                        .map_err(|e| format!("#!bfirs pragma size={value}: {e}"))?,
+                );
+            }
+            "eof" => {
+                pragmas.eof = Some(match value {
+                    "0" => EofArg::Zero,
+                    "-1" => EofArg::MinusOne,
+                    "unchanged" => EofArg::Unchanged,
+                    _ => {
+                        return Err(format!(
+                            "#!bfirs pragma eof={value}, expected 0, -1 or unchanged"
+                        )
+                        .into())
+                    }
+                });
+            }
+            _ => return Err(format!("unknown #!bfirs pragma `{key}`").into()),
+        }
+    }
+
+    Ok((pragmas, PREFIX.len() + line.len()))
+}
+
+/// Reads the first line of `source`, enough to [`parse`] its pragmas without loading all of it
+pub fn read_line(source: impl io::Read) -> io::Result<Vec<u8>> {
+    let mut line = vec![];
+    io::BufReader::new(source.take(MAX_LINE)).read_until(b'\n', &mut line)?;
+    Ok(line)
+}
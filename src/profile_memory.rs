@@ -0,0 +1,54 @@
+use std::{
+    fs,
+    io::{self, Write},
+    mem,
+};
+
+use bfirs::compiler::{BfInstruc, BfOptimizable};
+
+/// Peak resident set size of this process in bytes, from `/proc/self/status` where available
+fn peak_rss() -> Option<u64> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+
+    Some(kib * 1024)
+}
+
+/// Writes the memory used by a finished run to stderr: peak RSS, the size of the compiled stream,
+/// and how much of the tape was allocated and still holds data
+pub fn report<CellSize: BfOptimizable>(
+    code: &[BfInstruc<CellSize>],
+    tape: &[CellSize],
+) -> io::Result<()> {
+    let mut out = io::stderr().lock();
+
+    match peak_rss() {
+        Some(bytes) => writeln!(out, "peak rss        {bytes:>14} bytes")?,
+        None => writeln!(out, "peak rss        {:>14}", "unavailable")?,
+    }
+
+    writeln!(
+        out,
+        "compiled stream {:>14} bytes ({} instructions)",
+        mem::size_of_val(code),
+        code.len()
+    )?;
+
+    let nonzero = tape.iter().filter(|&&cell| cell != CellSize::ZERO).count();
+    let highest = tape.iter().rposition(|&cell| cell != CellSize::ZERO);
+
+    writeln!(
+        out,
+        "tape            {:>14} bytes ({} cells, {nonzero} nonzero at exit, highest nonzero {})",
+        mem::size_of_val(tape),
+        tape.len(),
+        highest.map_or_else(|| "none".to_owned(), |idx| idx.to_string())
+    )
+}
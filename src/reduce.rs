@@ -0,0 +1,340 @@
+//! Shrinking a program while an oracle command keeps accepting it, for minimal bug reports
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bfirs::token::is_op;
+use clap::Args;
+
+use crate::{pragma, temp_dir::TempDir, PathIoError};
+
+#[derive(Args)]
+/// shrink a program to a small one that an oracle command still accepts, for bug reports
+pub struct ReduceArgs {
+    /// shell command given the path of a candidate program as $1, candidates it exits
+    /// successfully on are kept
+    #[arg(long)]
+    oracle: String,
+
+    /// seconds to give each oracle run, candidates it runs out of time on are rejected, as
+    /// removing code easily leaves an endless loop
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    timeout: u64,
+
+    /// file to write the reduced program to, defaults to stdout
+    #[arg(short, long)]
+    output: Option<String>,
+}
+
+/// Runs the oracle on candidates, remembering every verdict so no candidate is run twice
+struct Oracle<'a> {
+    command: &'a str,
+    timeout: Duration,
+    /// where candidates are written, inside a directory no other user can plant a file in
+    path: PathBuf,
+    _dir: TempDir,
+    /// the pragma line, which is passed on to every candidate untouched
+    prefix: &'a [u8],
+    /// unbalanced candidates are rejected without running them, unless the original was
+    /// unbalanced itself
+    balanced: bool,
+    seen: HashMap<Vec<u8>, bool>,
+    runs: u64,
+}
+
+impl<'a> Oracle<'a> {
+    fn new(command: &'a str, timeout: Duration, prefix: &'a [u8], body: &[u8]) -> io::Result<Self> {
+        let dir = TempDir::new("bf-reduce")?;
+
+        Ok(Self {
+            command,
+            timeout,
+            path: dir.path().join("candidate.b"),
+            _dir: dir,
+            prefix,
+            balanced: is_balanced(body),
+            seen: HashMap::new(),
+            runs: 0,
+        })
+    }
+
+    fn accepts(&mut self, body: &[u8]) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.balanced && !is_balanced(body) {
+            return Ok(false);
+        }
+
+        if let Some(&verdict) = self.seen.get(body) {
+            return Ok(verdict);
+        }
+
+        let mut candidate = self.prefix.to_vec();
+        candidate.extend_from_slice(body);
+
+        let path = self.path.display().to_string();
+        fs::write(&self.path, candidate).map_err(|e| PathIoError(path.clone(), e))?;
+
+        self.runs += 1;
+
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(self.command)
+            .arg("sh")
+            .arg(&self.path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+
+        // a group of its own lets a timeout kill everything the oracle started, not just the shell
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+        let child = command
+            .spawn()
+            .map_err(|e| format!("could not run the oracle: {e}"))?;
+
+        let verdict = wait_timeout(child, self.timeout)?.is_some_and(|status| status.success());
+
+        self.seen.insert(body.to_vec(), verdict);
+
+        Ok(verdict)
+    }
+}
+
+/// waits for `child` to exit, killing it along with the process group it leads, if any, and
+/// returning `None` if it takes longer than `timeout`
+pub fn wait_timeout(mut child: Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let start = Instant::now();
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+
+        if start.elapsed() > timeout {
+            #[cfg(unix)]
+            if let Ok(pid) = libc::pid_t::try_from(child.id()) {
+                // SAFETY: kill has no memory safety requirements
+                unsafe { libc::kill(-pid, libc::SIGKILL) };
+            }
+
+            _ = child.kill();
+            child.wait()?;
+
+            return Ok(None);
+        }
+
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn is_balanced(code: &[u8]) -> bool {
+    let mut depth = 0u64;
+
+    for &b in code {
+        match b {
+            b'[' => depth += 1,
+            b']' => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return false,
+            },
+            _ => {}
+        }
+    }
+
+    depth == 0
+}
+
+/// index of the `]` closing the loop opened at `start`
+fn loop_end(code: &[u8], start: usize) -> Option<usize> {
+    let mut depth = 0u64;
+
+    for (idx, &b) in code.iter().enumerate().skip(start) {
+        match b {
+            b'[' => depth += 1,
+            b']' => {
+                depth -= 1;
+
+                if depth == 0 {
+                    return Some(idx);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// `code` with the bytes at `ranges` removed, the ranges must be in order and not overlap
+fn without(code: &[u8], ranges: &[(usize, usize)]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut from = 0;
+
+    for &(start, end) in ranges {
+        out.extend_from_slice(&code[from..start]);
+        from = end;
+    }
+
+    out.extend_from_slice(&code[from..]);
+    out
+}
+
+/// tries removing each loop whole, then just its brackets, keeping the body
+fn simplify_loops(
+    mut code: Vec<u8>,
+    oracle: &mut Oracle,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut idx = 0;
+
+    while let Some(start) = code[idx..].iter().position(|&b| b == b'[').map(|i| i + idx) {
+        idx = start + 1;
+
+        let Some(end) = loop_end(&code, start) else {
+            continue;
+        };
+
+        for candidate in [
+            without(&code, &[(start, end + 1)]),
+            without(&code, &[(start, start + 1), (end, end + 1)]),
+        ] {
+            if oracle.accepts(&candidate)? {
+                code = candidate;
+                idx = start;
+                break;
+            }
+        }
+    }
+
+    Ok(code)
+}
+
+/// tries removing chunks of ever smaller size, down to single bytes
+fn remove_chunks(
+    mut code: Vec<u8>,
+    oracle: &mut Oracle,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut size = (code.len() / 2).max(1);
+
+    loop {
+        let mut start = 0;
+
+        while start < code.len() {
+            let end = (start + size).min(code.len());
+            let candidate = without(&code, &[(start, end)]);
+
+            if oracle.accepts(&candidate)? {
+                code = candidate;
+            } else {
+                start = end;
+            }
+        }
+
+        if size == 1 {
+            return Ok(code);
+        }
+
+        size /= 2;
+    }
+}
+
+pub fn reduce(code: &[u8], args: &ReduceArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let skip = pragma::parse(code).map_or(0, |(_, skip)| skip);
+    let (prefix, body) = code.split_at(skip);
+
+    let mut oracle = Oracle::new(
+        &args.oracle,
+        Duration::from_secs(args.timeout),
+        prefix,
+        body,
+    )?;
+
+    if !oracle.accepts(body)? {
+        return Err(
+            "the oracle does not accept the original program, there is nothing to keep".into(),
+        );
+    }
+
+    let mut best = body.to_vec();
+
+    // comments are usually most of a program, and rarely what the oracle is looking for
+    let ops = best
+        .iter()
+        .copied()
+        .filter(|&b| is_op(b))
+        .collect::<Vec<_>>();
+    if ops != best && oracle.accepts(&ops)? {
+        best = ops;
+    }
+
+    // every accepted candidate is shorter, so this ends once a round removes nothing
+    loop {
+        let before = best.len();
+
+        best = simplify_loops(best, &mut oracle)?;
+        best = remove_chunks(best, &mut oracle)?;
+
+        if best.len() == before {
+            break;
+        }
+    }
+
+    let mut reduced = prefix.to_vec();
+    reduced.extend_from_slice(&best);
+
+    eprintln!(
+        "reduce: {} bytes down to {} in {} oracle runs",
+        code.len(),
+        reduced.len(),
+        oracle.runs
+    );
+
+    match &args.output {
+        Some(path) => fs::write(path, reduced).map_err(|e| PathIoError(path.clone(), e))?,
+        None => io::stdout().write_all(&reduced)?,
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_is_balanced() {
+    assert!(is_balanced(b""));
+    assert!(is_balanced(b"+[-[>]<]."));
+    assert!(!is_balanced(b"[[]"));
+    assert!(!is_balanced(b"]["));
+}
+
+#[test]
+fn test_loop_end() {
+    let code = b"+[-[>]<]";
+
+    assert_eq!(loop_end(code, 1), Some(7));
+    assert_eq!(loop_end(code, 3), Some(5));
+    assert_eq!(loop_end(b"+[[-]", 1), None);
+}
+
+#[test]
+fn test_without() {
+    assert_eq!(without(b"abcdef", &[]), b"abcdef");
+    assert_eq!(without(b"abcdef", &[(0, 1), (3, 5)]), b"bcf");
+    assert_eq!(without(b"abcdef", &[(0, 6)]), b"");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_simplify_loops() {
+    let body = b"+[>,<-][-]";
+    let mut oracle = Oracle::new(r#"grep -q , "$1""#, Duration::from_secs(10), b"", body).unwrap();
+
+    // the read keeps its loop's body but not the brackets, the loop without one goes entirely
+    let code = simplify_loops(body.to_vec(), &mut oracle).unwrap();
+    assert_eq!(code, b"+>,<-");
+}
@@ -0,0 +1,181 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+};
+
+use bfirs::analysis;
+use bfirs::compiler::{BfInstructionStream, BfOptimizable, CCodegenOpts, Eof};
+use clap::Args;
+use sha2::{Digest, Sha256};
+
+use crate::{interpret, render_static_prefix, system_cc, InterpreterArgs, PathIoError};
+
+#[derive(Args)]
+/// run brainfuck the fastest available way, as a cached native executable when a C compiler is
+/// available and the program provably stays on its tape, otherwise in the interpreter
+pub struct RunArgs {
+    /// always run in the interpreter
+    #[arg(long)]
+    interpret: bool,
+}
+
+/// where compiled programs are kept between runs, `$XDG_CACHE_HOME/bfirs` or `~/.cache/bfirs`
+fn cache_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|cache| cache.join("bfirs"))
+}
+
+/// whether only this user can change what `meta` describes, as anyone else who can could make
+/// `run` execute a program of their choosing
+#[cfg(unix)]
+fn is_private(meta: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    // SAFETY: geteuid has no memory safety requirements and can not fail
+    meta.uid() == unsafe { libc::geteuid() } && meta.mode() & 0o022 == 0
+}
+
+#[cfg(not(unix))]
+fn is_private(_meta: &fs::Metadata) -> bool {
+    true
+}
+
+/// returns an executable built from `c`, compiling it into the cache if it is not already there,
+/// or `None` if there is no cache or no working C compiler
+pub fn cached_executable(c: &[u8]) -> Option<PathBuf> {
+    cached_executable_in(&cache_dir()?, &system_cc(), c)
+}
+
+/// returns an executable built from `c` with `cc`, kept in `dir`. A cache directory or executable
+/// that someone else could have replaced is never run, the directory is refused and the executable
+/// built again.
+fn cached_executable_in(dir: &Path, cc: &str, c: &[u8]) -> Option<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+    builder.create(dir).ok()?;
+
+    if !is_private(&fs::metadata(dir).ok()?) {
+        return None;
+    }
+
+    // a hit is run without comparing what it was built from, so the name must be a hash nobody
+    // can find a collision for. The compiler name can not hold a NUL, which keeps it apart from
+    // the C.
+    let key = Sha256::new()
+        .chain_update(cc)
+        .chain_update([0])
+        .chain_update(c)
+        .finalize();
+    let exe = dir.join(format!("{key:x}"));
+
+    // the executable is only trusted if it is a plain file, not a link to somewhere else
+    if fs::symlink_metadata(&exe).is_ok_and(|meta| meta.is_file() && is_private(&meta)) {
+        return Some(exe);
+    }
+
+    // build beside the final path and rename into place, so a concurrent run never sees a
+    // partially written executable
+    let partial = exe.with_extension(process::id().to_string());
+
+    let mut child = Command::new(cc)
+        .args(["-O2", "-x", "c", "-", "-o"])
+        .arg(&partial)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let wrote = child.stdin.take().map(|mut stdin| stdin.write_all(c));
+    let built = child.wait().is_ok_and(|status| status.success());
+
+    if !(built && matches!(wrote, Some(Ok(())))) {
+        _ = fs::remove_file(&partial);
+        return None;
+    }
+
+    fs::rename(&partial, &exe).ok()?;
+
+    Some(exe)
+}
+
+pub fn run<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    eof: Eof,
+    args: &RunArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !args.interpret {
+        let stream =
+            BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?;
+
+        // compiled C does not check pointer bounds, so only programs proven to stay on the tape
+        // can run natively without turning a clean error into undefined behaviour
+        if analysis::prove_pointer_bounds(&stream, stream.reccomended_array_size()).is_some() {
+            let mut c = vec![];
+            let opts = CCodegenOpts {
+                eof,
+                ..CCodegenOpts::default()
+            };
+            render_static_prefix(&stream, opts, &mut c)?;
+
+            if let Some(exe) = cached_executable(&c) {
+                let status = Command::new(&exe)
+                    .status()
+                    .map_err(|e| PathIoError(exe.display().to_string(), e))?;
+
+                if !status.success() {
+                    return Err(format!("program exited unsuccessfully ({status})").into());
+                }
+
+                return Ok(());
+            }
+        }
+    }
+
+    interpret::<CellSize>(code, arr_len, eof, &InterpreterArgs::default())
+}
+
+#[cfg(unix)]
+#[test]
+fn test_cached_executable() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = crate::temp_dir::TempDir::new("bf-test-cache").unwrap();
+    let cc = system_cc();
+    let c = b"int main(void) { return 0; }";
+
+    // without a working C compiler there is nothing to cache
+    let Some(exe) = cached_executable_in(dir.path(), &cc, c) else {
+        return;
+    };
+
+    // named by a sha-256 of the compiler and the C
+    assert_eq!(exe.file_name().map(std::ffi::OsStr::len), Some(64));
+
+    // a hit is returned as it is, without building it again
+    fs::write(&exe, b"cached").unwrap();
+    assert_eq!(cached_executable_in(dir.path(), &cc, c), Some(exe.clone()));
+    assert_eq!(fs::read(&exe).unwrap(), b"cached");
+
+    // other code misses
+    let other = cached_executable_in(dir.path(), &cc, b"int main(void) { return 1; }");
+    assert!(other.is_some_and(|other| other != exe));
+
+    // an executable anyone can write to is not trusted, and is built again
+    fs::set_permissions(&exe, fs::Permissions::from_mode(0o777)).unwrap();
+    assert_eq!(cached_executable_in(dir.path(), &cc, c), Some(exe.clone()));
+    assert_ne!(fs::read(&exe).unwrap(), b"cached");
+
+    // as is a cache directory anyone can write to
+    fs::set_permissions(dir.path(), fs::Permissions::from_mode(0o777)).unwrap();
+    assert_eq!(cached_executable_in(dir.path(), &cc, c), None);
+}
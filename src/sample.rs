@@ -0,0 +1,131 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    convert::Infallible,
+    io::{self, Write},
+};
+
+use bfirs::compiler::{BfInstruc, BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::{BfExecError, BfExecErrorTy, BrainFuckExecutor};
+use bfirs::token::LineIndex;
+
+/// source lines shown in the report
+const REPORT_LINES: usize = 10;
+
+/// Runs `code` from instruction `start` for at most `limit` instructions, in chunks of `every`
+/// through the limited loop. After every chunk `on_chunk` is given the executor, the instructions
+/// that chunk ran, and the instruction it paused on, or `None` once the program stopped for any
+/// other reason than the chunk running out. An error from `on_chunk` ends the run.
+pub fn run_chunked<CellSize: BfOptimizable, I: io::Read, O: io::Write, E>(
+    execenv: &mut BrainFuckExecutor<CellSize, I, O>,
+    code: &[BfInstruc<CellSize>],
+    start: usize,
+    every: u64,
+    limit: u64,
+    mut on_chunk: impl FnMut(&BrainFuckExecutor<CellSize, I, O>, u64, Option<usize>) -> Result<(), E>,
+) -> Result<Result<(), BfExecError>, E> {
+    let mut left = limit;
+    let mut idx = start;
+
+    loop {
+        let chunk = every.min(left);
+        execenv.instruction_limit = chunk;
+
+        let res = execenv.run_limited_from(code, idx);
+        let ran = chunk - execenv.instructions_left();
+        left -= ran;
+
+        match res {
+            Err(BfExecError {
+                source: BfExecErrorTy::NotEnoughInstructions,
+                idx: at,
+            }) => {
+                on_chunk(execenv, ran, Some(at))?;
+
+                if left == 0 {
+                    return Ok(res);
+                }
+
+                idx = at;
+            }
+            res => {
+                on_chunk(execenv, ran, None)?;
+                return Ok(res);
+            }
+        }
+    }
+}
+
+/// Runs `code` on stdio, recording the instruction about to run every `every` instructions, then
+/// reports which source lines the samples fell on to stderr. The program runs in chunks through
+/// the limited loop, so sampling costs one resume per chunk rather than work per instruction.
+pub fn sample<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    every: u64,
+    limit: Option<u64>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = code;
+    let code = BfInstructionStream::<CellSize>::optimized_from_text_spanned(code, arr_len)?;
+    let spans = code.spans().expect("spanned streams always have spans");
+
+    let mut execenv =
+        BrainFuckExecutor::new_stdio_locked::<CellSize>(code.reccomended_array_size());
+
+    // jump points are u32, so every instruction index fits one
+    let mut samples = Vec::<u32>::new();
+
+    let Ok(result) = run_chunked(
+        &mut execenv,
+        &code,
+        0,
+        every,
+        limit.unwrap_or(u64::MAX),
+        |_, _, at| {
+            samples
+                .extend(at.map(|at| u32::try_from(at).expect("instruction indexes fit in a u32")));
+            Ok::<_, Infallible>(())
+        },
+    );
+
+    execenv.stdout.flush()?;
+
+    let lines = LineIndex::new(source);
+    let text: Vec<&[u8]> = source.split(|&b| b == b'\n').collect();
+
+    let mut by_line = BTreeMap::<usize, u64>::new();
+
+    for &sample in &samples {
+        let (line, _) = lines.line_col(spans[sample as usize].start);
+        *by_line.entry(line).or_default() += 1;
+    }
+
+    let mut by_line: Vec<_> = by_line.into_iter().collect();
+    by_line.sort_by_key(|&(line, count)| (Reverse(count), line));
+
+    let mut out = io::stderr().lock();
+
+    writeln!(
+        out,
+        "{} samples, one every {every} instructions",
+        samples.len()
+    )?;
+
+    for &(line, count) in by_line.iter().take(REPORT_LINES) {
+        // precision loss is irrelevant for a human readable percentage
+        #[allow(clippy::cast_precision_loss)]
+        let percent = count as f64 / samples.len() as f64 * 100.0;
+
+        let excerpt = String::from_utf8_lossy(text[line - 1]);
+        let excerpt = excerpt.trim();
+        let excerpt: String = excerpt.chars().take(60).collect();
+
+        writeln!(out, "  line {line:>6} {percent:>6.2}%  {excerpt}")?;
+    }
+
+    if by_line.len() > REPORT_LINES {
+        writeln!(out, "  {} more lines", by_line.len() - REPORT_LINES)?;
+    }
+
+    Ok(result?)
+}
@@ -0,0 +1,427 @@
+//! Tapes persisted to disk between runs
+//!
+//! A tape file starts with a 16 byte header, all of it little endian:
+//!
+//! | bytes  | field                                                     |
+//! |--------|-----------------------------------------------------------|
+//! | 0..4   | magic, `BFTP`                                             |
+//! | 4      | format version, currently 1                               |
+//! | 5      | cell width in bits                                        |
+//! | 6      | byte order of the cells, 0 for little and 1 for big endian |
+//! | 7      | flags, bit 0 marks the cells as run length encoded        |
+//! | 8..16  | number of cells                                           |
+//!
+//! Each cell is stored in its width rounded up to whole bytes. Run length encoded cells are a
+//! sequence of runs, each a little endian `u32` count followed by the cell repeated that many
+//! times. Files are written little endian, and encoded whenever that makes them smaller.
+//!
+//! A resume point, written by `interpret --on-limit snapshot`, starts with its own 32 byte header
+//! and is followed by a whole tape file:
+//!
+//! | bytes  | field                                                     |
+//! |--------|-----------------------------------------------------------|
+//! | 0..4   | magic, `BFRP`                                             |
+//! | 4      | format version, currently 1                               |
+//! | 5..8   | reserved, zero                                            |
+//! | 8..16  | FNV-1a hash of the program source                         |
+//! | 16..24 | index of the next instruction in the optimized stream     |
+//! | 24..32 | cell the pointer is on                                    |
+
+use std::{fs, io};
+
+use bfirs::cells::{self, Narrowing};
+use bfirs::compiler::BfOptimizable;
+use clap::{Args, Subcommand};
+
+use crate::{ice::fnv1a, Mode, PathIoError};
+
+const MAGIC: &[u8; 4] = b"BFTP";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 16;
+
+const LITTLE_ENDIAN: u8 = 0;
+const BIG_ENDIAN: u8 = 1;
+
+const FLAG_RLE: u8 = 1;
+
+const RESUME_MAGIC: &[u8; 4] = b"BFRP";
+const RESUME_VERSION: u8 = 1;
+const RESUME_HEADER_LEN: usize = 32;
+
+/// Loads the tape saved at `path`, padded with zero cells to at least `min_len`. A missing file is
+/// an empty tape.
+pub fn load<CellSize: BfOptimizable>(
+    path: &str,
+    min_len: usize,
+) -> Result<Box<[CellSize]>, Box<dyn std::error::Error>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(PathIoError(path.to_owned(), e).into()),
+    };
+
+    let mut tape = if bytes.is_empty() {
+        vec![]
+    } else {
+        decode::<CellSize>(&bytes).map_err(|e| format!("{path}: {e}"))?
+    };
+
+    if tape.len() < min_len {
+        tape.resize(min_len, CellSize::ZERO);
+    }
+
+    Ok(tape.into_boxed_slice())
+}
+
+/// Checks the header of a tape file and decodes its cells
+fn decode<CellSize: BfOptimizable>(bytes: &[u8]) -> Result<Vec<CellSize>, String> {
+    let Some((header, body)) = bytes.split_first_chunk::<HEADER_LEN>() else {
+        return Err("too short to be a tape file".to_owned());
+    };
+
+    let [m0, m1, m2, m3, version, bits, order, flags, len @ ..] = *header;
+
+    if [m0, m1, m2, m3] != *MAGIC {
+        return Err("not a tape file, it does not start with BFTP".to_owned());
+    }
+
+    if version != VERSION {
+        return Err(format!(
+            "tape file version {version} is not supported, only version {VERSION} is"
+        ));
+    }
+
+    if u32::from(bits) != CellSize::BITS {
+        return Err(format!(
+            "tape holds {bits} bit cells but this run uses {} bit cells, change it with \
+             `bf snapshot convert`",
+            CellSize::BITS
+        ));
+    }
+
+    let big_endian = match order {
+        LITTLE_ENDIAN => false,
+        BIG_ENDIAN => true,
+        _ => return Err(format!("unknown cell byte order {order}")),
+    };
+
+    if flags & !FLAG_RLE != 0 {
+        return Err(format!("unknown flags {flags:#04x}"));
+    }
+
+    let len = usize::try_from(u64::from_le_bytes(len))
+        .map_err(|_| "tape is too long for this machine".to_owned())?;
+    let width = CellSize::BITS.div_ceil(8) as usize;
+
+    let cell = |idx: usize, bytes: &[u8]| {
+        let mut le = [0; 8];
+        le[..width].copy_from_slice(bytes);
+
+        if big_endian {
+            le[..width].reverse();
+        }
+
+        // only cells narrower than their bytes can hold too much
+        CellSize::try_from(u64::from_le_bytes(le))
+            .map_err(|_| format!("cell {idx} does not fit in {} bits", CellSize::BITS))
+    };
+
+    let mut tape = vec![];
+
+    if flags & FLAG_RLE == 0 {
+        if Some(body.len()) != len.checked_mul(width) {
+            return Err(format!(
+                "header declares {len} cells but the file holds {} bytes of them",
+                body.len()
+            ));
+        }
+
+        for (idx, bytes) in body.chunks(width).enumerate() {
+            tape.push(cell(idx, bytes)?);
+        }
+    } else {
+        // every run is a 4 byte count and one cell, and holds at most u32::MAX cells, so a header
+        // claiming more than that is corrupt and must not decide how much memory to allocate
+        let runs = body.len() / (4 + width);
+
+        if len > runs.saturating_mul(u32::MAX as usize) {
+            return Err(format!(
+                "header declares {len} cells but {runs} runs hold at most {}",
+                runs.saturating_mul(u32::MAX as usize)
+            ));
+        }
+
+        tape.try_reserve_exact(len)
+            .map_err(|e| format!("can not allocate a tape of {len} cells: {e}"))?;
+
+        let mut rest = body;
+
+        while let Some((count, after)) = rest.split_first_chunk::<4>() {
+            let count = u32::from_le_bytes(*count) as usize;
+
+            if count == 0 || after.len() < width || tape.len() + count > len {
+                return Err(format!(
+                    "run at byte {} is malformed",
+                    bytes.len() - rest.len()
+                ));
+            }
+
+            let value = cell(tape.len(), &after[..width])?;
+            tape.resize(tape.len() + count, value);
+
+            rest = &after[width..];
+        }
+
+        if !rest.is_empty() || tape.len() != len {
+            return Err(format!(
+                "header declares {len} cells but the file holds {}",
+                tape.len()
+            ));
+        }
+    }
+
+    Ok(tape)
+}
+
+/// Saves `tape` to `path`, writing a sibling file first and renaming it over `path` so that a
+/// reader never sees a partially written tape
+pub fn save<CellSize: BfOptimizable>(path: &str, tape: &[CellSize]) -> Result<(), PathIoError> {
+    write_atomic(path, &encode(tape))
+}
+
+fn write_atomic(path: &str, bytes: &[u8]) -> Result<(), PathIoError> {
+    let partial = format!("{path}.partial");
+
+    fs::write(&partial, bytes).map_err(|e| PathIoError(partial.clone(), e))?;
+    fs::rename(&partial, path).map_err(|e| PathIoError(path.to_owned(), e))
+}
+
+fn encode<CellSize: BfOptimizable>(tape: &[CellSize]) -> Vec<u8> {
+    let width = CellSize::BITS.div_ceil(8) as usize;
+    let le = |cell: CellSize| cell.into().to_le_bytes().into_iter().take(width);
+
+    let raw = tape.iter().flat_map(|&cell| le(cell)).collect::<Vec<_>>();
+
+    let mut rle = vec![];
+    let mut rest = tape;
+
+    while let Some(&first) = rest.first() {
+        let count = rest
+            .iter()
+            .take(u32::MAX as usize)
+            .take_while(|&&cell| cell == first)
+            .count();
+
+        let run = u32::try_from(count)
+            .unwrap_or_else(|_| unreachable!("runs are capped at u32::MAX cells"));
+
+        rle.extend_from_slice(&run.to_le_bytes());
+        rle.extend(le(first));
+
+        rest = &rest[count..];
+    }
+
+    let (flags, body) = if rle.len() < raw.len() {
+        (FLAG_RLE, rle)
+    } else {
+        (0, raw)
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    let mut bytes = [
+        MAGIC[0],
+        MAGIC[1],
+        MAGIC[2],
+        MAGIC[3],
+        VERSION,
+        CellSize::BITS as u8,
+    ]
+    .to_vec();
+    bytes.extend_from_slice(&[LITTLE_ENDIAN, flags]);
+    bytes.extend_from_slice(&(tape.len() as u64).to_le_bytes());
+    bytes.extend(body);
+
+    bytes
+}
+
+/// Where a limited run stopped, enough to carry on from there in a later run
+pub struct ResumePoint<CellSize> {
+    pub tape: Box<[CellSize]>,
+    pub ptr: usize,
+    /// index of the next instruction to run in the optimized stream
+    pub idx: usize,
+}
+
+/// Saves where a run of `source` stopped to `path`, replacing it atomically like [`save`]
+pub fn save_resume<CellSize: BfOptimizable>(
+    path: &str,
+    source: &[u8],
+    point: &ResumePoint<CellSize>,
+) -> Result<(), PathIoError> {
+    let mut bytes = RESUME_MAGIC.to_vec();
+    bytes.extend_from_slice(&[RESUME_VERSION, 0, 0, 0]);
+    bytes.extend_from_slice(&fnv1a(source).to_le_bytes());
+    bytes.extend_from_slice(&(point.idx as u64).to_le_bytes());
+    bytes.extend_from_slice(&(point.ptr as u64).to_le_bytes());
+    bytes.extend(encode(&point.tape));
+
+    write_atomic(path, &bytes)
+}
+
+/// Loads the resume point saved at `path` for `source`, `None` if there is no file. The tape is
+/// padded with zero cells to at least `min_len`, and the instruction index checked against a
+/// stream of `stream_len` instructions.
+pub fn load_resume<CellSize: BfOptimizable>(
+    path: &str,
+    source: &[u8],
+    min_len: usize,
+    stream_len: usize,
+) -> Result<Option<ResumePoint<CellSize>>, Box<dyn std::error::Error>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(PathIoError(path.to_owned(), e).into()),
+    };
+
+    let point =
+        decode_resume(&bytes, source, min_len, stream_len).map_err(|e| format!("{path}: {e}"))?;
+
+    Ok(Some(point))
+}
+
+fn decode_resume<CellSize: BfOptimizable>(
+    bytes: &[u8],
+    source: &[u8],
+    min_len: usize,
+    stream_len: usize,
+) -> Result<ResumePoint<CellSize>, String> {
+    let Some((header, tape)) = bytes.split_first_chunk::<RESUME_HEADER_LEN>() else {
+        return Err("too short to be a resume point".to_owned());
+    };
+
+    let field = |at: usize| {
+        let mut le = [0; 8];
+        le.copy_from_slice(&header[at..at + 8]);
+        u64::from_le_bytes(le)
+    };
+
+    if header[..4] != *RESUME_MAGIC {
+        return Err("not a resume point, it does not start with BFRP".to_owned());
+    }
+
+    if header[4] != RESUME_VERSION {
+        return Err(format!(
+            "resume point version {} is not supported, only version {RESUME_VERSION} is",
+            header[4]
+        ));
+    }
+
+    if field(8) != fnv1a(source) {
+        return Err("saved for a different program".to_owned());
+    }
+
+    let mut tape = decode::<CellSize>(tape)?;
+
+    if tape.len() < min_len {
+        tape.resize(min_len, CellSize::ZERO);
+    }
+
+    let idx = usize::try_from(field(16))
+        .ok()
+        .filter(|&idx| idx < stream_len)
+        .ok_or(
+            "instruction index is past the end of the program, was it saved with other flags?",
+        )?;
+
+    let ptr = usize::try_from(field(24))
+        .ok()
+        .filter(|&ptr| ptr < tape.len())
+        .ok_or("pointer is past the end of the tape")?;
+
+    Ok(ResumePoint {
+        tape: tape.into_boxed_slice(),
+        ptr,
+        idx,
+    })
+}
+
+#[derive(Args)]
+/// work with tape files saved by `interpret --tape-file`
+pub struct SnapshotArgs {
+    #[command(subcommand)]
+    action: SnapshotAction,
+}
+
+#[derive(Subcommand)]
+enum SnapshotAction {
+    Convert(ConvertArgs),
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Default)]
+enum NarrowingArg {
+    /// fail on the first cell that does not fit
+    #[default]
+    Reject,
+    /// keep the low bits, the value a narrower wrapping cell would hold
+    Wrap,
+    /// clamp to the nearest value the narrower width can hold
+    Saturate,
+}
+
+impl From<NarrowingArg> for Narrowing {
+    fn from(value: NarrowingArg) -> Self {
+        match value {
+            NarrowingArg::Reject => Narrowing::Reject,
+            NarrowingArg::Wrap => Narrowing::Wrap,
+            NarrowingArg::Saturate => Narrowing::Saturate,
+        }
+    }
+}
+
+#[derive(Args)]
+/// convert a tape file between cell widths, keeping the value of every cell, so widening sign
+/// extends signed cells and zero extends unsigned ones
+struct ConvertArgs {
+    /// tape file to read
+    #[arg(short, long)]
+    input: String,
+
+    /// cell width of the input tape
+    #[arg(long)]
+    from: Mode,
+
+    /// file to write the converted tape to, may be the input file
+    #[arg(short, long)]
+    output: String,
+
+    /// cell width to convert to
+    #[arg(long)]
+    to: Mode,
+
+    /// what to do with cells whose value the new width can not hold
+    #[arg(long, value_enum, default_value_t = NarrowingArg::Reject)]
+    narrowing: NarrowingArg,
+}
+
+pub fn snapshot(args: &SnapshotArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let SnapshotAction::Convert(args) = &args.action;
+
+    with_cell!(args.from, |Src| convert_from::<Src>(args))
+}
+
+fn convert_from<Src: BfOptimizable>(args: &ConvertArgs) -> Result<(), Box<dyn std::error::Error>> {
+    with_cell!(args.to, |Dst| convert::<Src, Dst>(args))
+}
+
+fn convert<Src: BfOptimizable, Dst: BfOptimizable>(
+    args: &ConvertArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    // loading treats a missing tape as empty, which is never what a conversion wants
+    fs::metadata(&args.input).map_err(|e| PathIoError(args.input.clone(), e))?;
+
+    let tape = load::<Src>(&args.input, 0)?;
+    let converted = cells::convert::<Src, Dst>(&tape, args.narrowing.into())
+        .map_err(|e| format!("{}: {e}", args.input))?;
+
+    Ok(save(&args.output, &converted)?)
+}
@@ -0,0 +1,66 @@
+//! Private scratch directories for files handed to other programs
+
+use std::{
+    collections::hash_map::RandomState,
+    env, fs,
+    hash::{BuildHasher, Hasher},
+    io,
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+/// A temporary directory only this user can enter, removed with everything in it on drop
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a new directory in the system temporary directory, named `prefix` and a random
+    /// suffix. An existing path is never reused, as another user could have created it to read or
+    /// replace what is written there.
+    pub fn new(prefix: &str) -> io::Result<Self> {
+        #[cfg_attr(not(unix), allow(unused_mut))]
+        let mut builder = fs::DirBuilder::new();
+
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+        for _ in 0..16 {
+            let path = env::temp_dir().join(format!("{prefix}-{:016x}", random()));
+
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "every temporary directory name tried was already taken",
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// a number that can not be guessed ahead of time, std has no random number generator but seeds
+/// the keys of every [`RandomState`] from the operating system
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+
+    hasher.write_u32(process::id());
+
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+
+    hasher.finish()
+}
@@ -0,0 +1,103 @@
+//! Terminal input modes for interactive programs, set on stdin for the length of a run
+
+use std::{io, sync::OnceLock};
+
+/// the terminal settings before the run, restored on drop and by the signal handler
+static ORIGINAL: OnceLock<libc::termios> = OnceLock::new();
+
+/// How the terminal on stdin hands input to the program
+#[derive(Clone, Copy)]
+pub struct InputMode {
+    /// deliver each byte as it is typed rather than a line at a time after enter
+    pub char_input: bool,
+    pub echo: bool,
+}
+
+/// signals that would otherwise end the process with the terminal left in the run's mode
+const SIGNALS: [libc::c_int; 4] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT];
+
+/// Restores the original terminal settings and signal handlers when dropped
+pub struct TerminalGuard {
+    /// the handler of each of [`SIGNALS`] before the guard replaced it
+    previous: [libc::sighandler_t; SIGNALS.len()],
+}
+
+extern "C" fn restore_and_reraise(signal: libc::c_int) {
+    // SAFETY: tcsetattr, signal and raise are async signal safe, and the termios was fully
+    // initialized before this handler was installed
+    unsafe {
+        if let Some(original) = ORIGINAL.get() {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+        }
+
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+impl TerminalGuard {
+    /// Applies `mode` to the terminal on stdin, or does nothing if stdin is not a terminal.
+    /// The original settings are also restored if the process is interrupted or terminated.
+    pub fn apply(mode: InputMode) -> io::Result<Option<Self>> {
+        // SAFETY: termios is plain old data, and is only read after tcgetattr fills it in
+        let mut settings = unsafe { std::mem::zeroed::<libc::termios>() };
+
+        // SAFETY: settings is a valid termios to write into
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &raw mut settings) } != 0 {
+            let err = io::Error::last_os_error();
+
+            return match err.raw_os_error() {
+                Some(libc::ENOTTY) => Ok(None),
+                _ => Err(err),
+            };
+        }
+
+        let original = *ORIGINAL.get_or_init(|| settings);
+
+        let handler = restore_and_reraise as extern "C" fn(libc::c_int);
+
+        // SAFETY: the handler only makes async signal safe calls
+        let previous =
+            SIGNALS.map(|signal| unsafe { libc::signal(signal, handler as libc::sighandler_t) });
+
+        settings.c_lflag = original.c_lflag;
+
+        if mode.char_input {
+            settings.c_lflag &= !libc::ICANON;
+            settings.c_cc[libc::VMIN] = 1;
+            settings.c_cc[libc::VTIME] = 0;
+        } else {
+            settings.c_lflag |= libc::ICANON;
+        }
+
+        if mode.echo {
+            settings.c_lflag |= libc::ECHO;
+        } else {
+            settings.c_lflag &= !libc::ECHO;
+        }
+
+        // dropped on error too, putting the handlers back
+        let guard = Self { previous };
+
+        // SAFETY: settings was filled in by tcgetattr and only its flags were changed
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw const settings) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Some(guard))
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if let Some(original) = ORIGINAL.get() {
+            // SAFETY: original was filled in by tcgetattr
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original) };
+        }
+
+        for (signal, previous) in SIGNALS.into_iter().zip(self.previous) {
+            // SAFETY: previous was returned by signal for this same signal
+            unsafe { libc::signal(signal, previous) };
+        }
+    }
+}
@@ -0,0 +1,388 @@
+//! Random program generators for property testing optimizer passes and alternative backends
+
+use core::fmt;
+use std::num::NonZeroU32;
+
+use arbitrary::{Arbitrary, Unstructured};
+use proptest::prelude::*;
+use usize_cast::IntoUsize;
+
+use crate::compiler::{BfCompError, BfInstruc, BfInstructionStream, BfOptimizable};
+use crate::sandbox::{Limits, RunError, RunOutcome, Termination};
+
+const SIMPLE_OPS: &[u8] = b"+-<>.,";
+
+/// Brainfuck source text that is guaranteed to have balanced loops, and so always compiles
+#[derive(Clone, PartialEq, Eq)]
+pub struct BfProgram(Vec<u8>);
+
+impl BfProgram {
+    /// Returns the source text of this program
+    #[must_use]
+    pub fn source(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Compiles this program to an optimized instruction stream
+    ///
+    /// # Errors
+    /// This function only errors if the program overflows the maximum code size allowed by the interpreter
+    pub fn stream<T: BfOptimizable>(
+        &self,
+        array_len: Option<u32>,
+    ) -> Result<BfInstructionStream<T>, BfCompError> {
+        BfInstructionStream::optimized_from_text(self.0.iter().copied(), array_len)
+    }
+}
+
+impl fmt::Debug for BfProgram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BfProgram({:?})", String::from_utf8_lossy(&self.0))
+    }
+}
+
+impl<'a> Arbitrary<'a> for BfProgram {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut source = vec![];
+        let mut depth = 0usize;
+
+        while !u.is_empty() {
+            match u.int_in_range(0u8..=7)? {
+                6 => {
+                    source.push(b'[');
+                    depth += 1;
+                }
+                7 if depth > 0 => {
+                    source.push(b']');
+                    depth -= 1;
+                }
+                op => source.push(SIMPLE_OPS[usize::from(op % 6)]),
+            }
+        }
+
+        source.extend(std::iter::repeat_n(b']', depth));
+
+        Ok(Self(source))
+    }
+}
+
+/// A proptest strategy generating balanced programs of up to `depth` nested loops
+pub fn arb_program(depth: u32) -> impl Strategy<Value = BfProgram> {
+    let ops = prop::collection::vec(prop::sample::select(SIMPLE_OPS), 0..8);
+
+    ops.prop_recursive(depth, 256, 4, |inner| {
+        prop::collection::vec((inner, any::<bool>()), 1..4).prop_map(|parts| {
+            let mut source = vec![];
+
+            for (part, looped) in parts {
+                if looped {
+                    source.push(b'[');
+                    source.extend(part);
+                    source.push(b']');
+                } else {
+                    source.extend(part);
+                }
+            }
+
+            source
+        })
+    })
+    .prop_map(BfProgram)
+}
+
+impl proptest::arbitrary::Arbitrary for BfProgram {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        arb_program(4).boxed()
+    }
+}
+
+/// An instruction stream with balanced loops, built from optimized instructions directly rather
+/// than from source text, so passes and engines see mixes of instructions the optimizer would
+/// not produce itself.
+///
+/// Constant writes are never generated, as they only match the source they came from when the
+/// optimizer proved what the cell held. Neither are the instructions of extensions.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct BfInstructions<T>(Vec<BfInstruc<T>>);
+
+impl<T: BfOptimizable> BfInstructions<T> {
+    /// Returns the instructions, with every loop jump point left at zero
+    #[must_use]
+    pub fn instructions(&self) -> &[BfInstruc<T>] {
+        &self.0
+    }
+
+    /// Builds a run ready stream from these instructions, see
+    /// [`BfInstructionStream::try_from_instructions`]
+    ///
+    /// # Errors
+    /// This function only errors if the stream overflows the maximum code size allowed by the interpreter
+    pub fn stream(&self, array_len: Option<u32>) -> Result<BfInstructionStream<T>, BfCompError> {
+        BfInstructionStream::try_from_instructions(self.0.clone(), array_len)
+    }
+}
+
+/// pointer moves and bulk reads are kept short, so generated programs mostly stay on small tapes
+const MAX_RUN: u32 = 4;
+
+impl<'a, T: BfOptimizable> Arbitrary<'a> for BfInstructions<T> {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        use BfInstruc::*;
+
+        let mut instructions = vec![];
+        let mut depth = 0usize;
+
+        let run = |u: &mut Unstructured<'a>| {
+            u.int_in_range(1..=MAX_RUN)
+                .map(|n| NonZeroU32::new(n).expect("runs start at one"))
+        };
+
+        while !u.is_empty() {
+            let instruc = match u.int_in_range(0u8..=14)? {
+                0 => Zero,
+                1 => Inc,
+                2 => Dec,
+                3 => IncPtr,
+                4 => DecPtr,
+                5 => Write,
+                6 => Read,
+                7 => IncBy(T::from(u.arbitrary()?)),
+                8 => DecBy(T::from(u.arbitrary()?)),
+                9 => Set(T::from(u.arbitrary()?)),
+                10 => IncPtrBy(run(u)?),
+                11 => DecPtrBy(run(u)?),
+                12 => ReadN(run(u)?),
+                13 => {
+                    depth += 1;
+                    LStart(0)
+                }
+                _ if depth > 0 => {
+                    depth -= 1;
+                    LEnd(0)
+                }
+                _ => continue,
+            };
+
+            instructions.push(instruc);
+        }
+
+        instructions.extend(std::iter::repeat_n(LEnd(0), depth));
+
+        Ok(Self(instructions))
+    }
+}
+
+/// A proptest strategy generating a single instruction that is not a loop
+fn arb_instruction<T: BfOptimizable + fmt::Debug + 'static>() -> impl Strategy<Value = BfInstruc<T>>
+{
+    use BfInstruc::*;
+
+    let run = || (1..=MAX_RUN).prop_map(|n| NonZeroU32::new(n).expect("runs start at one"));
+
+    prop_oneof![
+        prop::sample::select(vec![Zero, Inc, Dec, IncPtr, DecPtr, Write, Read]),
+        any::<u8>().prop_map(|n| IncBy(T::from(n))),
+        any::<u8>().prop_map(|n| DecBy(T::from(n))),
+        any::<u8>().prop_map(|n| Set(T::from(n))),
+        run().prop_map(IncPtrBy),
+        run().prop_map(DecPtrBy),
+        run().prop_map(ReadN),
+    ]
+}
+
+/// A proptest strategy generating instruction streams of up to `depth` nested loops
+pub fn arb_instructions<T: BfOptimizable + fmt::Debug + 'static>(
+    depth: u32,
+) -> impl Strategy<Value = BfInstructions<T>> {
+    let ops = prop::collection::vec(arb_instruction(), 0..8);
+
+    ops.prop_recursive(depth, 256, 4, |inner| {
+        prop::collection::vec((inner, any::<bool>()), 1..4).prop_map(|parts| {
+            let mut instructions = vec![];
+
+            for (part, looped) in parts {
+                if looped {
+                    instructions.push(BfInstruc::LStart(0));
+                    instructions.extend(part);
+                    instructions.push(BfInstruc::LEnd(0));
+                } else {
+                    instructions.extend(part);
+                }
+            }
+
+            instructions
+        })
+    })
+    .prop_map(BfInstructions)
+}
+
+impl<T: BfOptimizable + fmt::Debug + 'static> proptest::arbitrary::Arbitrary for BfInstructions<T> {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with((): Self::Parameters) -> Self::Strategy {
+        arb_instructions(4).boxed()
+    }
+}
+
+/// Runs a program directly from its source text with no optimizations applied, as a reference to
+/// compare optimized engines against.
+///
+/// Instruction counts are not comparable to optimized runs, and error indexes are byte offsets in
+/// the source rather than instruction indexes. The tape size defaults to the same size the optimizing
+/// compiler would pick.
+#[must_use]
+pub fn reference_run<T: BfOptimizable>(
+    program: &[u8],
+    input: &[u8],
+    limits: Limits,
+) -> RunOutcome<T> {
+    let array_len = limits.array_len.map_or_else(
+        || {
+            program
+                .iter()
+                .fold(0, |accu, &b| accu + usize::from(b == b'>'))
+                .max(30_000)
+        },
+        IntoUsize::into_usize,
+    );
+
+    let mut outcome = RunOutcome {
+        output: vec![],
+        pointer: 0,
+        tape: vec![T::ZERO; array_len].into_boxed_slice(),
+        instructions_executed: 0,
+        termination: Termination::Completed,
+    };
+
+    let mut jumps = vec![0; program.len()];
+    let mut stack = vec![];
+
+    for (idx, &b) in program.iter().enumerate() {
+        match b {
+            b'[' => stack.push(idx),
+            b']' => {
+                let Some(start) = stack.pop() else {
+                    outcome.termination =
+                        Termination::Error(RunError::Compile(BfCompError::LoopEndBeforeLoopStart));
+                    return outcome;
+                };
+                jumps[start] = idx;
+                jumps[idx] = start;
+            }
+            _ => {}
+        }
+    }
+
+    if !stack.is_empty() {
+        outcome.termination = Termination::Error(RunError::Compile(BfCompError::LoopCountMismatch));
+        return outcome;
+    }
+
+    let mut input = input.iter().copied();
+    let tape = &mut outcome.tape;
+    let ptr = &mut outcome.pointer;
+
+    let mut idx = 0;
+    while idx < program.len() {
+        if !SIMPLE_OPS.contains(&program[idx]) && !matches!(program[idx], b'[' | b']') {
+            idx += 1;
+            continue;
+        }
+
+        if outcome.instructions_executed == limits.instructions {
+            outcome.termination = Termination::BudgetExhausted { resume_at: idx };
+            return outcome;
+        }
+
+        match program[idx] {
+            b'+' => tape[*ptr] = tape[*ptr].wrapping_add(T::from(1)),
+            b'-' => tape[*ptr] = tape[*ptr].wrapping_sub(T::from(1)),
+            b'>' if *ptr + 1 >= tape.len() => {
+                outcome.termination = Termination::Error(RunError::Overflow(idx));
+                return outcome;
+            }
+            b'>' => *ptr += 1,
+            b'<' if *ptr == 0 => {
+                outcome.termination = Termination::Error(RunError::Underflow(idx));
+                return outcome;
+            }
+            b'<' => *ptr -= 1,
+            b'.' => outcome.output.push(tape[*ptr].truncate_u8()),
+            b',' => tape[*ptr] = T::from(input.next().unwrap_or(0)),
+            b'[' if tape[*ptr] == T::ZERO => idx = jumps[idx],
+            b']' if tape[*ptr] != T::ZERO => idx = jumps[idx],
+            _ => {}
+        }
+
+        outcome.instructions_executed += 1;
+        idx += 1;
+    }
+
+    outcome
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn optimizer_preserves_semantics(program in arb_program(4), input in prop::collection::vec(any::<u8>(), 0..8)) {
+        let limits = Limits { instructions: 10_000, array_len: Some(64) };
+
+        let optimized = crate::run_with_limits::<u8>(program.source(), &input, limits);
+        let reference = reference_run::<u8>(program.source(), &input, Limits { instructions: 100_000, ..limits });
+
+        match (optimized.termination, reference.termination) {
+            (Termination::Completed, Termination::Completed) => {
+                prop_assert_eq!(optimized.output, reference.output);
+                prop_assert_eq!(optimized.pointer, reference.pointer);
+                prop_assert_eq!(optimized.tape, reference.tape);
+            }
+            (Termination::Error(RunError::Overflow(_)), Termination::Error(RunError::Overflow(_)))
+            | (Termination::Error(RunError::Underflow(_)), Termination::Error(RunError::Underflow(_))) => {
+                prop_assert_eq!(optimized.output, reference.output);
+            }
+            // one side ran out of budget, as instruction counts differ there is nothing to compare
+            (Termination::BudgetExhausted { .. }, _) | (_, Termination::BudgetExhausted { .. }) => {}
+            (a, b) => prop_assert!(false, "terminations differ: optimized {:?}, reference {:?}", a, b),
+        }
+    }
+}
+
+#[cfg(test)]
+proptest! {
+    #[test]
+    fn instruction_streams_match_their_source(instructions in arb_instructions::<u8>(4), input in prop::collection::vec(any::<u8>(), 0..8)) {
+        use crate::interpreter::{BfExecError, BfExecErrorTy, BrainFuckExecutorBuilder};
+
+        let stream = instructions.stream(Some(64)).unwrap();
+
+        let mut source = vec![];
+        stream.render_bf(&mut source).unwrap();
+        let reference = reference_run::<u8>(&source, &input, Limits { instructions: 100_000, array_len: Some(64) });
+
+        let mut exec = BrainFuckExecutorBuilder::<u8, _, _>::new()
+            .stream_in(&input[..])
+            .stream_out(vec![])
+            .array_len(64)
+            .limit(10_000)
+            .build()
+            .unwrap();
+
+        match (exec.run_limited(&stream), reference.termination) {
+            (Ok(()), Termination::Completed) => {
+                prop_assert_eq!(&exec.stdout, &reference.output);
+                prop_assert_eq!(exec.state(), (reference.pointer, &reference.tape[..]));
+            }
+            (Err(BfExecError { source: BfExecErrorTy::Overflow, .. }), Termination::Error(RunError::Overflow(_)))
+            | (Err(BfExecError { source: BfExecErrorTy::Underflow, .. }), Termination::Error(RunError::Underflow(_))) => {
+                prop_assert_eq!(&exec.stdout, &reference.output);
+            }
+            // one side ran out of budget, as instruction counts differ there is nothing to compare
+            (Err(BfExecError { source: BfExecErrorTy::NotEnoughInstructions, .. }), _) | (_, Termination::BudgetExhausted { .. }) => {}
+            (a, b) => prop_assert!(false, "terminations differ: stream {:?}, source {:?}", a, b),
+        }
+    }
+}
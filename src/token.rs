@@ -0,0 +1,192 @@
+//! A shared tokenizer for brainfuck source that keeps the byte offset of every token, so the
+//! compiler, diagnostics and `explain` all agree on positions
+
+use std::ops::Range;
+
+/// What a [`Token`] holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    /// one of the eight brainfuck instruction characters, or an enabled [`Extensions`] character
+    Op(u8),
+    /// a run of bytes that are not instructions
+    Comment,
+}
+
+/// A token and the byte range of source it was read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Range<usize>,
+}
+
+/// Returns true if `byte` is a brainfuck instruction character
+#[must_use]
+pub const fn is_op(byte: u8) -> bool {
+    matches!(byte, b'+' | b'-' | b'>' | b'<' | b'.' | b',' | b'[' | b']')
+}
+
+/// Instruction characters beyond the standard eight, which are comments unless enabled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Extensions {
+    /// `^` stores how many instructions have run so far in the current cell, wrapping
+    pub clock: bool,
+    /// `@` ends the program successfully
+    pub halt: bool,
+    /// `#` dumps the pointer and the cells around it to stderr
+    pub debug: bool,
+}
+
+impl Extensions {
+    /// Returns true if `byte` is an instruction character with these extensions enabled
+    #[must_use]
+    pub const fn is_op(self, byte: u8) -> bool {
+        is_op(byte)
+            || (self.clock && byte == b'^')
+            || (self.halt && byte == b'@')
+            || (self.debug && byte == b'#')
+    }
+}
+
+/// Splits source bytes into tokens, yielding only instructions unless comments are requested with
+/// [`Tokenizer::with_comments`]
+pub struct Tokenizer<I> {
+    bytes: I,
+    offset: usize,
+    comments: bool,
+    extensions: Extensions,
+    /// an instruction read while ending a comment, returned on the next call
+    pending: Option<Token>,
+}
+
+impl<I: Iterator<Item = u8>> Tokenizer<I> {
+    pub fn new(bytes: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            bytes: bytes.into_iter(),
+            offset: 0,
+            comments: false,
+            extensions: Extensions::default(),
+            pending: None,
+        }
+    }
+
+    /// Also yields runs of non instruction bytes as [`TokenKind::Comment`]
+    #[must_use]
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+
+        self
+    }
+
+    /// Also yields the characters of enabled extensions as instructions
+    #[must_use]
+    pub fn with_extensions(mut self, extensions: Extensions) -> Self {
+        self.extensions = extensions;
+
+        self
+    }
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Tokenizer<I> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if let Some(token) = self.pending.take() {
+            return Some(token);
+        }
+
+        let mut comment_start = None;
+
+        loop {
+            let Some(byte) = self.bytes.next() else {
+                return comment_start.map(|start| Token {
+                    kind: TokenKind::Comment,
+                    span: start..self.offset,
+                });
+            };
+
+            let at = self.offset;
+            self.offset += 1;
+
+            if self.extensions.is_op(byte) {
+                let op = Token {
+                    kind: TokenKind::Op(byte),
+                    span: at..at + 1,
+                };
+
+                let Some(start) = comment_start else {
+                    return Some(op);
+                };
+
+                self.pending = Some(op);
+
+                return Some(Token {
+                    kind: TokenKind::Comment,
+                    span: start..at,
+                });
+            }
+
+            if self.comments && comment_start.is_none() {
+                comment_start = Some(at);
+            }
+        }
+    }
+}
+
+/// Maps byte offsets in a source to one based line and column numbers
+pub struct LineIndex(Vec<usize>);
+
+impl LineIndex {
+    #[must_use]
+    pub fn new(source: &[u8]) -> Self {
+        Self(
+            std::iter::once(0)
+                .chain(
+                    source
+                        .iter()
+                        .enumerate()
+                        .filter(|&(_, &b)| b == b'\n')
+                        .map(|(idx, _)| idx + 1),
+                )
+                .collect(),
+        )
+    }
+
+    /// returns the line and column of the byte at `offset`
+    #[must_use]
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let line = self.0.partition_point(|&start| start <= offset);
+
+        (line, offset - self.0[line - 1] + 1)
+    }
+}
+
+#[test]
+fn test_tokenizer() {
+    let kinds =
+        |tokens: Tokenizer<_>| -> Vec<_> { tokens.map(|token| (token.kind, token.span)).collect() };
+
+    let source = b"+ab[-]\n.";
+
+    assert_eq!(
+        kinds(Tokenizer::new(source.iter().copied())),
+        [
+            (TokenKind::Op(b'+'), 0..1),
+            (TokenKind::Op(b'['), 3..4),
+            (TokenKind::Op(b'-'), 4..5),
+            (TokenKind::Op(b']'), 5..6),
+            (TokenKind::Op(b'.'), 7..8),
+        ]
+    );
+    assert_eq!(
+        kinds(Tokenizer::new(source.iter().copied()).with_comments())[..3],
+        [
+            (TokenKind::Op(b'+'), 0..1),
+            (TokenKind::Comment, 1..3),
+            (TokenKind::Op(b'['), 3..4),
+        ]
+    );
+
+    let lines = LineIndex::new(source);
+    assert_eq!(lines.line_col(3), (1, 4));
+    assert_eq!(lines.line_col(7), (2, 1));
+}
@@ -0,0 +1,352 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{self, Write},
+};
+
+use bfirs::compiler::{BfInstruc, BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::{BfExecError, BfExecErrorTy, BrainFuckExecutor, BrainFuckExecutorBuilder};
+use bfirs::token::LineIndex;
+use clap::Args;
+
+use crate::PathIoError;
+
+#[derive(clap::ValueEnum, Clone, Copy, Default)]
+pub enum TraceFormat {
+    /// trace event JSON for `chrome://tracing` or Perfetto
+    #[default]
+    Chrome,
+    /// folded stacks of loop nests weighted by instructions executed, for inferno or flamegraph.pl
+    Folded,
+}
+
+/// receives loop entries and exits, timestamped by instructions executed
+trait LoopTrace {
+    fn enter(&mut self, name: &str, ts: u64) -> io::Result<()>;
+    fn exit(&mut self, name: &str, ts: u64) -> io::Result<()>;
+    fn finish(self: Box<Self>, ts: u64) -> io::Result<()>;
+
+    /// records that output bytes `bytes` were written by the instruction at `ts`
+    fn output(&mut self, _bytes: std::ops::Range<u64>, _ts: u64) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// writes trace events in the chrome trace event format, timestamped by instructions executed
+struct ChromeTrace<W: Write> {
+    out: W,
+    first: bool,
+}
+
+impl<W: Write> ChromeTrace<W> {
+    fn new(mut out: W) -> io::Result<Self> {
+        write!(out, "{{\"traceEvents\":[")?;
+
+        Ok(Self { out, first: true })
+    }
+
+    fn event(&mut self, phase: char, name: &str, ts: u64) -> io::Result<()> {
+        let sep = if self.first { "" } else { "," };
+        self.first = false;
+
+        write!(
+            self.out,
+            "{sep}\n{{\"name\":\"{name}\",\"ph\":\"{phase}\",\"ts\":{ts},\"pid\":1,\"tid\":1}}"
+        )
+    }
+}
+
+impl<W: Write> LoopTrace for ChromeTrace<W> {
+    fn enter(&mut self, name: &str, ts: u64) -> io::Result<()> {
+        self.event('B', name, ts)
+    }
+
+    fn exit(&mut self, name: &str, ts: u64) -> io::Result<()> {
+        self.event('E', name, ts)
+    }
+
+    fn finish(mut self: Box<Self>, _ts: u64) -> io::Result<()> {
+        writeln!(self.out, "\n]}}")?;
+        self.out.flush()
+    }
+
+    fn output(&mut self, bytes: std::ops::Range<u64>, ts: u64) -> io::Result<()> {
+        for byte in bytes {
+            self.event('i', &format!("output {byte}"), ts)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// accumulates the instructions executed under each distinct loop nest, written out as one
+/// `program;outer;inner count` line per nest
+struct FoldedStacks<W: Write> {
+    out: W,
+    stack: Vec<String>,
+    counts: BTreeMap<String, u64>,
+    last_ts: u64,
+}
+
+impl<W: Write> FoldedStacks<W> {
+    fn new(out: W) -> Self {
+        Self {
+            out,
+            stack: vec!["program".to_owned()],
+            counts: BTreeMap::new(),
+            last_ts: 0,
+        }
+    }
+
+    /// attributes every instruction since the last change of nest to the current nest
+    fn record(&mut self, ts: u64) {
+        if ts > self.last_ts {
+            *self.counts.entry(self.stack.join(";")).or_default() += ts - self.last_ts;
+            self.last_ts = ts;
+        }
+    }
+}
+
+impl<W: Write> LoopTrace for FoldedStacks<W> {
+    fn enter(&mut self, name: &str, ts: u64) -> io::Result<()> {
+        self.record(ts);
+        self.stack.push(name.to_owned());
+        Ok(())
+    }
+
+    fn exit(&mut self, _name: &str, ts: u64) -> io::Result<()> {
+        self.record(ts);
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>, ts: u64) -> io::Result<()> {
+        self.record(ts);
+
+        for (stack, count) in &self.counts {
+            writeln!(self.out, "{stack} {count}")?;
+        }
+
+        self.out.flush()
+    }
+}
+
+/// Runs `code` on stdio one instruction at a time, writing every loop entry and exit to `path` in
+/// `format`. Timestamps count executed instructions rather than wall time, so the same program and
+/// input always produce the same trace, and chrome traces mark the instruction each output byte
+/// came from.
+pub fn trace<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    limit: Option<u64>,
+    path: &str,
+    format: TraceFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = code;
+    let code = BfInstructionStream::<CellSize>::traceable_from_text(code, arr_len)?;
+    let spans = code.spans().expect("spanned streams always have spans");
+
+    let lines = LineIndex::new(source);
+
+    let name = |idx: usize| {
+        let (line, col) = lines.line_col(spans[idx].start);
+
+        format!("loop {line}:{col}")
+    };
+
+    let out = io::BufWriter::new(File::create(path).map_err(|e| PathIoError(path.to_owned(), e))?);
+
+    let mut trace: Box<dyn LoopTrace> = match format {
+        TraceFormat::Chrome => Box::new(ChromeTrace::new(out)?),
+        TraceFormat::Folded => Box::new(FoldedStacks::new(out)),
+    };
+
+    let mut execenv =
+        BrainFuckExecutor::new_stdio_locked::<CellSize>(code.reccomended_array_size());
+
+    let mut open = vec![];
+    let mut ts = 0u64;
+    let mut written = 0u64;
+    let mut idx = (!code.is_empty()).then_some(0);
+
+    let result = loop {
+        let Some(i) = idx else {
+            break Ok(());
+        };
+
+        if limit == Some(ts) {
+            break Err(BfExecError {
+                source: BfExecErrorTy::NotEnoughInstructions,
+                idx: i,
+            });
+        }
+
+        let next = match execenv.step(&code, i) {
+            Ok(next) => next,
+            Err(e) => break Err(e),
+        };
+
+        match code[i] {
+            BfInstruc::LStart(_) if next == Some(i + 1) => {
+                trace.enter(&name(i), ts)?;
+                open.push(i);
+            }
+            BfInstruc::LEnd(start) if next != Some(start as usize + 1) => {
+                if let Some(start) = open.pop() {
+                    trace.exit(&name(start), ts + 1)?;
+                }
+            }
+            BfInstruc::Write => {
+                trace.output(written..written + 1, ts)?;
+                written += 1;
+            }
+            BfInstruc::WriteConst(bytes) => {
+                let len = bytes.as_bytes().len() as u64;
+                trace.output(written..written + len, ts)?;
+                written += len;
+            }
+            _ => {}
+        }
+
+        ts += 1;
+        idx = next;
+    };
+
+    // loops still running when execution stopped are closed where it stopped
+    while let Some(start) = open.pop() {
+        trace.exit(&name(start), ts)?;
+    }
+
+    trace.finish(ts)?;
+    execenv.stdout.flush()?;
+
+    Ok(result?)
+}
+
+#[derive(Args)]
+/// replay a program to the moment it wrote an output byte and show its state there
+pub struct TraceViewArgs {
+    /// zero based index of the output byte to stop at
+    #[arg(long, value_name = "N")]
+    at_output: usize,
+
+    /// file to replay as input, defaults to stdin, this must match the input of the traced run
+    #[arg(short, long)]
+    input: Option<String>,
+}
+
+/// cells shown either side of the pointer
+const VIEW_RADIUS: usize = 8;
+
+/// Replays `code` one instruction at a time, counting instructions the same way [`trace`] does,
+/// until it writes output byte `args.at_output`, then prints where and in what state that happened
+pub fn view<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    args: &TraceViewArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let source = code;
+    let code = BfInstructionStream::<CellSize>::traceable_from_text(code, arr_len)?;
+    let spans = code.spans().expect("spanned streams always have spans");
+
+    let input: Box<dyn io::Read> = match &args.input {
+        Some(fname) => Box::new(io::Cursor::new(
+            fs::read(fname).map_err(|e| PathIoError(fname.clone(), e))?,
+        )),
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let mut execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .array_len(code.reccomended_array_size())
+        .stream_in(input)
+        .stream_out(vec![])
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    let mut ts = 0u64;
+    let mut idx = (!code.is_empty()).then_some(0);
+
+    while let Some(i) = idx {
+        idx = execenv.step(&code, i)?;
+
+        if execenv.stdout.len() <= args.at_output {
+            ts += 1;
+            continue;
+        }
+
+        let byte = execenv.stdout[args.at_output];
+        let (line, col) = LineIndex::new(source).line_col(spans[i].start);
+        let (ptr, tape) = execenv.state();
+
+        let mut out = io::stdout().lock();
+
+        writeln!(
+            out,
+            "output byte {} ({byte:#04x} {:?}) was written at trace timestamp {ts}, source {line}:{col}",
+            args.at_output,
+            char::from(byte)
+        )?;
+        writeln!(out, "pointer at cell {ptr}")?;
+
+        let start = ptr.saturating_sub(VIEW_RADIUS);
+        let end = (ptr + VIEW_RADIUS + 1).min(tape.len());
+
+        for (cell, value) in (start..end).zip(&tape[start..end]) {
+            let marker = if cell == ptr { ">" } else { " " };
+            writeln!(out, "{marker} {cell:>8}: {value}")?;
+        }
+
+        return Ok(());
+    }
+
+    Err(format!(
+        "program stopped after {ts} instructions having written only {} output bytes",
+        execenv.stdout.len()
+    )
+    .into())
+}
+
+#[test]
+fn test_chrome_trace() {
+    let mut out = vec![];
+
+    let mut trace: Box<dyn LoopTrace> = Box::new(ChromeTrace::new(&mut out).unwrap());
+    trace.enter("loop 1:1", 0).unwrap();
+    trace.output(0..2, 1).unwrap();
+    trace.exit("loop 1:1", 3).unwrap();
+    trace.finish(3).unwrap();
+
+    let event = |name: &str, phase: char, ts: u64| {
+        format!("{{\"name\":\"{name}\",\"ph\":\"{phase}\",\"ts\":{ts},\"pid\":1,\"tid\":1}}")
+    };
+
+    let expect = format!(
+        "{{\"traceEvents\":[\n{},\n{},\n{},\n{}\n]}}\n",
+        event("loop 1:1", 'B', 0),
+        event("output 0", 'i', 1),
+        event("output 1", 'i', 1),
+        event("loop 1:1", 'E', 3),
+    );
+
+    assert_eq!(String::from_utf8(out).unwrap(), expect);
+}
+
+#[test]
+fn test_folded_stacks() {
+    let mut out = vec![];
+
+    let mut trace: Box<dyn LoopTrace> = Box::new(FoldedStacks::new(&mut out));
+    trace.enter("a", 2).unwrap();
+    trace.enter("b", 5).unwrap();
+    trace.exit("b", 9).unwrap();
+    trace.exit("a", 10).unwrap();
+    trace.enter("a", 11).unwrap();
+    trace.exit("a", 12).unwrap();
+    trace.finish(15).unwrap();
+
+    // instructions are attributed to the innermost open nest, and repeated nests are summed
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "program 6\nprogram;a 5\nprogram;a;b 4\n"
+    );
+}
@@ -0,0 +1,196 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use bfirs::compiler::{BfInstructionStream, BfOptimizable, CCodegenOpts, CStd, Eof};
+use bfirs::interpreter::BrainFuckExecutorBuilder;
+use clap::Args;
+
+use crate::{
+    reduce::wait_timeout, render, system_cc, temp_dir::TempDir, CStdArg, Consteval, PathIoError,
+};
+
+#[derive(Args)]
+/// check the C backend against the interpreter by compiling and running both on the same input
+pub struct VerifyCArgs {
+    /// C compiler to build the generated C with, defaults to $CC or cc
+    #[arg(long)]
+    cc: Option<String>,
+
+    /// file to use as input for both the interpreter and the compiled program, defaults to no input
+    #[arg(short, long)]
+    input: Option<String>,
+
+    /// consteval by prerunning in interpreter for up to N seconds when generating C, defaults to O1
+    #[arg(short = 'O', long = "opt-level")]
+    opt_level: Option<u32>,
+
+    /// run a limited amount of instructions in the interpreter
+    #[arg(short, long)]
+    limit: Option<u64>,
+
+    /// C standard to generate and compile as, defaults to c99 and the compilers own default
+    #[arg(long = "c-std")]
+    c_std: Option<CStdArg>,
+
+    /// seconds to give the compiled program, which is killed and reported as a mismatch after
+    /// that, as a miscompiled loop easily never ends
+    #[arg(long, value_name = "SECS", default_value_t = 10)]
+    timeout: u64,
+}
+
+pub fn verify_c<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    eof: Eof,
+    args: &VerifyCArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let code = BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?;
+
+    let input = match &args.input {
+        Some(fname) => fs::read(fname).map_err(|e| PathIoError(fname.clone(), e))?,
+        None => vec![],
+    };
+
+    let mut execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .array_len(code.reccomended_array_size())
+        .stream_in(&input[..])
+        .stream_out(vec![])
+        .eof(eof)
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    match args.limit {
+        Some(lim) => {
+            execenv.add_instruction_limit(lim)?;
+            execenv.run_limited(&code)
+        }
+        None => execenv.run(&code),
+    }
+    .map_err(|e| format!("interpreter: {e}, cannot verify a program that does not complete"))?;
+
+    let expected = execenv.stdout;
+
+    let dir = TempDir::new("bf-verify-c")?;
+    let c_path = dir.path().join("verify.c");
+    let bin_path = dir.path().join("verify");
+
+    {
+        let mut fp = io::BufWriter::new(File::create(&c_path)?);
+        render(
+            &code,
+            Consteval {
+                secs: args.opt_level.unwrap_or(1),
+                ..Consteval::default()
+            },
+            CCodegenOpts {
+                std: args.c_std.map_or_else(CStd::default, CStd::from),
+                eof,
+                ..CCodegenOpts::default()
+            },
+            &mut fp,
+        )?;
+        fp.flush()?;
+    }
+
+    let cc = args.cc.clone().unwrap_or_else(system_cc);
+
+    let mut command = Command::new(&cc);
+
+    if let Some(std) = args.c_std {
+        command.args([std.cc_flag(), "-pedantic-errors"]);
+    }
+
+    let status = command
+        .arg("-O1")
+        .arg(&c_path)
+        .arg("-o")
+        .arg(&bin_path)
+        .status()
+        .map_err(|e| PathIoError(cc.clone(), e))?;
+
+    if !status.success() {
+        return Err(format!("{cc} failed to compile the generated C ({status})").into());
+    }
+
+    let got = run_compiled(&bin_path, &input, args.timeout)?;
+
+    if got != expected {
+        let at = expected
+            .iter()
+            .zip(&got)
+            .position(|(a, b)| a != b)
+            .unwrap_or(expected.len().min(got.len()));
+
+        return Err(format!(
+            "C backend output differs from the interpreter at byte {at} (interpreter wrote {} bytes, compiled program wrote {} bytes)",
+            expected.len(),
+            got.len()
+        )
+        .into());
+    }
+
+    writeln!(
+        io::stderr(),
+        "verify-c: compiled output matches the interpreter ({} bytes)",
+        expected.len()
+    )?;
+
+    Ok(())
+}
+
+/// runs the compiled program at `path` on `input`, returning what it wrote if it exits successfully
+/// within `timeout` seconds
+fn run_compiled(
+    path: &Path,
+    input: &[u8],
+    timeout: u64,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut child = Command::new(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().expect("stdin was piped");
+    let mut stdout = child.stdout.take().expect("stdout was piped");
+
+    // write input and read output from other threads so a program that outputs before reading
+    // everything cant deadlock us, while this one enforces the timeout
+    let (status, got) = thread::scope(|s| {
+        s.spawn(move || {
+            // the program may exit before reading all input, which is not an error for verification
+            _ = stdin.write_all(input);
+        });
+
+        let reader = s.spawn(move || {
+            let mut got = vec![];
+            stdout.read_to_end(&mut got).map(|_| got)
+        });
+
+        let status = wait_timeout(child, Duration::from_secs(timeout));
+
+        (
+            status,
+            reader.join().expect("reading output does not panic"),
+        )
+    });
+
+    let Some(status) = status? else {
+        return Err(format!(
+            "compiled program did not finish within {timeout} seconds, while the interpreter did"
+        )
+        .into());
+    };
+    let got = got?;
+
+    if !status.success() {
+        return Err(format!("compiled program exited unsuccessfully ({status})").into());
+    }
+
+    Ok(got)
+}
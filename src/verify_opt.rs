@@ -0,0 +1,86 @@
+use std::{
+    io::{self, Read, Write},
+    mem,
+};
+
+use bfirs::compiler::{BfInstruc, BfInstructionStream, BfOptimizable};
+use bfirs::interpreter::{BfExecError, BrainFuckExecutorBuilder};
+use bfirs::token::Tokenizer;
+
+use crate::InterpreterArgs;
+
+/// Runs `code` on `input` with output captured, returning the output and how the run ended
+fn run_captured<CellSize: BfOptimizable>(
+    code: &BfInstructionStream<CellSize>,
+    input: &[u8],
+) -> (Vec<u8>, Result<(), BfExecError>) {
+    let mut execenv = BrainFuckExecutorBuilder::<CellSize, _, _>::new()
+        .array_len(code.reccomended_array_size())
+        .stream_in(input)
+        .stream_out(vec![])
+        .build()
+        .expect("this panic should not occur, minimum builder fields are present");
+
+    let result = execenv.run(code);
+
+    (execenv.stdout, result)
+}
+
+/// Runs the optimized and unoptimized instruction streams of `code` on the same input, failing if
+/// their output or the way they end differs, then writes the output of the optimized run.
+///
+/// All of stdin is read up front so both runs can be given identical input.
+pub fn verify_opt<CellSize: BfOptimizable>(
+    code: &[u8],
+    arr_len: Option<u32>,
+    args: &InterpreterArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let optimized =
+        BfInstructionStream::<CellSize>::optimized_from_text(code.iter().copied(), arr_len)?;
+    let plain = BfInstructionStream::<CellSize>::try_from_instructions(
+        Tokenizer::new(code.iter().copied())
+            .filter_map(|token| BfInstruc::from_token(&token))
+            .collect(),
+        arr_len,
+    )?;
+
+    let mut input = vec![];
+    args.stdin().read_to_end(&mut input)?;
+
+    let (expected, plain_result) = run_captured(&plain, &input);
+    let (got, result) = run_captured(&optimized, &input);
+
+    if got != expected {
+        let at = expected
+            .iter()
+            .zip(&got)
+            .position(|(a, b)| a != b)
+            .unwrap_or(expected.len().min(got.len()));
+
+        return Err(format!(
+            "optimized output differs from unoptimized output at byte {at} (unoptimized wrote {} bytes, optimized wrote {} bytes)",
+            expected.len(),
+            got.len()
+        )
+        .into());
+    }
+
+    let ended_alike = match (&plain_result, &result) {
+        (Ok(()), Ok(())) => true,
+        (Err(a), Err(b)) => mem::discriminant(&a.source) == mem::discriminant(&b.source),
+        _ => false,
+    };
+
+    if !ended_alike {
+        return Err(format!(
+            "optimized run ended with {result:?} but unoptimized run ended with {plain_result:?}"
+        )
+        .into());
+    }
+
+    let mut out = io::stdout().lock();
+    out.write_all(&got)?;
+    out.flush()?;
+
+    Ok(result?)
+}